@@ -0,0 +1,132 @@
+use gimli::{read, Reader};
+use serde_json::{json, Value};
+use std::collections::{BTreeMap, HashMap};
+use wdwarf::{AddressMap, Symbolizer};
+
+// The source location and the innermost (possibly inlined) function of
+// the address.
+#[derive(Debug, Default)]
+struct Location {
+    file: Option<String>,
+    line: Option<u64>,
+    function: Option<String>,
+}
+
+fn find_location<R: Reader<Offset = usize>>(
+    symbolizer: &Symbolizer<R>,
+    addr: u64,
+) -> read::Result<Location> {
+    // The innermost frame has the line of the address. Its function is
+    // compared too, since the outer frames differ when the code was inlined
+    // by the transform.
+    let frames = symbolizer.find_frames(addr)?;
+    let innermost = frames.first();
+    Ok(Location {
+        file: innermost.and_then(|frame| frame.file.clone()),
+        line: innermost.and_then(|frame| frame.line),
+        function: innermost.and_then(|frame| frame.function.clone()),
+    })
+}
+
+fn format_value<T: ToString>(value: &Option<T>) -> String {
+    value.as_ref().map_or("??".to_string(), |v| v.to_string())
+}
+
+pub struct Mismatch {
+    pub original: u64,
+    pub target: u64,
+    // The mismatched field: file, line or function.
+    pub field: &'static str,
+    pub expected: String,
+    pub actual: String,
+}
+
+impl Mismatch {
+    pub fn to_json(&self) -> Value {
+        json!({
+            "original": self.original,
+            "target": self.target,
+            "field": self.field,
+            "expected": self.expected,
+            "actual": self.actual,
+        })
+    }
+}
+
+fn compare_locations(
+    original: u64,
+    target: u64,
+    expected: &Location,
+    actual: &Location,
+) -> Vec<Mismatch> {
+    let fields = [
+        (
+            "file",
+            format_value(&expected.file),
+            format_value(&actual.file),
+        ),
+        (
+            "line",
+            format_value(&expected.line),
+            format_value(&actual.line),
+        ),
+        (
+            "function",
+            format_value(&expected.function),
+            format_value(&actual.function),
+        ),
+    ];
+    fields
+        .iter()
+        .filter(|(_, expected, actual)| expected != actual)
+        .map(|(field, expected, actual)| Mismatch {
+            original,
+            target,
+            field,
+            expected: expected.clone(),
+            actual: actual.clone(),
+        })
+        .collect()
+}
+
+// Compares the source line, file and function of every mapped target address:
+// the original addresses are resolved with the original DWARF, and the target
+// one with the translated DWARF. The code of several original addresses can
+// be merged into one target address, so it matches if any of them agrees.
+// Returns the number of the checked target addresses, and the mismatches of
+// the original address that differs the least.
+pub fn check_translation<R: Reader<Offset = usize>, T: Reader<Offset = usize>>(
+    original: &Symbolizer<R>,
+    translated: &Symbolizer<T>,
+    map: &AddressMap,
+) -> read::Result<(usize, Vec<Mismatch>)> {
+    let mut targets: BTreeMap<u64, Vec<u64>> = BTreeMap::new();
+    for (original_addr, target_addr) in map.pairs() {
+        targets
+            .entry(target_addr.0)
+            .or_default()
+            .push(original_addr.0);
+    }
+    let mut expected_locations: HashMap<u64, Location> = HashMap::new();
+    let mut mismatches = Vec::new();
+    for (target_addr, original_addrs) in targets.iter_mut() {
+        original_addrs.sort();
+        original_addrs.dedup();
+        let actual = find_location(translated, *target_addr)?;
+        let mut closest: Option<Vec<Mismatch>> = None;
+        for original_addr in original_addrs.iter() {
+            if !expected_locations.contains_key(original_addr) {
+                let location = find_location(original, *original_addr)?;
+                expected_locations.insert(*original_addr, location);
+            }
+            let expected = &expected_locations[original_addr];
+            let candidate = compare_locations(*original_addr, *target_addr, expected, &actual);
+            match &closest {
+                Some(closest) if closest.len() <= candidate.len() => (),
+                _ => closest = Some(candidate),
+            }
+        }
+        mismatches.extend(closest.unwrap_or_default());
+    }
+    Ok((targets.len(), mismatches))
+}
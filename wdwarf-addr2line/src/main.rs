@@ -0,0 +1,275 @@
+use docopt::Docopt;
+use serde::Deserialize;
+use serde_json::json;
+use std::collections::HashSet;
+use std::fs;
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::Path;
+use std::process;
+use wdwarf::{
+    demangle, read_code_section, read_dwarf, read_json_map_transform, AddressMap, Frame,
+    ReverseAddressMap, Symbolizer, TargetAddress,
+};
+
+mod check;
+
+const USAGE: &str = "
+Symbolize WebAssembly code addresses using the DWARF. The addresses are read
+from the standard input if none are specified.
+
+Usage:
+    wdwarf-addr2line <file> [-m <json> -w <wasm>] [options] [<address>...]
+    wdwarf-addr2line <file> -m <json> -w <wasm> --check [--json]
+    wdwarf-addr2line --help
+
+Options:
+    -h, --help              print this help message
+    -m, --source-map=JSON   Map the addresses to the original ones with the source
+                            map first, the file has the original DWARF
+    -w, --wasm-file=WASM    WebAssembly transformed file the addresses belong to
+    -c, --code-offsets      The addresses are relative to the code section
+                            instead of the module start
+    -a, --addresses         Print the address before the frames
+    --no-demangle           Do not demangle the function names
+    --json                  Print one JSON object per address or mismatch
+    --check                 Compare the source lines, files and innermost functions
+                            of the original file with the ones of the transformed
+                            file DWARF, for every target address of the source map
+";
+
+#[derive(Deserialize, Debug, Clone)]
+struct Args {
+    arg_file: String,
+    arg_address: Vec<String>,
+    flag_source_map: Option<String>,
+    flag_wasm_file: Option<String>,
+    flag_code_offsets: bool,
+    flag_addresses: bool,
+    flag_no_demangle: bool,
+    flag_json: bool,
+    flag_check: bool,
+}
+
+struct AddressOptions {
+    // The code section offset of the module the addresses belong to.
+    code_section_offset: Option<u64>,
+    map: Option<ReverseAddressMap>,
+}
+
+impl AddressOptions {
+    // Converts the address to the code section relative one of the DWARF.
+    fn to_dwarf_address(&self, addr: u64) -> Option<u64> {
+        let addr = match self.code_section_offset {
+            Some(offset) => addr.checked_sub(offset)?,
+            None => addr,
+        };
+        match &self.map {
+            Some(map) => map.find_original_address(TargetAddress(addr)).map(|a| a.0),
+            None => Some(addr),
+        }
+    }
+}
+
+fn parse_address(s: &str) -> Option<u64> {
+    if s.starts_with("0x") || s.starts_with("0X") {
+        u64::from_str_radix(&s[2..], 16).ok()
+    } else {
+        s.parse().ok()
+    }
+}
+
+fn function_name(frame: &Frame, no_demangle: bool) -> Option<String> {
+    let name = frame.function.as_ref()?;
+    if no_demangle {
+        return Some(name.clone());
+    }
+    Some(demangle(name).unwrap_or_else(|| name.clone()))
+}
+
+fn write_frames<W: Write>(
+    out: &mut W,
+    address: &str,
+    frames: &[Frame],
+    args: &Args,
+) -> io::Result<()> {
+    if args.flag_json {
+        let frames = frames
+            .iter()
+            .enumerate()
+            .map(|(i, frame)| {
+                json!({
+                    "function": function_name(frame, args.flag_no_demangle),
+                    "file": frame.file,
+                    "line": frame.line,
+                    "column": frame.column,
+                    "inlined": i + 1 < frames.len(),
+                })
+            })
+            .collect::<Vec<_>>();
+        let result = json!({ "address": address, "frames": frames });
+        return writeln!(out, "{}", result);
+    }
+    if args.flag_addresses {
+        writeln!(out, "{}", address)?;
+    }
+    if frames.is_empty() {
+        writeln!(out, "??")?;
+        writeln!(out, "??:0")?;
+    }
+    for frame in frames {
+        let name = function_name(frame, args.flag_no_demangle);
+        writeln!(out, "{}", name.as_ref().map_or("??", |s| s.as_str()))?;
+        write!(
+            out,
+            "{}:{}",
+            frame.file.as_ref().map_or("??", |s| s.as_str()),
+            frame.line.unwrap_or(0)
+        )?;
+        if let Some(column) = frame.column {
+            write!(out, ":{}", column)?;
+        }
+        writeln!(out)?;
+    }
+    Ok(())
+}
+
+fn read_code_section_offset(bin: &[u8]) -> u64 {
+    read_code_section(bin)
+        .expect("wasm")
+        .expect("code section")
+        .offset
+}
+
+fn read_map(source_map_file: &str, input: &[u8]) -> AddressMap {
+    let code_section_offset = read_code_section_offset(input);
+    let file = fs::File::open(source_map_file).expect("json file");
+    read_json_map_transform(BufReader::new(file), code_section_offset).expect("json")
+}
+
+// Checks the DWARF of the transformed file, e.g. produced by wdwarf-cp,
+// against the original DWARF.
+fn check_command<R: gimli::Reader<Offset = usize>>(args: &Args, symbolizer: &Symbolizer<R>) {
+    let wasm_file = args.flag_wasm_file.as_ref().expect("wasm file");
+    let wasm_path = Path::new(wasm_file);
+    let input = fs::read(wasm_path).expect("file data");
+    let map = read_map(args.flag_source_map.as_ref().expect("source map"), &input);
+    let mut external = Vec::new();
+    let dwarf = read_dwarf(
+        &input,
+        wasm_path.parent().unwrap_or_else(|| Path::new("")),
+        &mut external,
+    )
+    .expect("dwarf");
+    let translated = Symbolizer::new(&dwarf).expect("symbolizer");
+    let (checked, mismatches) =
+        check::check_translation(symbolizer, &translated, &map).expect("check");
+
+    let stdout = io::stdout();
+    let mut out = stdout.lock();
+    for mismatch in mismatches.iter() {
+        if args.flag_json {
+            writeln!(out, "{}", mismatch.to_json())
+        } else {
+            writeln!(
+                out,
+                "0x{:x} -> 0x{:x}: {}: {} != {}",
+                mismatch.original,
+                mismatch.target,
+                mismatch.field,
+                mismatch.expected,
+                mismatch.actual
+            )
+        }
+        .expect("write");
+    }
+    if !mismatches.is_empty() {
+        let mismatched = mismatches
+            .iter()
+            .map(|mismatch| mismatch.target)
+            .collect::<HashSet<_>>();
+        eprintln!(
+            "{} of {} target addresses mismatch",
+            mismatched.len(),
+            checked
+        );
+        process::exit(1);
+    }
+}
+
+fn main() {
+    let args: Args = Docopt::new(USAGE)
+        .and_then(|d| d.help(true).deserialize())
+        .unwrap_or_else(|e| e.exit());
+
+    let path = Path::new(&args.arg_file);
+    let bin = fs::read(path).expect("file data");
+    let mut external = Vec::new();
+    let dwarf = read_dwarf(
+        &bin,
+        path.parent().unwrap_or_else(|| Path::new("")),
+        &mut external,
+    )
+    .expect("dwarf");
+    let symbolizer = Symbolizer::new(&dwarf).expect("symbolizer");
+    if args.flag_check {
+        check_command(&args, &symbolizer);
+        return;
+    }
+
+    // The addresses belong to the transformed file if the source map is used.
+    let options = match &args.flag_source_map {
+        Some(source_map_file) => {
+            let wasm_file = args.flag_wasm_file.as_ref().expect("wasm file");
+            let input = fs::read(wasm_file).expect("file data");
+            let code_section_offset = read_code_section_offset(&input);
+            let map = read_map(source_map_file, &input);
+            AddressOptions {
+                code_section_offset: if args.flag_code_offsets {
+                    None
+                } else {
+                    Some(code_section_offset)
+                },
+                map: Some(ReverseAddressMap::new(&map)),
+            }
+        }
+        None => AddressOptions {
+            code_section_offset: if args.flag_code_offsets {
+                None
+            } else {
+                Some(read_code_section_offset(&bin))
+            },
+            map: None,
+        },
+    };
+
+    let stdout = io::stdout();
+    let mut out = stdout.lock();
+    let mut symbolize = |address: &str| {
+        let address = address.trim();
+        let frames = match parse_address(address) {
+            Some(addr) => match options.to_dwarf_address(addr) {
+                Some(addr) => symbolizer.find_frames(addr).expect("frames"),
+                None => vec![],
+            },
+            None => {
+                eprintln!("{}: invalid address", address);
+                vec![]
+            }
+        };
+        write_frames(&mut out, address, &frames, &args).expect("write");
+        out.flush().expect("flush");
+    };
+    if args.arg_address.is_empty() {
+        let stdin = io::stdin();
+        for line in stdin.lock().lines() {
+            let line = line.expect("line");
+            if !line.trim().is_empty() {
+                symbolize(&line);
+            }
+        }
+    } else {
+        for address in args.arg_address.iter() {
+            symbolize(address);
+        }
+    }
+}
@@ -0,0 +1,183 @@
+use gimli::Reader;
+use std::collections::HashMap;
+use std::io::{self, Write};
+use wdwarf::{demangle, LineRange, Symbolizer};
+
+// The Breakpad text symbol file, see
+// https://chromium.googlesource.com/breakpad/breakpad/+/master/docs/symbol_files.md
+// The addresses are relative to the code section, and the module build ID is
+// used as the debug ID.
+
+// The debug ID is the build ID formatted as the GUID (the first three fields
+// are little-endian), followed by the age (always 0), as it is done for ELF.
+pub fn format_debug_id(build_id: &[u8]) -> String {
+    let mut guid = [0u8; 16];
+    for (i, b) in build_id.iter().take(16).enumerate() {
+        guid[i] = *b;
+    }
+    let mut result = format!(
+        "{:08X}{:04X}{:04X}",
+        u32::from_le_bytes([guid[0], guid[1], guid[2], guid[3]]),
+        u16::from_le_bytes([guid[4], guid[5]]),
+        u16::from_le_bytes([guid[6], guid[7]])
+    );
+    for b in guid[8..].iter() {
+        result.push_str(&format!("{:02X}", b));
+    }
+    result.push('0');
+    result
+}
+
+// The module identifier if there is no build ID: the first page of the code
+// section XORed in 16 bytes blocks, as dump_syms does for ELF files.
+pub fn code_identifier(code: &[u8]) -> Vec<u8> {
+    let mut result = vec![0u8; 16];
+    for (i, b) in code.iter().take(4096).enumerate() {
+        result[i % 16] ^= *b;
+    }
+    result
+}
+
+// Assigns the numbers to the files and the inline origins names.
+struct Table {
+    items: Vec<String>,
+    index: HashMap<String, usize>,
+}
+
+impl Table {
+    fn new() -> Self {
+        Table {
+            items: Vec::new(),
+            index: HashMap::new(),
+        }
+    }
+
+    fn get(&mut self, item: &str) -> usize {
+        if let Some(i) = self.index.get(item) {
+            return *i;
+        }
+        let i = self.items.len();
+        self.items.push(item.to_string());
+        self.index.insert(item.to_string(), i);
+        i
+    }
+}
+
+fn file_number(files: &mut Table, file: &Option<String>) -> usize {
+    files.get(file.as_ref().map_or("??", |file| file.as_str()))
+}
+
+fn function_name(name: &Option<String>) -> String {
+    match name {
+        Some(name) => demangle(name).unwrap_or_else(|| name.clone()),
+        None => "<name omitted>".to_string(),
+    }
+}
+
+// Returns the line records of the function range: the line ranges are
+// clipped, and the adjacent ones with the same line are merged.
+fn function_lines(lines: &[LineRange], begin: u64, end: u64) -> Vec<(u64, u64, &LineRange)> {
+    let start = match lines.binary_search_by(|range| range.end.cmp(&begin)) {
+        Ok(i) => i + 1,
+        Err(i) => i,
+    };
+    let mut result: Vec<(u64, u64, &LineRange)> = Vec::new();
+    for range in lines[start..].iter() {
+        if range.begin >= end {
+            break;
+        }
+        if range.end <= begin || range.line == 0 {
+            continue;
+        }
+        let (range_begin, range_end) = (range.begin.max(begin), range.end.min(end));
+        if let Some(last) = result.last_mut() {
+            if last.1 == range_begin && last.2.line == range.line && last.2.file == range.file {
+                last.1 = range_end;
+                continue;
+            }
+        }
+        result.push((range_begin, range_end, range));
+    }
+    result
+}
+
+pub fn write_symbols<R: Reader<Offset = usize>, W: Write>(
+    out: &mut W,
+    symbolizer: &Symbolizer<R>,
+    module_name: &str,
+    build_id: &[u8],
+) -> io::Result<()> {
+    let functions = symbolizer.functions().expect("functions");
+    let lines = symbolizer.line_ranges().expect("line ranges");
+
+    let mut ranges = functions
+        .iter()
+        .flat_map(|f| f.ranges.iter().map(move |range| (*range, f)))
+        .collect::<Vec<_>>();
+    ranges.sort_by_key(|(range, _)| *range);
+    ranges.dedup_by_key(|(range, _)| *range);
+
+    let mut files = Table::new();
+    let mut origins = Table::new();
+    let mut records = Vec::new();
+    for ((begin, end), function) in ranges {
+        records.push(format!(
+            "FUNC {:x} {:x} 0 {}",
+            begin,
+            end - begin,
+            function_name(&function.name)
+        ));
+        for call in function.inlined.iter() {
+            let call_ranges = call
+                .ranges
+                .iter()
+                .filter(|(b, e)| *b >= begin && *e <= end)
+                .map(|(b, e)| format!(" {:x} {:x}", b, e - b))
+                .collect::<String>();
+            if call_ranges.is_empty() {
+                continue;
+            }
+            let call_file = file_number(&mut files, &call.call_file);
+            records.push(format!(
+                "INLINE {} {} {} {}{}",
+                call.depth,
+                call.call_line.unwrap_or(0),
+                call_file,
+                origins.get(&function_name(&call.function)),
+                call_ranges
+            ));
+        }
+        for (line_begin, line_end, range) in function_lines(&lines, begin, end) {
+            let file = file_number(&mut files, &range.file);
+            records.push(format!(
+                "{:x} {:x} {} {}",
+                line_begin,
+                line_end - line_begin,
+                range.line,
+                file
+            ));
+        }
+    }
+
+    writeln!(
+        out,
+        "MODULE wasm wasm32 {} {}",
+        format_debug_id(build_id),
+        module_name
+    )?;
+    let code_id = build_id
+        .iter()
+        .map(|b| format!("{:02X}", b))
+        .collect::<String>();
+    writeln!(out, "INFO CODE_ID {}", code_id)?;
+    for (i, file) in files.items.iter().enumerate() {
+        writeln!(out, "FILE {} {}", i, file)?;
+    }
+    for (i, origin) in origins.items.iter().enumerate() {
+        writeln!(out, "INLINE_ORIGIN {} {}", i, origin)?;
+    }
+    for record in records {
+        writeln!(out, "{}", record)?;
+    }
+    Ok(())
+}
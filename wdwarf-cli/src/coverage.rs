@@ -0,0 +1,290 @@
+use gimli::Reader;
+use std::collections::BTreeMap;
+use std::io::{self, BufRead, Write};
+use std::path::Path;
+use wdwarf::{demangle, LineRange, Symbolizer};
+
+pub struct FunctionCoverage {
+    pub name: String,
+    // The line of the function entry.
+    pub line: u64,
+    pub hits: u64,
+}
+
+#[derive(Default)]
+pub struct FileCoverage {
+    pub lines: BTreeMap<u64, u64>,
+    pub functions: Vec<FunctionCoverage>,
+}
+
+impl FileCoverage {
+    fn lines_hit(&self) -> usize {
+        self.lines.values().filter(|hits| **hits > 0).count()
+    }
+
+    fn functions_hit(&self) -> usize {
+        self.functions.iter().filter(|f| f.hits > 0).count()
+    }
+}
+
+// Reads the executed code offsets: the "<offset> [<count>]" lines, the count
+// is 1 if omitted. The empty lines and the lines starting with '#' are skipped.
+pub fn read_counts<B: BufRead>(input: B) -> io::Result<Vec<(u64, u64)>> {
+    let mut result = Vec::new();
+    for line in input.lines() {
+        let line = line?;
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let mut parts = line.split_whitespace();
+        let offset = parts.next().and_then(parse_number);
+        let count = match parts.next() {
+            Some(count) => parse_number(count),
+            None => Some(1),
+        };
+        match (offset, count) {
+            (Some(offset), Some(count)) => result.push((offset, count)),
+            _ => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("invalid counts line: {}", line),
+                ))
+            }
+        }
+    }
+    Ok(result)
+}
+
+fn parse_number(s: &str) -> Option<u64> {
+    match s.strip_prefix("0x") {
+        Some(hex) => u64::from_str_radix(hex, 16).ok(),
+        None => s.parse().ok(),
+    }
+}
+
+pub fn find_line_range(lines: &[LineRange], addr: u64) -> Option<&LineRange> {
+    let i = lines.partition_point(|range| range.begin <= addr);
+    if i == 0 {
+        return None;
+    }
+    let range = &lines[i - 1];
+    if addr < range.end {
+        Some(range)
+    } else {
+        None
+    }
+}
+
+// Collects the line and the function hits of the code section relative
+// offsets. All the lines of the line programs are reported, and the hits of
+// the line (or the function) are the largest count of its offsets.
+pub fn collect_coverage<R: Reader<Offset = usize>>(
+    symbolizer: &Symbolizer<R>,
+    counts: &[(u64, u64)],
+) -> BTreeMap<String, FileCoverage> {
+    let lines = symbolizer.line_ranges().expect("line ranges");
+    let functions = symbolizer.functions().expect("functions");
+    let mut coverage: BTreeMap<String, FileCoverage> = BTreeMap::new();
+    for range in lines.iter() {
+        if let (Some(file), true) = (&range.file, range.line != 0) {
+            let file = coverage.entry(file.clone()).or_default();
+            file.lines.entry(range.line).or_insert(0);
+        }
+    }
+    for (addr, count) in counts.iter() {
+        let range = match find_line_range(&lines, *addr) {
+            Some(range) => range,
+            None => continue,
+        };
+        if let (Some(file), true) = (&range.file, range.line != 0) {
+            let hits = coverage
+                .get_mut(file)
+                .unwrap()
+                .lines
+                .get_mut(&range.line)
+                .unwrap();
+            *hits = (*hits).max(*count);
+        }
+    }
+
+    let mut ranges = functions
+        .iter()
+        .filter_map(|f| f.ranges.first().map(|range| (*range, f)))
+        .collect::<Vec<_>>();
+    ranges.sort_by_key(|(range, _)| *range);
+    ranges.dedup_by_key(|(range, _)| *range);
+    let mut sorted_counts = counts.to_vec();
+    sorted_counts.sort_unstable_by_key(|(addr, _)| *addr);
+    for ((begin, _), function) in ranges {
+        let entry = match find_line_range(&lines, begin) {
+            Some(LineRange {
+                file: Some(file),
+                line,
+                ..
+            }) => (file, *line),
+            _ => continue,
+        };
+        let hits = function
+            .ranges
+            .iter()
+            .flat_map(|(b, e)| {
+                let i = sorted_counts.partition_point(|(addr, _)| addr < b);
+                sorted_counts[i..]
+                    .iter()
+                    .take_while(move |(addr, _)| addr < e)
+            })
+            .map(|(_, count)| *count)
+            .max()
+            .unwrap_or(0);
+        let name = match &function.name {
+            Some(name) => demangle(name).unwrap_or_else(|| name.clone()),
+            None => format!("<function at 0x{:x}>", begin),
+        };
+        coverage
+            .entry(entry.0.clone())
+            .or_default()
+            .functions
+            .push(FunctionCoverage {
+                name,
+                line: entry.1,
+                hits,
+            });
+    }
+    coverage
+}
+
+pub fn write_lcov<W: Write>(
+    out: &mut W,
+    coverage: &BTreeMap<String, FileCoverage>,
+) -> io::Result<()> {
+    writeln!(out, "TN:")?;
+    for (file, file_coverage) in coverage.iter() {
+        writeln!(out, "SF:{}", file)?;
+        for function in file_coverage.functions.iter() {
+            writeln!(out, "FN:{},{}", function.line, function.name)?;
+        }
+        for function in file_coverage.functions.iter() {
+            writeln!(out, "FNDA:{},{}", function.hits, function.name)?;
+        }
+        writeln!(out, "FNF:{}", file_coverage.functions.len())?;
+        writeln!(out, "FNH:{}", file_coverage.functions_hit())?;
+        for (line, hits) in file_coverage.lines.iter() {
+            writeln!(out, "DA:{},{}", line, hits)?;
+        }
+        writeln!(out, "LF:{}", file_coverage.lines.len())?;
+        writeln!(out, "LH:{}", file_coverage.lines_hit())?;
+        writeln!(out, "end_of_record")?;
+    }
+    Ok(())
+}
+
+fn escape_xml(s: &str) -> String {
+    let mut result = String::with_capacity(s.len());
+    for ch in s.chars() {
+        match ch {
+            '<' => result.push_str("&lt;"),
+            '>' => result.push_str("&gt;"),
+            '&' => result.push_str("&amp;"),
+            '"' => result.push_str("&quot;"),
+            '\'' => result.push_str("&apos;"),
+            _ => result.push(ch),
+        }
+    }
+    result
+}
+
+fn rate(hit: usize, total: usize) -> String {
+    if total == 0 {
+        return "1".to_string();
+    }
+    format!("{:.4}", hit as f64 / total as f64)
+}
+
+// The Cobertura XML report: the files are the classes, grouped into the
+// packages by their directory. The timestamp is 0 to keep the reports of
+// the same counts identical.
+pub fn write_cobertura<W: Write>(
+    out: &mut W,
+    coverage: &BTreeMap<String, FileCoverage>,
+) -> io::Result<()> {
+    let mut packages: BTreeMap<String, Vec<(&String, &FileCoverage)>> = BTreeMap::new();
+    for (file, file_coverage) in coverage.iter() {
+        let dir = Path::new(file)
+            .parent()
+            .map_or(String::new(), |dir| dir.to_string_lossy().into_owned());
+        packages.entry(dir).or_default().push((file, file_coverage));
+    }
+    let lines_valid = coverage.values().map(|f| f.lines.len()).sum::<usize>();
+    let lines_covered = coverage.values().map(|f| f.lines_hit()).sum::<usize>();
+
+    writeln!(out, "<?xml version=\"1.0\" ?>")?;
+    writeln!(
+        out,
+        "<!DOCTYPE coverage SYSTEM \"http://cobertura.sourceforge.net/xml/coverage-04.dtd\">"
+    )?;
+    writeln!(
+        out,
+        "<coverage line-rate=\"{}\" branch-rate=\"0\" lines-covered=\"{}\" lines-valid=\"{}\" \
+         branches-covered=\"0\" branches-valid=\"0\" complexity=\"0\" version=\"0.1\" timestamp=\"0\">",
+        rate(lines_covered, lines_valid),
+        lines_covered,
+        lines_valid
+    )?;
+    writeln!(out, "  <sources><source>.</source></sources>")?;
+    writeln!(out, "  <packages>")?;
+    for (dir, files) in packages.iter() {
+        let valid = files.iter().map(|(_, f)| f.lines.len()).sum::<usize>();
+        let covered = files.iter().map(|(_, f)| f.lines_hit()).sum::<usize>();
+        writeln!(
+            out,
+            "    <package name=\"{}\" line-rate=\"{}\" branch-rate=\"0\" complexity=\"0\">",
+            escape_xml(dir),
+            rate(covered, valid)
+        )?;
+        writeln!(out, "      <classes>")?;
+        for (file, file_coverage) in files.iter() {
+            let name = Path::new(file)
+                .file_name()
+                .map_or(String::new(), |name| name.to_string_lossy().into_owned());
+            writeln!(
+                out,
+                "        <class name=\"{}\" filename=\"{}\" line-rate=\"{}\" branch-rate=\"0\" complexity=\"0\">",
+                escape_xml(&name),
+                escape_xml(file),
+                rate(file_coverage.lines_hit(), file_coverage.lines.len())
+            )?;
+            writeln!(out, "          <methods>")?;
+            for function in file_coverage.functions.iter() {
+                writeln!(
+                    out,
+                    "            <method name=\"{}\" signature=\"\" line-rate=\"{}\" branch-rate=\"0\" complexity=\"0\">",
+                    escape_xml(&function.name),
+                    rate((function.hits > 0) as usize, 1)
+                )?;
+                writeln!(
+                    out,
+                    "              <lines><line number=\"{}\" hits=\"{}\"/></lines>",
+                    function.line, function.hits
+                )?;
+                writeln!(out, "            </method>")?;
+            }
+            writeln!(out, "          </methods>")?;
+            writeln!(out, "          <lines>")?;
+            for (line, hits) in file_coverage.lines.iter() {
+                writeln!(
+                    out,
+                    "            <line number=\"{}\" hits=\"{}\"/>",
+                    line, hits
+                )?;
+            }
+            writeln!(out, "          </lines>")?;
+            writeln!(out, "        </class>")?;
+        }
+        writeln!(out, "      </classes>")?;
+        writeln!(out, "    </package>")?;
+    }
+    writeln!(out, "  </packages>")?;
+    writeln!(out, "</coverage>")?;
+    Ok(())
+}
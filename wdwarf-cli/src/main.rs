@@ -0,0 +1,386 @@
+use docopt::Docopt;
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::{self, BufReader, BufWriter, Write};
+use std::path::Path;
+use std::process;
+use wdwarf::{
+    load_dwarf, read_build_id, read_code_section, read_custom_section, read_dwarf_sections,
+    read_external_debug_info, verify_dwarf, Diagnostics, Severity, Symbolizer,
+};
+
+mod breakpad;
+mod coverage;
+mod size;
+mod store;
+mod symbolicate;
+
+use symbolicate::Symbolicator;
+
+const USAGE: &str = "
+WebAssembly DWARF tools.
+
+Usage:
+    wdwarf store add <file>... -d <dir>
+    wdwarf store find <build-id-or-file> -d <dir> [--executable]
+    wdwarf symbolicate <wasm> [<trace>] [--debug-file=<file>] [-d <dir>] [--no-demangle]
+    wdwarf breakpad <wasm> [-o <output>] [--debug-file=<file>] [-d <dir>]
+    wdwarf coverage <wasm> <counts> [options]
+    wdwarf size <wasm> [options]
+    wdwarf verify <wasm> [--debug-file=<file>] [-d <dir>]
+    wdwarf --help
+
+Options:
+    -h, --help              print this help message
+    -d, --dir=<dir>         Symbol store directory
+    --executable            Find the module with code instead of the debug file
+    --debug-file=<file>     Read the DWARF from the separate debug file
+    --no-demangle           Do not demangle the function names
+    -o, --output=<output>   Output file, the standard output is used by default
+    -c, --code-offsets      The coverage offsets are relative to the code
+                            section instead of the module start
+    --format=<format>       Coverage report format: lcov or cobertura
+                            [default: lcov]
+    --by=<key>              Size report grouping: file, line, function or unit
+                            [default: file]
+    --sort=<order>          Size report order: size or name [default: size]
+    --json                  Print the size report as the JSON tree
+    --diff=<base>           Compare the sizes with the base build module
+
+The verify command checks that the DWARF is consistent with the module code,
+e.g. the subprograms are inside the function bodies, and prints the problems.
+It fails only if errors are found, the warnings are just reported.
+
+The coverage counts file lists the executed code offsets and their optional hit
+counts, one offset per line.
+";
+
+#[derive(Deserialize, Debug, Clone)]
+struct Args {
+    cmd_store: bool,
+    cmd_add: bool,
+    cmd_find: bool,
+    cmd_symbolicate: bool,
+    cmd_breakpad: bool,
+    cmd_coverage: bool,
+    cmd_size: bool,
+    cmd_verify: bool,
+    arg_file: Vec<String>,
+    arg_build_id_or_file: String,
+    arg_wasm: String,
+    arg_trace: Option<String>,
+    arg_counts: String,
+    flag_dir: Option<String>,
+    flag_executable: bool,
+    flag_debug_file: Option<String>,
+    flag_no_demangle: bool,
+    flag_output: Option<String>,
+    flag_code_offsets: bool,
+    flag_format: String,
+    flag_by: String,
+    flag_sort: String,
+    flag_json: bool,
+    flag_diff: Option<String>,
+}
+
+fn store_add_command(args: &Args) {
+    let store = Path::new(args.flag_dir.as_ref().expect("store directory"));
+    for file in args.arg_file.iter() {
+        match store::add_file(store, Path::new(file)) {
+            Ok(paths) => {
+                for path in paths {
+                    println!("{} -> {}", file, path.display());
+                }
+            }
+            Err(err) => {
+                eprintln!("{}: {}", file, err);
+                process::exit(1);
+            }
+        }
+    }
+}
+
+fn store_find_command(args: &Args) {
+    let store = Path::new(args.flag_dir.as_ref().expect("store directory"));
+    // The build ID is read from the file, if such file exists.
+    let query = &args.arg_build_id_or_file;
+    let build_id = if Path::new(query).is_file() {
+        let bin = fs::read(query).expect("file data");
+        read_build_id(&bin)
+            .expect("wasm")
+            .expect("build_id section")
+    } else {
+        store::parse_build_id(query).expect("hex build ID")
+    };
+    let kind = if args.flag_executable {
+        store::EXECUTABLE
+    } else {
+        store::DEBUGINFO
+    };
+    match store::find_file(store, &build_id, kind) {
+        Some(path) => println!("{}", path.display()),
+        None => {
+            eprintln!("{}: not found", store::format_build_id(&build_id));
+            process::exit(1);
+        }
+    }
+}
+
+// Reads the debug file data, if the module DWARF is not embedded: from
+// the file referenced by the external_debug_info section, or from the symbol
+// store by the build ID.
+fn read_debug_data(bin: &[u8], path: &Path, args: &Args) -> Option<Vec<u8>> {
+    if let Some(debug_file) = &args.flag_debug_file {
+        return Some(fs::read(debug_file).expect("debug file"));
+    }
+    if read_custom_section(bin, ".debug_info")
+        .expect("wasm")
+        .is_some()
+    {
+        return None;
+    }
+    if let Some(url) = read_external_debug_info(bin).expect("wasm") {
+        let dir = path.parent().unwrap_or_else(|| Path::new(""));
+        let debug_file = dir.join(url.trim_start_matches("file://"));
+        if debug_file.is_file() {
+            return Some(fs::read(debug_file).expect("debug file"));
+        }
+    }
+    if let (Some(dir), Some(build_id)) = (&args.flag_dir, read_build_id(bin).expect("wasm")) {
+        if let Some(debug_file) = store::find_file(Path::new(dir), &build_id, store::DEBUGINFO) {
+            return Some(fs::read(debug_file).expect("debug file"));
+        }
+    }
+    None
+}
+
+fn symbolicate_command(args: &Args) {
+    let path = Path::new(&args.arg_wasm);
+    let bin = fs::read(path).expect("file data");
+    let code = read_code_section(&bin)
+        .expect("wasm")
+        .expect("code section");
+    let debug_data = read_debug_data(&bin, path, args);
+    let sections = read_dwarf_sections(debug_data.as_ref().unwrap_or(&bin)).expect("wasm");
+    let dwarf = load_dwarf(sections).expect("dwarf");
+    let symbolicator = Symbolicator {
+        symbolizer: Symbolizer::new(&dwarf).expect("symbolizer"),
+        code: &code,
+        demangle: !args.flag_no_demangle,
+    };
+
+    let stdout = io::stdout();
+    let mut out = stdout.lock();
+    match &args.arg_trace {
+        Some(trace) => {
+            let file = fs::File::open(trace).expect("trace file");
+            symbolicator.symbolicate(BufReader::new(file), &mut out)
+        }
+        None => {
+            let stdin = io::stdin();
+            symbolicator.symbolicate(stdin.lock(), &mut out)
+        }
+    }
+    .expect("symbolicate");
+}
+
+fn breakpad_command(args: &Args) {
+    let path = Path::new(&args.arg_wasm);
+    let bin = fs::read(path).expect("file data");
+    let build_id = match read_build_id(&bin).expect("wasm") {
+        Some(build_id) => build_id,
+        None => {
+            let code = match read_code_section(&bin).expect("wasm") {
+                Some(code) => code,
+                None => {
+                    eprintln!("{}: build_id section was not found", args.arg_wasm);
+                    process::exit(1);
+                }
+            };
+            eprintln!(
+                "warning: {}: build_id section was not found, the code identifier is used",
+                args.arg_wasm
+            );
+            let start = code.offset as usize;
+            breakpad::code_identifier(&bin[start..start + code.size as usize])
+        }
+    };
+    let debug_data = read_debug_data(&bin, path, args);
+    let sections = read_dwarf_sections(debug_data.as_ref().unwrap_or(&bin)).expect("wasm");
+    let dwarf = load_dwarf(sections).expect("dwarf");
+    let symbolizer = Symbolizer::new(&dwarf).expect("symbolizer");
+    let module_name = path.file_name().unwrap().to_string_lossy();
+
+    match &args.flag_output {
+        Some(output) => {
+            let file = fs::File::create(output).expect("output file");
+            breakpad::write_symbols(
+                &mut BufWriter::new(file),
+                &symbolizer,
+                &module_name,
+                &build_id,
+            )
+        }
+        None => {
+            let stdout = io::stdout();
+            let mut out = BufWriter::new(stdout.lock());
+            breakpad::write_symbols(&mut out, &symbolizer, &module_name, &build_id)
+        }
+    }
+    .expect("write symbols");
+}
+
+fn coverage_command(args: &Args) {
+    let path = Path::new(&args.arg_wasm);
+    let bin = fs::read(path).expect("file data");
+    let file = fs::File::open(&args.arg_counts).expect("counts file");
+    let mut counts = coverage::read_counts(BufReader::new(file)).expect("counts");
+    if !args.flag_code_offsets {
+        let code = read_code_section(&bin)
+            .expect("wasm")
+            .expect("code section");
+        counts = counts
+            .into_iter()
+            .filter_map(|(offset, count)| Some((offset.checked_sub(code.offset)?, count)))
+            .collect();
+    }
+    if args.flag_format != "lcov" && args.flag_format != "cobertura" {
+        eprintln!("{}: unknown coverage format", args.flag_format);
+        process::exit(1);
+    }
+    let debug_data = read_debug_data(&bin, path, args);
+    let sections = read_dwarf_sections(debug_data.as_ref().unwrap_or(&bin)).expect("wasm");
+    let dwarf = load_dwarf(sections).expect("dwarf");
+    let symbolizer = Symbolizer::new(&dwarf).expect("symbolizer");
+    let report = coverage::collect_coverage(&symbolizer, &counts);
+
+    let mut out: Box<dyn Write> = match &args.flag_output {
+        Some(output) => Box::new(BufWriter::new(
+            fs::File::create(output).expect("output file"),
+        )),
+        None => Box::new(BufWriter::new(io::stdout())),
+    };
+    if args.flag_format == "cobertura" {
+        coverage::write_cobertura(&mut out, &report)
+    } else {
+        coverage::write_lcov(&mut out, &report)
+    }
+    .expect("write coverage");
+}
+
+// Reads the module code sizes attribution.
+fn read_code_sizes(path: &Path, args: &Args) -> BTreeMap<size::Owner, u64> {
+    let bin = fs::read(path).expect("file data");
+    let code = read_code_section(&bin)
+        .expect("wasm")
+        .expect("code section");
+    let debug_data = read_debug_data(&bin, path, args);
+    let sections = read_dwarf_sections(debug_data.as_ref().unwrap_or(&bin)).expect("wasm");
+    let dwarf = load_dwarf(sections).expect("dwarf");
+    let symbolizer = Symbolizer::new(&dwarf).expect("symbolizer");
+    match size::attribute_code(&symbolizer, code.size) {
+        Ok(sizes) => sizes,
+        Err(err) => {
+            eprintln!("{}: {}", path.display(), err);
+            process::exit(1);
+        }
+    }
+}
+
+fn size_command(args: &Args) {
+    let by = match size::GroupBy::parse(&args.flag_by) {
+        Some(by) => by,
+        None => {
+            eprintln!("{}: unknown size grouping", args.flag_by);
+            process::exit(1);
+        }
+    };
+    let sort_by_name = match args.flag_sort.as_str() {
+        "size" => false,
+        "name" => true,
+        order => {
+            eprintln!("{}: unknown size order", order);
+            process::exit(1);
+        }
+    };
+    let path = Path::new(&args.arg_wasm);
+    let sizes = read_code_sizes(path, args);
+
+    let stdout = io::stdout();
+    let mut out = BufWriter::new(stdout.lock());
+    match &args.flag_diff {
+        Some(base) => {
+            // The debug file option is meant for the new build only.
+            let base_args = Args {
+                flag_debug_file: None,
+                ..args.clone()
+            };
+            let base_sizes = read_code_sizes(Path::new(base), &base_args);
+            size::write_diff(
+                &mut out,
+                &base_sizes,
+                &sizes,
+                by,
+                sort_by_name,
+                args.flag_json,
+            )
+        }
+        None if args.flag_json => {
+            let module_name = path.file_name().unwrap().to_string_lossy();
+            size::write_json_tree(&mut out, &sizes, &module_name)
+        }
+        None => size::write_table(&mut out, &sizes, by, sort_by_name),
+    }
+    .expect("write size report");
+}
+
+fn verify_command(args: &Args) {
+    let path = Path::new(&args.arg_wasm);
+    let bin = fs::read(path).expect("file data");
+    let code = read_code_section(&bin)
+        .expect("wasm")
+        .expect("code section");
+    let debug_data = read_debug_data(&bin, path, args);
+    let sections = read_dwarf_sections(debug_data.as_ref().unwrap_or(&bin)).expect("wasm");
+    let dwarf = load_dwarf(sections).expect("dwarf");
+    let mut diagnostics = Diagnostics::default();
+    verify_dwarf(&dwarf, &code.function_ranges, &mut diagnostics).expect("verify");
+    for diagnostic in diagnostics.items.iter() {
+        println!("{}", diagnostic);
+    }
+    // Only the errors fail the verification.
+    let errors = diagnostics.count(Severity::Error);
+    let warnings = diagnostics.count(Severity::Warning);
+    if errors > 0 || warnings > 0 {
+        eprintln!(
+            "{}: {} errors and {} warnings found",
+            args.arg_wasm, errors, warnings
+        );
+    }
+    if errors > 0 {
+        process::exit(1);
+    }
+}
+
+fn main() {
+    let args: Args = Docopt::new(USAGE)
+        .and_then(|d| d.help(true).deserialize())
+        .unwrap_or_else(|e| e.exit());
+
+    if args.cmd_store && args.cmd_add {
+        store_add_command(&args);
+    } else if args.cmd_store && args.cmd_find {
+        store_find_command(&args);
+    } else if args.cmd_symbolicate {
+        symbolicate_command(&args);
+    } else if args.cmd_breakpad {
+        breakpad_command(&args);
+    } else if args.cmd_coverage {
+        coverage_command(&args);
+    } else if args.cmd_size {
+        size_command(&args);
+    } else if args.cmd_verify {
+        verify_command(&args);
+    }
+}
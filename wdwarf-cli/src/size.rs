@@ -0,0 +1,302 @@
+use gimli::{read, Reader};
+use serde_json::{json, Value};
+use std::cmp::Reverse;
+use std::collections::{BTreeMap, BTreeSet};
+use std::io::{self, Write};
+use wdwarf::{demangle, FunctionInfo, Symbolizer};
+
+use crate::coverage::find_line_range;
+
+const UNKNOWN: &str = "<unknown>";
+
+// The source the code bytes belong to. The function is the innermost one,
+// i.e. the inlined function for the inlined code.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Owner {
+    pub unit: String,
+    pub file: String,
+    pub function: String,
+    pub line: u64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum GroupBy {
+    File,
+    Line,
+    Function,
+    Unit,
+}
+
+impl GroupBy {
+    pub fn parse(s: &str) -> Option<GroupBy> {
+        Some(match s {
+            "file" => GroupBy::File,
+            "line" => GroupBy::Line,
+            "function" => GroupBy::Function,
+            "unit" => GroupBy::Unit,
+            _ => return None,
+        })
+    }
+
+    fn title(self) -> &'static str {
+        match self {
+            GroupBy::File => "File",
+            GroupBy::Line => "Line",
+            GroupBy::Function => "Function",
+            GroupBy::Unit => "Compilation unit",
+        }
+    }
+
+    fn key(self, owner: &Owner) -> String {
+        match self {
+            GroupBy::File => owner.file.clone(),
+            GroupBy::Line => format!("{}:{}", owner.file, owner.line),
+            GroupBy::Function => owner.function.clone(),
+            GroupBy::Unit => owner.unit.clone(),
+        }
+    }
+}
+
+fn function_name(name: &Option<String>) -> String {
+    match name {
+        Some(name) => demangle(name).unwrap_or_else(|| name.clone()),
+        None => UNKNOWN.to_string(),
+    }
+}
+
+fn contains(ranges: &[(u64, u64)], addr: u64) -> bool {
+    ranges
+        .iter()
+        .any(|(begin, end)| *begin <= addr && addr < *end)
+}
+
+// Returns the innermost function name at the address, and the compilation
+// unit name.
+fn find_function(functions: &[(u64, u64, &FunctionInfo)], addr: u64) -> Option<(String, String)> {
+    let i = functions.partition_point(|(begin, _, _)| *begin <= addr);
+    if i == 0 || addr >= functions[i - 1].1 {
+        return None;
+    }
+    let function = functions[i - 1].2;
+    let unit = function.unit.clone().unwrap_or_else(|| UNKNOWN.to_string());
+    let mut name = &function.name;
+    let mut depth = None;
+    for call in function.inlined.iter() {
+        if depth < Some(call.depth) && contains(&call.ranges, addr) {
+            name = &call.function;
+            depth = Some(call.depth);
+        }
+    }
+    Some((function_name(name), unit))
+}
+
+// Attributes every code section byte to its owner, using the line table and
+// the subprograms (and the inlined subroutines) ranges. The bytes that are
+// not described by the DWARF, e.g. the function bodies headers, have the
+// unknown owner.
+pub fn attribute_code<R: Reader<Offset = usize>>(
+    symbolizer: &Symbolizer<R>,
+    code_size: u64,
+) -> read::Result<BTreeMap<Owner, u64>> {
+    let lines = symbolizer.line_ranges()?;
+    let function_infos = symbolizer.functions()?;
+    let mut functions = function_infos
+        .iter()
+        .flat_map(|f| f.ranges.iter().map(move |(begin, end)| (*begin, *end, f)))
+        .collect::<Vec<_>>();
+    functions.sort_by_key(|(begin, end, _)| (*begin, *end));
+    functions.dedup_by_key(|(begin, end, _)| (*begin, *end));
+
+    let mut boundaries = vec![0, code_size];
+    for range in lines.iter() {
+        boundaries.push(range.begin);
+        boundaries.push(range.end);
+    }
+    for f in function_infos.iter() {
+        let inlined = f.inlined.iter().flat_map(|call| call.ranges.iter());
+        for (begin, end) in f.ranges.iter().chain(inlined) {
+            boundaries.push(*begin);
+            boundaries.push(*end);
+        }
+    }
+    boundaries.retain(|addr| *addr <= code_size);
+    boundaries.sort();
+    boundaries.dedup();
+
+    let mut result = BTreeMap::new();
+    for pair in boundaries.windows(2) {
+        let (begin, end) = (pair[0], pair[1]);
+        let (file, line) = match find_line_range(&lines, begin) {
+            Some(range) if range.line != 0 => (range.file.clone(), range.line),
+            Some(range) => (range.file.clone(), 0),
+            None => (None, 0),
+        };
+        let (function, unit) = find_function(&functions, begin)
+            .unwrap_or_else(|| (UNKNOWN.to_string(), UNKNOWN.to_string()));
+        let owner = Owner {
+            unit,
+            file: file.unwrap_or_else(|| UNKNOWN.to_string()),
+            function,
+            line,
+        };
+        *result.entry(owner).or_insert(0) += end - begin;
+    }
+    Ok(result)
+}
+
+pub fn group_sizes(sizes: &BTreeMap<Owner, u64>, by: GroupBy) -> BTreeMap<String, u64> {
+    let mut result = BTreeMap::new();
+    for (owner, size) in sizes.iter() {
+        *result.entry(by.key(owner)).or_insert(0) += size;
+    }
+    result
+}
+
+fn percent(size: u64, total: u64) -> f64 {
+    if total == 0 {
+        return 0.0;
+    }
+    size as f64 * 100.0 / total as f64
+}
+
+pub fn write_table<W: Write>(
+    out: &mut W,
+    sizes: &BTreeMap<Owner, u64>,
+    by: GroupBy,
+    sort_by_name: bool,
+) -> io::Result<()> {
+    let mut rows = group_sizes(sizes, by).into_iter().collect::<Vec<_>>();
+    if !sort_by_name {
+        rows.sort_by_key(|(_, size)| Reverse(*size));
+    }
+    let total = rows.iter().map(|(_, size)| size).sum::<u64>();
+    writeln!(out, "{:>10} {:>8}  {}", "Bytes", "Percent", by.title())?;
+    for (name, size) in rows.iter() {
+        writeln!(
+            out,
+            "{:>10} {:>7.2}%  {}",
+            size,
+            percent(*size, total),
+            name
+        )?;
+    }
+    writeln!(out, "{:>10} {:>7.2}%  <total>", total, 100.0)?;
+    Ok(())
+}
+
+fn tree_node(name: String, children: Vec<Value>) -> Value {
+    let size = children
+        .iter()
+        .map(|child| child["size"].as_u64().unwrap())
+        .sum::<u64>();
+    json!({ "name": name, "size": size, "children": children })
+}
+
+fn sorted_by_size(mut nodes: Vec<Value>) -> Vec<Value> {
+    nodes.sort_by_key(|node| Reverse(node["size"].as_u64().unwrap()));
+    nodes
+}
+
+// The JSON tree of the sizes: the compilation units, their files, the files
+// functions and the functions lines. The children are ordered by size.
+pub fn write_json_tree<W: Write>(
+    out: &mut W,
+    sizes: &BTreeMap<Owner, u64>,
+    module_name: &str,
+) -> io::Result<()> {
+    let mut tree: BTreeMap<&str, BTreeMap<&str, BTreeMap<&str, Vec<Value>>>> = BTreeMap::new();
+    for (owner, size) in sizes.iter() {
+        tree.entry(&owner.unit)
+            .or_default()
+            .entry(&owner.file)
+            .or_default()
+            .entry(&owner.function)
+            .or_default()
+            .push(json!({ "name": owner.line.to_string(), "size": size }));
+    }
+    let units = tree
+        .into_iter()
+        .map(|(unit, files)| {
+            let files = files
+                .into_iter()
+                .map(|(file, functions)| {
+                    let functions = functions
+                        .into_iter()
+                        .map(|(function, lines)| {
+                            tree_node(function.to_string(), sorted_by_size(lines))
+                        })
+                        .collect();
+                    tree_node(file.to_string(), sorted_by_size(functions))
+                })
+                .collect();
+            tree_node(unit.to_string(), sorted_by_size(files))
+        })
+        .collect();
+    let root = tree_node(module_name.to_string(), sorted_by_size(units));
+    writeln!(out, "{}", serde_json::to_string_pretty(&root)?)
+}
+
+// Compares the grouped sizes of the base and the new builds. Only the changed
+// entries are listed, the largest changes first.
+pub fn write_diff<W: Write>(
+    out: &mut W,
+    base: &BTreeMap<Owner, u64>,
+    sizes: &BTreeMap<Owner, u64>,
+    by: GroupBy,
+    sort_by_name: bool,
+    as_json: bool,
+) -> io::Result<()> {
+    let base = group_sizes(base, by);
+    let sizes = group_sizes(sizes, by);
+    let mut rows = base
+        .keys()
+        .chain(sizes.keys())
+        .collect::<BTreeSet<_>>()
+        .into_iter()
+        .map(|name| {
+            let old = base.get(name).cloned().unwrap_or(0);
+            let new = sizes.get(name).cloned().unwrap_or(0);
+            (name, old, new, new as i64 - old as i64)
+        })
+        .filter(|(_, _, _, delta)| *delta != 0)
+        .collect::<Vec<_>>();
+    if !sort_by_name {
+        rows.sort_by_key(|(_, _, _, delta)| Reverse(delta.abs()));
+    }
+    let old_total = base.values().sum::<u64>();
+    let new_total = sizes.values().sum::<u64>();
+    let total_delta = new_total as i64 - old_total as i64;
+
+    if as_json {
+        let rows = rows
+            .iter()
+            .map(|(name, old, new, delta)| {
+                json!({ "name": name, "old": old, "new": new, "delta": delta })
+            })
+            .collect::<Vec<_>>();
+        let result = json!({
+            "old": old_total,
+            "new": new_total,
+            "delta": total_delta,
+            "changes": rows,
+        });
+        return writeln!(out, "{}", serde_json::to_string_pretty(&result)?);
+    }
+    writeln!(
+        out,
+        "{:>10} {:>10} {:>10}  {}",
+        "Old",
+        "New",
+        "Delta",
+        by.title()
+    )?;
+    for (name, old, new, delta) in rows.iter() {
+        writeln!(out, "{:>10} {:>10} {:>+10}  {}", old, new, delta, name)?;
+    }
+    writeln!(
+        out,
+        "{:>10} {:>10} {:>+10}  <total>",
+        old_total, new_total, total_delta
+    )?;
+    Ok(())
+}
@@ -0,0 +1,74 @@
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use wdwarf::{read_build_id, read_code_section, read_custom_section, ReadError};
+
+// The files are stored in the debuginfod-like layout:
+// <store>/buildid/<hex build id>/{debuginfo,executable}.
+pub const DEBUGINFO: &str = "debuginfo";
+pub const EXECUTABLE: &str = "executable";
+
+pub fn format_build_id(build_id: &[u8]) -> String {
+    build_id.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+pub fn parse_build_id(s: &str) -> Option<Vec<u8>> {
+    if s.is_empty() || !s.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..s.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(s.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+pub fn store_path(store: &Path, build_id: &[u8], kind: &str) -> PathBuf {
+    store
+        .join("buildid")
+        .join(format_build_id(build_id))
+        .join(kind)
+}
+
+// Copies the file into the store: the debug companion (or the module with
+// the embedded DWARF) as debuginfo, and the module with code as executable.
+pub fn add_file(store: &Path, file: &Path) -> io::Result<Vec<PathBuf>> {
+    let bin = fs::read(file)?;
+    let invalid_data = |e: ReadError| io::Error::new(io::ErrorKind::InvalidData, e.to_string());
+    let build_id = match read_build_id(&bin).map_err(invalid_data)? {
+        Some(build_id) => build_id,
+        None => {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "build_id section was not found",
+            ))
+        }
+    };
+    let mut kinds = Vec::new();
+    if read_custom_section(&bin, ".debug_info")
+        .map_err(invalid_data)?
+        .is_some()
+    {
+        kinds.push(DEBUGINFO);
+    }
+    if read_code_section(&bin).map_err(invalid_data)?.is_some() {
+        kinds.push(EXECUTABLE);
+    }
+    let mut result = Vec::new();
+    for kind in kinds {
+        let path = store_path(store, &build_id, kind);
+        fs::create_dir_all(path.parent().unwrap())?;
+        fs::write(&path, &bin)?;
+        result.push(path);
+    }
+    Ok(result)
+}
+
+pub fn find_file(store: &Path, build_id: &[u8], kind: &str) -> Option<PathBuf> {
+    let path = store_path(store, build_id, kind);
+    if path.is_file() {
+        Some(path)
+    } else {
+        None
+    }
+}
@@ -0,0 +1,139 @@
+use gimli::Reader;
+use std::io::{self, BufRead, Write};
+use wdwarf::{demangle, CodeSection, Frame, Symbolizer};
+
+// The wasm frame location of the stack trace line, e.g.
+// `wasm-function[12]:0x1a3`. The hexadecimal offsets are relative to the
+// module start, and the decimal ones (older V8) to the function start.
+struct WasmLocation {
+    function_index: u32,
+    offset: u64,
+    is_module_offset: bool,
+}
+
+fn parse_location(line: &str) -> Option<WasmLocation> {
+    const PREFIX: &str = "wasm-function[";
+    let start = line.find(PREFIX)? + PREFIX.len();
+    let rest = &line[start..];
+    let end = rest.find(']')?;
+    let function_index = rest[..end].parse().ok()?;
+    let rest = rest[end + 1..].strip_prefix(':')?;
+    let (digits, radix, is_module_offset) = match rest.strip_prefix("0x") {
+        Some(digits) => (digits, 16, true),
+        None => (rest, 10, false),
+    };
+    let len = digits
+        .find(|c: char| !c.is_digit(radix))
+        .unwrap_or(digits.len());
+    let offset = u64::from_str_radix(&digits[..len], radix).ok()?;
+    Some(WasmLocation {
+        function_index,
+        offset,
+        is_module_offset,
+    })
+}
+
+enum TraceStyle {
+    // `    at name (url:wasm-function[12]:0x1a3)`
+    V8(String),
+    // `name@url:wasm-function[12]:0x1a3`
+    Firefox,
+    // The line is kept, and the frames are listed after it.
+    Other(String),
+}
+
+fn trace_style(line: &str) -> TraceStyle {
+    let trimmed = line.trim_start();
+    let indent = line[..line.len() - trimmed.len()].to_string();
+    if trimmed.starts_with("at ") {
+        TraceStyle::V8(indent)
+    } else if trimmed.contains('@') {
+        TraceStyle::Firefox
+    } else {
+        TraceStyle::Other(indent)
+    }
+}
+
+pub struct Symbolicator<'a, R: Reader<Offset = usize>> {
+    pub symbolizer: Symbolizer<'a, R>,
+    pub code: &'a CodeSection,
+    pub demangle: bool,
+}
+
+impl<'a, R: Reader<Offset = usize>> Symbolicator<'a, R> {
+    fn code_address(&self, location: &WasmLocation) -> Option<u64> {
+        if location.is_module_offset {
+            return location.offset.checked_sub(self.code.offset);
+        }
+        let index = location
+            .function_index
+            .checked_sub(self.code.imported_functions)?;
+        let (start, end) = *self.code.function_ranges.get(index as usize)?;
+        let addr = start + location.offset;
+        if addr < end {
+            Some(addr)
+        } else {
+            None
+        }
+    }
+
+    fn function_name(&self, frame: &Frame) -> String {
+        match &frame.function {
+            Some(name) if self.demangle => demangle(name).unwrap_or_else(|| name.clone()),
+            Some(name) => name.clone(),
+            None => "??".to_string(),
+        }
+    }
+
+    // Returns the source level frames lines, or None if the line is not
+    // a wasm frame or it cannot be resolved, e.g. due to the malformed DWARF.
+    pub fn rewrite_line(&self, line: &str) -> Option<Vec<String>> {
+        let location = parse_location(line)?;
+        let addr = self.code_address(&location)?;
+        let frames = self.symbolizer.find_frames(addr).ok()?;
+        if frames.is_empty() {
+            return None;
+        }
+        let style = trace_style(line);
+        let mut lines = Vec::new();
+        if let TraceStyle::Other(_) = style {
+            lines.push(line.to_string());
+        }
+        lines.extend(frames.iter().map(|frame| {
+            let name = self.function_name(frame);
+            let source = format_source_location(frame);
+            match &style {
+                TraceStyle::V8(indent) => format!("{}at {} ({})", indent, name, source),
+                TraceStyle::Firefox => format!("{}@{}", name, source),
+                TraceStyle::Other(indent) => format!("{}    {} ({})", indent, name, source),
+            }
+        }));
+        Some(lines)
+    }
+
+    pub fn symbolicate<B: BufRead, W: Write>(&self, input: B, out: &mut W) -> io::Result<()> {
+        for line in input.lines() {
+            let line = line?;
+            match self.rewrite_line(&line) {
+                Some(lines) => {
+                    for line in lines {
+                        writeln!(out, "{}", line)?;
+                    }
+                }
+                None => writeln!(out, "{}", line)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+fn format_source_location(frame: &Frame) -> String {
+    let mut result = frame.file.clone().unwrap_or_else(|| "??".to_string());
+    if let Some(line) = frame.line {
+        result.push_str(&format!(":{}", line));
+        if let Some(column) = frame.column {
+            result.push_str(&format!(":{}", column));
+        }
+    }
+    result
+}
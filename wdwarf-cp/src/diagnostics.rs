@@ -0,0 +1,71 @@
+use gimli::UnitSectionOffset;
+use serde_json::json;
+use wdwarf::{Diagnostic, DiagnosticCode, Diagnostics, Severity};
+
+pub struct DiagnosticOptions {
+    // The lowest severity to print, or none if the diagnostics are not printed.
+    pub min_severity: Option<Severity>,
+    pub deny_warnings: bool,
+    pub allow: Vec<DiagnosticCode>,
+    pub json: bool,
+}
+
+// Returns the diagnostic code, or the error message listing the known codes.
+pub fn parse_code(name: &str) -> Result<DiagnosticCode, String> {
+    DiagnosticCode::from_name(name).ok_or_else(|| {
+        let names = DiagnosticCode::ALL
+            .iter()
+            .map(|code| code.name())
+            .collect::<Vec<_>>();
+        format!(
+            "unknown diagnostic code: {}, expected one of: {}",
+            name,
+            names.join(", ")
+        )
+    })
+}
+
+fn die_offset(offset: UnitSectionOffset) -> usize {
+    match offset {
+        UnitSectionOffset::DebugInfoOffset(o) => o.0,
+        UnitSectionOffset::DebugTypesOffset(o) => o.0,
+    }
+}
+
+fn print_diagnostic(diagnostic: &Diagnostic, json: bool) {
+    if json {
+        let item = json!({
+            "code": diagnostic.code.name(),
+            "severity": diagnostic.severity().name(),
+            "message": diagnostic.message,
+            "die": diagnostic.die.map(die_offset),
+            "address": diagnostic.address,
+        });
+        eprintln!("{}", item);
+    } else {
+        eprintln!("{}", diagnostic);
+    }
+}
+
+// Prints the diagnostics that are not suppressed, and returns the number
+// of the errors and the denied warnings.
+pub fn report_diagnostics(diagnostics: &Diagnostics, options: &DiagnosticOptions) -> usize {
+    let mut failures = 0;
+    for diagnostic in diagnostics.items.iter() {
+        if options.allow.contains(&diagnostic.code) {
+            continue;
+        }
+        match diagnostic.severity() {
+            Severity::Error => failures += 1,
+            Severity::Warning if options.deny_warnings => failures += 1,
+            _ => (),
+        }
+        match options.min_severity {
+            Some(min_severity) if diagnostic.severity() >= min_severity => {
+                print_diagnostic(diagnostic, options.json)
+            }
+            _ => (),
+        }
+    }
+    failures
+}
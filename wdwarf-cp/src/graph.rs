@@ -0,0 +1,92 @@
+use gimli::UnitSectionOffset;
+use serde_json::json;
+use std::collections::HashMap;
+use std::io::{self, Write};
+use wdwarf::DependencyGraph;
+
+fn format_offset(offset: UnitSectionOffset) -> String {
+    match offset {
+        UnitSectionOffset::DebugInfoOffset(o) => format!("0x{:08x}", o.0),
+        UnitSectionOffset::DebugTypesOffset(o) => format!("types+0x{:08x}", o.0),
+    }
+}
+
+fn escape_dot(s: &str) -> String {
+    s.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+pub fn write_dot<W: Write>(graph: &DependencyGraph, w: &mut W) -> io::Result<()> {
+    writeln!(w, "digraph dependencies {{")?;
+    writeln!(w, "  node [shape=box, fontsize=10];")?;
+    // Grouping the DIEs by compilation unit.
+    let mut units: Vec<(&str, Vec<usize>)> = Vec::new();
+    let mut unit_index = HashMap::new();
+    for (i, node) in graph.nodes.iter().enumerate() {
+        let index = *unit_index.entry(node.unit.as_str()).or_insert_with(|| {
+            units.push((node.unit.as_str(), Vec::new()));
+            units.len() - 1
+        });
+        units[index].1.push(i);
+    }
+    for (i, (unit, nodes)) in units.iter().enumerate() {
+        writeln!(w, "  subgraph cluster_{} {{", i)?;
+        writeln!(w, "    label=\"{}\";", escape_dot(unit))?;
+        for node in nodes.iter().map(|j| &graph.nodes[*j]) {
+            let mut label = format!("{}\\n{}", format_offset(node.offset), node.tag);
+            if let Some(name) = &node.name {
+                label.push_str(&format!("\\n{}", escape_dot(name)));
+            }
+            label.push_str(&format!("\\n{} bytes", node.size));
+            let style = match (node.root, node.reachable) {
+                (true, _) => "bold",
+                (false, true) => "solid",
+                (false, false) => "dashed",
+            };
+            writeln!(
+                w,
+                "    \"{}\" [label=\"{}\", style={}];",
+                format_offset(node.offset),
+                label,
+                style
+            )?;
+        }
+        writeln!(w, "  }}")?;
+    }
+    for (a, b) in graph.edges.iter() {
+        writeln!(
+            w,
+            "  \"{}\" -> \"{}\";",
+            format_offset(*a),
+            format_offset(*b)
+        )?;
+    }
+    writeln!(w, "}}")?;
+    Ok(())
+}
+
+pub fn write_json<W: Write>(graph: &DependencyGraph, w: &mut W) -> io::Result<()> {
+    let nodes = graph
+        .nodes
+        .iter()
+        .map(|node| {
+            json!({
+                "offset": format_offset(node.offset),
+                "unit": node.unit,
+                "tag": node.tag,
+                "name": node.name,
+                "size": node.size,
+                "reachable": node.reachable,
+                "root": node.root,
+            })
+        })
+        .collect::<Vec<_>>();
+    let edges = graph
+        .edges
+        .iter()
+        .map(|(a, b)| json!([format_offset(*a), format_offset(*b)]))
+        .collect::<Vec<_>>();
+    let result = json!({ "nodes": nodes, "edges": edges });
+    serde_json::to_writer_pretty(&mut *w, &result)?;
+    writeln!(w)?;
+    Ok(())
+}
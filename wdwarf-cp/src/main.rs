@@ -1,31 +1,100 @@
 use docopt::Docopt;
-use gimli::{self, read, write};
+use gimli::{self, read, write, DebugInfoOffset, UnitSectionOffset};
 use serde::Deserialize;
+use std::collections::HashMap;
 use std::fs;
-use std::io::BufReader;
+use std::io::{BufReader, BufWriter};
 use std::path::Path;
+use std::process;
 use wdwarf::{
-    build_dependencies, create_dwarf_sections, from_dwarf, AddressTranslator,
-    IdentityAddressTranslator, TranformAddressTranslator,
+    build_dependencies, build_dependency_graph, create_dwarf_sections,
+    create_dwarf_sections_with_sources, describe_die, find_dies_by_name, from_dwarf, is_die_offset,
+    read_dwarf, read_json_map_transform, verify_dwarf, write_custom_section, write_leb128,
+    AddressMap, AddressTranslator, ConvertOptions, ConvertReport, DebugInfoLevel, Dependencies,
+    DiagnosticCode, FunctionAddressTranslator, IdentityAddressTranslator, RootFilter, Severity,
+    TranformAddressTranslator, BUILD_ID, EXTERNAL_DEBUG_INFO,
 };
 
-mod json_map;
+mod diagnostics;
+mod graph;
+mod md5;
+mod sources;
+mod stats;
+mod trace;
 mod wasm;
 
+use diagnostics::DiagnosticOptions;
+use md5::md5;
+use sources::SourceOptions;
+use trace::{Trace, TraceQuery};
+
 const USAGE: &str = "
 Copy WebAssembly DWARF with appling a transform. The dead code will be removed.
 
 Usage:
-    wdwarf-cp <source-file> -o <output> [-m <json> -w <wasm>]
-    wdwarf-cp <source-file> -i <output> -m <json>
+    wdwarf-cp <source-file> -o <output> [-m <json> | --match-names] [-w <wasm>] [options] [--keep-cu=<glob>...] [--keep-name=<glob>...] [--include-cu=<glob>...] [--exclude-cu=<glob>...] [--prefix-map=<map>...] [--source-path=<dir>...] [--source-prefix-map=<map>...] [--embed-include=<glob>...] [--embed-exclude=<glob>...] [--min-stat=<stat>...] [--allow=<code>...]
+    wdwarf-cp <source-file> -i <output> -m <json> [options] [--keep-cu=<glob>...] [--keep-name=<glob>...] [--include-cu=<glob>...] [--exclude-cu=<glob>...] [--prefix-map=<map>...] [--source-path=<dir>...] [--source-prefix-map=<map>...] [--embed-include=<glob>...] [--embed-exclude=<glob>...] [--min-stat=<stat>...] [--allow=<code>...]
     wdwarf-cp --help
 
 Options:
-    -h, --help             print this help message
-    -m, --source-map=JSON  JSON source maps-like transform
-    -w, --wasm-file=WASM   WebAssembly transformed file
-    -i, --in-place         In-place WebAssembly file sections replacement
-    -o, --output           Output WebAssembly file
+    -h, --help              print this help message
+    -m, --source-map=JSON   JSON source maps-like transform
+    -w, --wasm-file=WASM    WebAssembly transformed file
+    -i, --in-place          In-place WebAssembly file sections replacement
+    --match-names           Match the functions by names instead of the source map,
+                            only the function ranges and entries are translated,
+                            the scopes are widened to the whole functions, and
+                            the location lists are removed.
+                            Without the source map and this option, the identical
+                            function bodies of the wasm file are matched
+    -o, --output            Output WebAssembly file
+    --check-data-segments   Drop global variables whose data was removed from the output
+    --keep-types            Keep all global types
+    --keep-cu=<glob>        Keep all DIEs of the compilation units matching the name
+    --keep-name=<glob>      Keep DIEs with DW_AT_name or DW_AT_linkage_name matching
+    --no-gc                 Do not remove the dead DIEs
+    --explain-die=<offset>  Explain why the DIE at .debug_info offset was kept or removed
+    --explain-name=<name>   Explain why the DIEs with matching name were kept or removed
+    --graph=<file>          Write the DIE dependency graph as DOT, or JSON for .json files
+    --graph-cu=<glob>       Include only the compilation units matching the name in the graph
+    --graph-root=<die>      Include only DIEs reachable from the DIE offset or name in the graph
+    --odr                   Merge identical named types of the different compilation units
+    --level=<level>         Debug info level: full, no-variables, names-only,
+                            or line-tables-only [default: full]
+    --include-cu=<glob>     Keep only the compilation units with matching name or directory
+    --exclude-cu=<glob>     Remove the compilation units with matching name or directory
+    --prefix-map=<map>      Replace the source path prefix, specified as OLD=NEW
+    --embed-sources         Embed the source files contents into the line programs
+                            (the DWARF version 5 is used)
+    --source-path=<dir>     Look for the embedded source files in the directory
+    --source-prefix-map=<map>
+                            Replace the source path prefix to find the file locally,
+                            specified as OLD=NEW
+    --embed-max-size=<bytes>
+                            Do not embed the source files larger than the size
+    --embed-include=<glob>  Embed only the source files with matching path
+    --embed-exclude=<glob>  Do not embed the source files with matching path
+    --debug-file=<file>     Write DWARF into the separate debug file, and reference
+                            it in the external_debug_info section of the output
+    --debug-url=<url>       The debug file location written into the output
+                            (defaults to the debug file path relative to the output)
+    --build-id              Write the build_id section, it is always written when
+                            the separate debug file is used
+    --stats                 Print the debug info statistics as JSON to stdout, the
+                            explain and trace output goes to stderr
+    --min-stat=<stat>       Fail if the kept percentage of the statistic is lower,
+                            specified as NAME=PERCENT, e.g. line_rows=90
+    --trace-address=<addr>  Trace the translation of the original code address
+                            (relative to the code section)
+    --trace-target=<addr>   Trace the translation to the target code address
+                            (relative to the code section)
+    -v, --verbose           Print the notes in addition to the warnings
+    -q, --quiet             Do not print the diagnostics
+    --deny-warnings         Fail if there are warnings
+    --allow=<code>          Suppress the diagnostics with the code, e.g. row-before-sequence
+    --diagnostics-json      Print the diagnostics as JSON, one object per line
+    --verify                Verify the written DWARF against the output code, and fail
+                            if it is inconsistent
 ";
 
 #[derive(Deserialize, Debug, Clone)]
@@ -34,15 +103,237 @@ struct Args {
     arg_output: String,
     flag_source_map: Option<String>,
     flag_wasm_file: Option<String>,
+    flag_match_names: bool,
+    flag_check_data_segments: bool,
+    flag_keep_types: bool,
+    flag_keep_cu: Vec<String>,
+    flag_keep_name: Vec<String>,
+    flag_no_gc: bool,
+    flag_explain_die: Option<String>,
+    flag_explain_name: Option<String>,
+    flag_graph: Option<String>,
+    flag_graph_cu: Option<String>,
+    flag_graph_root: Option<String>,
+    flag_odr: bool,
+    flag_level: String,
+    flag_include_cu: Vec<String>,
+    flag_exclude_cu: Vec<String>,
+    flag_prefix_map: Vec<String>,
+    flag_embed_sources: bool,
+    flag_source_path: Vec<String>,
+    flag_source_prefix_map: Vec<String>,
+    flag_embed_max_size: Option<u64>,
+    flag_embed_include: Vec<String>,
+    flag_embed_exclude: Vec<String>,
+    flag_debug_file: Option<String>,
+    flag_debug_url: Option<String>,
+    flag_build_id: bool,
+    flag_stats: bool,
+    flag_min_stat: Vec<String>,
+    flag_trace_address: Option<String>,
+    flag_trace_target: Option<String>,
+    flag_verbose: bool,
+    flag_quiet: bool,
+    flag_deny_warnings: bool,
+    flag_allow: Vec<String>,
+    flag_diagnostics_json: bool,
+    flag_verify: bool,
+}
+
+enum Transform {
+    // The address map (from the source map, or of the identical function
+    // bodies), and the target and original functions ranges.
+    Map(AddressMap, Vec<(u64, u64)>, Vec<(u64, u64)>),
+    // The pairs of the original and target functions ranges.
+    Functions(Vec<((u64, u64), (u64, u64))>),
+}
+
+enum DieQuery {
+    // The .debug_info offset.
+    Offset(usize),
+    Name(String),
+}
+
+impl DieQuery {
+    fn parse(s: &str) -> DieQuery {
+        let offset = match s.strip_prefix("0x") {
+            Some(hex) => usize::from_str_radix(hex, 16).ok(),
+            None => s.parse().ok(),
+        };
+        match offset {
+            Some(offset) => DieQuery::Offset(offset),
+            None => DieQuery::Name(s.to_string()),
+        }
+    }
+}
+
+fn find_dies<R: gimli::Reader<Offset = usize>>(
+    dwarf: &read::Dwarf<R>,
+    queries: &[DieQuery],
+) -> read::Result<Vec<UnitSectionOffset>> {
+    let mut offsets = Vec::new();
+    for query in queries {
+        match query {
+            DieQuery::Offset(offset) => {
+                let die = UnitSectionOffset::DebugInfoOffset(DebugInfoOffset(*offset));
+                if !is_die_offset(dwarf, die)? {
+                    usage_error(format!(
+                        "invalid DIE offset: 0x{:x}, expected the offset of a DIE in .debug_info",
+                        offset
+                    ));
+                }
+                offsets.push(die);
+            }
+            DieQuery::Name(name) => {
+                let found = find_dies_by_name(dwarf, name)?;
+                if found.is_empty() {
+                    eprintln!("{}: no DIEs found", name);
+                }
+                offsets.extend(found);
+            }
+        }
+    }
+    Ok(offsets)
+}
+
+struct GraphOptions {
+    file: String,
+    unit: Option<String>,
+    root: Option<DieQuery>,
+}
+
+struct GcOptions {
+    root_filters: Vec<RootFilter>,
+    explain: Vec<DieQuery>,
+    graph: Option<GraphOptions>,
 }
 
-fn build_new_dwarf<R: gimli::Reader<Offset = usize>, A: AddressTranslator>(
+fn explain_dies<R: gimli::Reader<Offset = usize>>(
+    dwarf: &read::Dwarf<R>,
+    deps: &Dependencies,
+    explain: &[DieQuery],
+) -> read::Result<()> {
+    for offset in find_dies(dwarf, explain)? {
+        let description = describe_die(dwarf, offset)?;
+        if let Some(path) = deps.find_path(offset) {
+            eprintln!("{} is kept:", description);
+            for (i, item) in path.into_iter().enumerate() {
+                let kind = if i == 0 { "root" } else { "->" };
+                eprintln!("  {} {}", kind, describe_die(dwarf, item)?);
+            }
+            continue;
+        }
+        eprintln!("{} is removed: not reachable from the roots", description);
+        for root in deps.find_rejected_roots(offset) {
+            eprintln!(
+                "  rejected root (code cannot be translated): {}",
+                describe_die(dwarf, root)?
+            );
+        }
+    }
+    Ok(())
+}
+
+fn write_graph<R: gimli::Reader<Offset = usize>>(
+    dwarf: &read::Dwarf<R>,
+    deps: &Dependencies,
+    options: &GraphOptions,
+) {
+    let roots = options
+        .root
+        .as_ref()
+        .map(|root| find_dies(dwarf, std::slice::from_ref(root)).expect("graph roots"));
+    let graph = build_dependency_graph(
+        dwarf,
+        deps,
+        options.unit.as_deref(),
+        roots.as_ref().map(|r| &r[..]),
+    )
+    .expect("graph");
+    let mut file = BufWriter::new(fs::File::create(&options.file).expect("graph file"));
+    if options.file.ends_with(".json") {
+        graph::write_json(&graph, &mut file)
+    } else {
+        graph::write_dot(&graph, &mut file)
+    }
+    .expect("write graph");
+}
+
+fn build_new_dwarf<R: gimli::Reader<Offset = usize>, A: AddressTranslator, D: Fn(u64) -> bool>(
     dwarf: read::Dwarf<R>,
     at: A,
+    data_filter: D,
+    gc: Option<&GcOptions>,
+    options: &ConvertOptions,
+    report: &mut ConvertReport,
+    trace: Option<&mut Trace>,
 ) -> write::ConvertResult<write::Dwarf> {
-    let deps = build_dependencies(&dwarf, &at).expect("deps");
+    if let Some(trace) = trace {
+        trace::trace_translation(&dwarf, &at, trace).expect("trace");
+    }
+    let gc = match gc {
+        Some(gc) => gc,
+        None => return from_dwarf(&dwarf, &at, &|_| true, options, report),
+    };
+    let deps =
+        build_dependencies(&dwarf, &at, &data_filter, &gc.root_filters, report).expect("deps");
+    if !gc.explain.is_empty() {
+        explain_dies(&dwarf, &deps, &gc.explain).expect("explain");
+    }
+    if let Some(graph) = &gc.graph {
+        write_graph(&dwarf, &deps, graph);
+    }
     let reachable = deps.get_reachable();
-    from_dwarf(&dwarf, &at, &|uo| reachable.contains(&uo))
+    from_dwarf(&dwarf, &at, &|uo| reachable.contains(&uo), options, report)
+}
+
+// Prints the docopt-style error for the invalid option value and exits.
+fn usage_error(message: String) -> ! {
+    docopt::Error::Argv(message).exit()
+}
+
+fn parse_prefix_map(maps: &[String]) -> Vec<(String, String)> {
+    maps.iter()
+        .map(|map| {
+            let mut parts = map.splitn(2, '=');
+            let old = parts.next().unwrap().to_string();
+            let new = parts
+                .next()
+                .unwrap_or_else(|| {
+                    usage_error(format!("invalid prefix map: {}, expected OLD=NEW", map))
+                })
+                .to_string();
+            (old, new)
+        })
+        .collect()
+}
+
+// The build ID is the hash of the module (without the debug sections) and
+// of the DWARF sections, so the same inputs always produce the same ID.
+fn compute_build_id(wasm: &[u8], dwarf_sections: &[u8]) -> [u8; 16] {
+    let mut data = Vec::with_capacity(wasm.len() + dwarf_sections.len());
+    data.extend_from_slice(wasm);
+    data.extend_from_slice(dwarf_sections);
+    md5(&data)
+}
+
+// The debug file location relative to the output file directory.
+fn debug_file_url(debug_file: &str, output: &str) -> String {
+    let debug_file = Path::new(debug_file);
+    let output_dir = Path::new(output).parent().unwrap_or_else(|| Path::new(""));
+    match debug_file.strip_prefix(output_dir) {
+        Ok(path) => path.to_string_lossy().into_owned(),
+        Err(_) => fs::canonicalize(debug_file)
+            .expect("debug file path")
+            .to_string_lossy()
+            .into_owned(),
+    }
+}
+
+fn contains(ranges: &[(u64, u64)], addr: u64) -> bool {
+    ranges
+        .iter()
+        .any(|(start, end)| *start <= addr && addr < *end)
 }
 
 fn main() {
@@ -50,44 +341,304 @@ fn main() {
         .and_then(|d| d.help(true).deserialize())
         .unwrap_or_else(|e| e.exit());
 
-    let bin = fs::read(Path::new(&args.arg_source_file)).expect("file data");
-    let dwarf = wasm::read_dwarf(&bin);
+    let source_path = Path::new(&args.arg_source_file);
+    let bin = fs::read(source_path).expect("file data");
+    let mut external = Vec::new();
+    let dwarf = read_dwarf(
+        &bin,
+        source_path.parent().unwrap_or_else(|| Path::new("")),
+        &mut external,
+    )
+    .expect("dwarf");
 
-    let (map, input_wasm) = if let Some(source_map_file) = &args.flag_source_map {
-        let (input_wasm, code_section_offsets) = {
+    let mut report = ConvertReport::default();
+    let original_data_ranges = wasm::read_data_ranges(&bin);
+    let (transform, input_wasm) =
+        if args.flag_source_map.is_some() || args.flag_match_names || args.flag_wasm_file.is_some()
+        {
             let wasm_input_file = args
                 .flag_wasm_file
                 .as_ref()
                 .unwrap_or_else(|| &args.arg_output);
             let mut input = fs::read(Path::new(wasm_input_file)).expect("file data");
-            let code_section_offsets = wasm::read_code_section_offsets(&input);
+            let transform = match &args.flag_source_map {
+                Some(source_map_file) => {
+                    let original_function_ranges = wasm::read_code_section(&bin).function_ranges;
+                    let code_section_offsets = wasm::read_code_section(&input);
+                    let file = fs::File::open(source_map_file).expect("json file");
+                    let map =
+                        read_json_map_transform(BufReader::new(file), code_section_offsets.offset)
+                            .expect("json");
+                    Transform::Map(
+                        map,
+                        code_section_offsets.function_ranges,
+                        original_function_ranges,
+                    )
+                }
+                None if args.flag_match_names => {
+                    let (functions, unmatched) = wasm::match_functions_by_name(&bin, &input);
+                    if unmatched > 0 {
+                        report.diagnostics.add(
+                            DiagnosticCode::UnmatchedFunctionName,
+                            format!("{} functions were not matched by name", unmatched),
+                            None,
+                            None,
+                        );
+                    }
+                    Transform::Functions(functions)
+                }
+                None => {
+                    let original_function_ranges = wasm::read_code_section(&bin).function_ranges;
+                    let function_ranges = wasm::read_code_section(&input).function_ranges;
+                    let (map, unmatched) = wasm::match_function_bodies(&bin, &input);
+                    for name in unmatched {
+                        report.diagnostics.add(
+                            DiagnosticCode::UnmatchedFunctionBody,
+                            format!("the function {} has no unique identical body", name),
+                            None,
+                            None,
+                        );
+                    }
+                    Transform::Map(map, function_ranges, original_function_ranges)
+                }
+            };
             wasm::remove_debug_sections(&mut input);
-            (input, code_section_offsets)
+            (Some(transform), input)
+        } else if args.flag_debug_file.is_some() {
+            // The DWARF of the source file is moved into the debug file.
+            let mut input = bin.clone();
+            wasm::remove_debug_sections(&mut input);
+            (None, input)
+        } else {
+            (None, Vec::from(wasm::WASM_HEADER))
         };
-        let file = fs::File::open(source_map_file).expect("json file");
-        let map = json_map::read_json_map_transform(
-            BufReader::new(file),
-            code_section_offsets.code_section_offset,
-        )
-        .expect("json");
-
-        (
-            Some((map, code_section_offsets.function_ranges)),
-            input_wasm,
-        )
+
+    // The variable data is still present if it is in the output data segments,
+    // or it was not in the original ones (e.g. zero-initialized data).
+    let data_ranges = if args.flag_check_data_segments && transform.is_some() {
+        wasm::read_data_ranges(&input_wasm)
     } else {
-        (None, Vec::from(wasm::WASM_HEADER))
+        None
+    };
+    let data_filter = |addr: u64| match (&original_data_ranges, &data_ranges) {
+        (Some(original), Some(data)) => contains(data, addr) || !contains(original, addr),
+        _ => true,
     };
 
-    let mut new_dwarf = if let Some(map) = map {
-        build_new_dwarf(dwarf, TranformAddressTranslator::new(map.0, map.1))
+    let gc = if args.flag_no_gc {
+        if args.flag_explain_die.is_some() || args.flag_explain_name.is_some() {
+            usage_error("--explain-die and --explain-name cannot be used with --no-gc".to_string());
+        }
+        None
     } else {
-        build_new_dwarf(dwarf, IdentityAddressTranslator(true))
+        let mut root_filters = Vec::new();
+        if args.flag_keep_types {
+            root_filters.push(RootFilter::Types);
+        }
+        root_filters.extend(args.flag_keep_cu.iter().cloned().map(RootFilter::Unit));
+        root_filters.extend(args.flag_keep_name.iter().cloned().map(RootFilter::Name));
+        let mut explain = Vec::new();
+        if let Some(die) = &args.flag_explain_die {
+            explain.push(DieQuery::parse(die));
+        }
+        if let Some(name) = &args.flag_explain_name {
+            explain.push(DieQuery::Name(name.clone()));
+        }
+        let graph = args.flag_graph.as_ref().map(|file| GraphOptions {
+            file: file.clone(),
+            unit: args.flag_graph_cu.clone(),
+            root: args
+                .flag_graph_root
+                .as_ref()
+                .map(|root| DieQuery::parse(root)),
+        });
+        Some(GcOptions {
+            root_filters,
+            explain,
+            graph,
+        })
+    };
+
+    let level = match args.flag_level.as_str() {
+        "full" => DebugInfoLevel::Full,
+        "no-variables" => DebugInfoLevel::NoVariables,
+        "names-only" => DebugInfoLevel::NamesOnly,
+        "line-tables-only" => DebugInfoLevel::LineTablesOnly,
+        _ => usage_error(format!("unknown debug info level: {}", args.flag_level)),
+    };
+    let options = ConvertOptions {
+        odr: args.flag_odr,
+        level,
+        include_units: args.flag_include_cu.clone(),
+        exclude_units: args.flag_exclude_cu.clone(),
+        prefix_map: parse_prefix_map(&args.flag_prefix_map),
+        version: if args.flag_embed_sources {
+            Some(5)
+        } else {
+            None
+        },
+    };
+
+    let thresholds = args
+        .flag_min_stat
+        .iter()
+        .map(|s| {
+            stats::parse_threshold(s).unwrap_or_else(|| {
+                usage_error(format!("invalid threshold: {}, expected NAME=PERCENT", s))
+            })
+        })
+        .collect::<Vec<_>>();
+    let parse_address = |addr: &str| {
+        trace::parse_address(addr)
+            .unwrap_or_else(|| usage_error(format!("invalid code address: {}", addr)))
+    };
+    let mut trace = match (&args.flag_trace_address, &args.flag_trace_target) {
+        (Some(addr), _) => Some(TraceQuery::Original(parse_address(addr))),
+        (None, Some(addr)) => Some(TraceQuery::Target(parse_address(addr))),
+        (None, None) => None,
+    }
+    .map(|query| Trace {
+        query,
+        targets: Vec::new(),
+    });
+    let diagnostic_options = DiagnosticOptions {
+        min_severity: if args.flag_quiet {
+            None
+        } else if args.flag_verbose {
+            Some(Severity::Note)
+        } else {
+            Some(Severity::Warning)
+        },
+        deny_warnings: args.flag_deny_warnings,
+        allow: args
+            .flag_allow
+            .iter()
+            .map(|code| {
+                diagnostics::parse_code(code).unwrap_or_else(|message| usage_error(message))
+            })
+            .collect(),
+        json: args.flag_diagnostics_json,
+    };
+    let transform_applied = transform.is_some();
+    let mut new_dwarf = match transform {
+        Some(Transform::Map(map, function_ranges, original_function_ranges)) => build_new_dwarf(
+            dwarf,
+            TranformAddressTranslator::new(
+                map,
+                function_ranges.into_boxed_slice(),
+                original_function_ranges.into_boxed_slice(),
+            ),
+            data_filter,
+            gc.as_ref(),
+            &options,
+            &mut report,
+            trace.as_mut(),
+        ),
+        Some(Transform::Functions(functions)) => build_new_dwarf(
+            dwarf,
+            FunctionAddressTranslator::new(functions),
+            data_filter,
+            gc.as_ref(),
+            &options,
+            &mut report,
+            trace.as_mut(),
+        ),
+        None => build_new_dwarf(
+            dwarf,
+            IdentityAddressTranslator(true),
+            data_filter,
+            gc.as_ref(),
+            &options,
+            &mut report,
+            trace.as_mut(),
+        ),
     }
     .expect("new dwarf");
 
+    let sections = if args.flag_embed_sources {
+        let mut sources = SourceOptions {
+            search_path: args.flag_source_path.clone(),
+            prefix_map: parse_prefix_map(&args.flag_source_prefix_map),
+            max_size: args.flag_embed_max_size,
+            include: args.flag_embed_include.clone(),
+            exclude: args.flag_embed_exclude.clone(),
+            cache: HashMap::new(),
+        };
+        let diagnostics = &mut report.diagnostics;
+        create_dwarf_sections_with_sources(&mut new_dwarf, |file| sources.load(file, diagnostics))
+            .expect("write dwarf sections")
+    } else {
+        create_dwarf_sections(&mut new_dwarf).expect("write dwarf sections")
+    };
+
+    if trace.is_some() || args.flag_verify {
+        let mut module = Vec::from(wasm::WASM_HEADER);
+        module.extend_from_slice(&sections);
+        let mut external = Vec::new();
+        let new_dwarf = read_dwarf(&module, Path::new(""), &mut external).expect("dwarf");
+        if let Some(trace) = &trace {
+            trace::trace_output(&new_dwarf, trace).expect("trace");
+        }
+        if args.flag_verify {
+            // Without the transform, the DWARF describes the original code.
+            let code = if transform_applied { &input_wasm } else { &bin };
+            let function_ranges = wasm::read_code_section(code).function_ranges;
+            verify_dwarf(&new_dwarf, &function_ranges, &mut report.diagnostics).expect("verify");
+        }
+    }
+    let failures = diagnostics::report_diagnostics(&report.diagnostics, &diagnostic_options);
+
     let mut wasm = Vec::new();
     wasm.extend_from_slice(&input_wasm);
-    wasm.extend_from_slice(&create_dwarf_sections(&mut new_dwarf).expect("write dwarf sections"));
+    let build_id = if args.flag_build_id || args.flag_debug_file.is_some() {
+        wasm::remove_custom_sections(&mut wasm, |name| name == BUILD_ID);
+        let mut build_id_section = Vec::new();
+        let build_id = compute_build_id(&wasm, &sections);
+        write_leb128(&mut build_id_section, build_id.len() as u32);
+        build_id_section.extend_from_slice(&build_id);
+        write_custom_section(&mut wasm, BUILD_ID, &build_id_section);
+        Some(build_id_section)
+    } else {
+        None
+    };
+    match &args.flag_debug_file {
+        Some(debug_file) => {
+            let mut debug_wasm = Vec::new();
+            debug_wasm.extend_from_slice(wasm::WASM_HEADER);
+            if let Some(build_id_section) = &build_id {
+                write_custom_section(&mut debug_wasm, BUILD_ID, build_id_section);
+            }
+            debug_wasm.extend_from_slice(&sections);
+            fs::write(Path::new(debug_file), &debug_wasm).expect("write debug wasm");
+
+            let url = match &args.flag_debug_url {
+                Some(url) => url.clone(),
+                None => debug_file_url(debug_file, &args.arg_output),
+            };
+            let mut section = Vec::new();
+            write_leb128(&mut section, url.len() as u32);
+            section.extend_from_slice(url.as_bytes());
+            write_custom_section(&mut wasm, EXTERNAL_DEBUG_INFO, &section);
+        }
+        None => wasm.extend_from_slice(&sections),
+    }
     fs::write(Path::new(&args.arg_output), &wasm).expect("write wasm");
+
+    if args.flag_stats || !thresholds.is_empty() {
+        let stats = stats::stats_json(&report.stats);
+        if args.flag_stats {
+            println!("{}", serde_json::to_string_pretty(&stats).unwrap());
+        }
+        let failures = stats::check_thresholds(&stats, &thresholds);
+        if !failures.is_empty() {
+            for failure in failures {
+                eprintln!("error: {}", failure);
+            }
+            process::exit(1);
+        }
+    }
+    if failures > 0 {
+        eprintln!("error: {} errors or denied warnings", failures);
+        process::exit(1);
+    }
 }
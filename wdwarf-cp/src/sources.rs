@@ -0,0 +1,136 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use wdwarf::{glob_match, strip_path_prefix, DiagnosticCode, Diagnostics, SourceFile};
+
+use crate::md5::md5;
+
+pub struct SourceOptions {
+    // The directories to look for the files with relative (or not found) paths.
+    pub search_path: Vec<String>,
+    // The (recorded, local) path prefix replacements. The last matching one is used.
+    pub prefix_map: Vec<(String, String)>,
+    pub max_size: Option<u64>,
+    pub include: Vec<String>,
+    pub exclude: Vec<String>,
+    // The loaded files, the same file can be referenced from many units.
+    pub cache: HashMap<String, Option<Vec<u8>>>,
+}
+
+impl SourceOptions {
+    fn is_embedded(&self, path: &str) -> bool {
+        (self.include.is_empty() || self.include.iter().any(|p| glob_match(p, path)))
+            && !self.exclude.iter().any(|p| glob_match(p, path))
+    }
+
+    fn candidates(&self, path: &str) -> Vec<PathBuf> {
+        let mut result = Vec::new();
+        let mut local = path.to_string();
+        for (old, new) in self.prefix_map.iter().rev() {
+            if let Some(rest) = strip_path_prefix(path.as_bytes(), old.as_bytes()) {
+                local = format!("{}{}", new, String::from_utf8_lossy(rest));
+                break;
+            }
+        }
+        result.push(PathBuf::from(&local));
+        // Trying the search path with the shorter and shorter path suffixes.
+        let parts = local
+            .split('/')
+            .filter(|part| !part.is_empty())
+            .collect::<Vec<_>>();
+        for dir in self.search_path.iter() {
+            for i in 0..parts.len() {
+                result.push(Path::new(dir).join(parts[i..].join("/")));
+            }
+        }
+        result
+    }
+
+    // Returns the file data to embed, the files that cannot be embedded are
+    // reported once.
+    pub fn load(&mut self, file: &SourceFile, diagnostics: &mut Diagnostics) -> Option<Vec<u8>> {
+        if let Some(data) = self.cache.get(file.path) {
+            return data.clone();
+        }
+        let data = self.read(file, diagnostics);
+        self.cache.insert(file.path.to_string(), data.clone());
+        data
+    }
+
+    fn read(&self, file: &SourceFile, diagnostics: &mut Diagnostics) -> Option<Vec<u8>> {
+        if !self.is_embedded(file.path) {
+            return None;
+        }
+        let found = self
+            .candidates(file.path)
+            .into_iter()
+            .find(|candidate| candidate.is_file());
+        let found = match found {
+            Some(found) => found,
+            None => {
+                diagnostics.add(
+                    DiagnosticCode::SourceNotFound,
+                    format!("{}: source file not found", file.path),
+                    None,
+                    None,
+                );
+                return None;
+            }
+        };
+        if let Some(max_size) = self.max_size {
+            let size = fs::metadata(&found).map(|m| m.len()).unwrap_or(0);
+            if size > max_size {
+                diagnostics.add(
+                    DiagnosticCode::SourceTooLarge,
+                    format!(
+                        "{}: source file is too large ({} bytes)",
+                        found.display(),
+                        size
+                    ),
+                    None,
+                    None,
+                );
+                return None;
+            }
+        }
+        let data = match fs::read(&found) {
+            Ok(data) => data,
+            Err(err) => {
+                diagnostics.add(
+                    DiagnosticCode::SourceUnreadable,
+                    format!("{}: {}", found.display(), err),
+                    None,
+                    None,
+                );
+                return None;
+            }
+        };
+        // The embedded source text is NUL-terminated.
+        if data.contains(&0) {
+            diagnostics.add(
+                DiagnosticCode::SourceHasNulBytes,
+                format!(
+                    "{}: source file contains NUL bytes, not embedded",
+                    found.display()
+                ),
+                None,
+                None,
+            );
+            return None;
+        }
+        if let Some(expected) = file.md5 {
+            if expected != [0; 16] && md5(&data) != expected {
+                diagnostics.add(
+                    DiagnosticCode::SourceChecksumMismatch,
+                    format!(
+                        "{}: source file does not match the MD5 checksum",
+                        found.display()
+                    ),
+                    None,
+                    None,
+                );
+            }
+        }
+        Some(data)
+    }
+}
@@ -0,0 +1,74 @@
+use serde_json::{json, Value};
+use wdwarf::Statistics;
+
+fn percent(kept: u64, total: u64) -> f64 {
+    if total == 0 {
+        return 100.0;
+    }
+    (kept as f64 * 10000.0 / total as f64).round() / 100.0
+}
+
+fn kept(original: u64, kept: u64) -> Value {
+    json!({
+        "original": original,
+        "kept": kept,
+        "percent": percent(kept, original),
+    })
+}
+
+pub fn stats_json(stats: &Statistics) -> Value {
+    json!({
+        "dies": kept(stats.dies, stats.dies_kept),
+        "subprograms": kept(stats.subprograms, stats.subprograms_kept),
+        "functions": kept(stats.functions, stats.functions_kept),
+        "variables": kept(stats.variables, stats.variables_kept),
+        "line_sequences": {
+            "original": stats.line_sequences,
+            "dropped": stats.line_sequences_dropped,
+        },
+        "line_rows": {
+            "original": stats.line_rows,
+            "mapped": stats.line_rows_mapped,
+            "merged": stats.line_rows_merged,
+            "dropped": stats.line_rows_dropped,
+            "percent": percent(stats.line_rows_mapped, stats.line_rows),
+        },
+        "location_bytes": kept(stats.location_bytes, stats.location_bytes_kept),
+        "ranges": {
+            "original": stats.ranges,
+            "split": stats.ranges_split,
+            "dropped": stats.ranges_dropped,
+        },
+        "gc": {
+            "roots": stats.roots,
+            "rejected_roots": stats.rejected_roots,
+        },
+    })
+}
+
+// Parses the NAME=PERCENT threshold.
+pub fn parse_threshold(s: &str) -> Option<(String, f64)> {
+    let mut parts = s.splitn(2, '=');
+    let name = parts.next().unwrap().to_string();
+    let min = parts
+        .next()
+        .and_then(|min| min.trim_end_matches('%').parse().ok())?;
+    Some((name, min))
+}
+
+// Returns the messages for the statistics with the percentage lower than
+// their thresholds.
+pub fn check_thresholds(stats: &Value, thresholds: &[(String, f64)]) -> Vec<String> {
+    let mut failures = Vec::new();
+    for (name, min) in thresholds {
+        match stats[name]["percent"].as_f64() {
+            Some(percent) if percent < *min => failures.push(format!(
+                "{}: {}% is below the {}% threshold",
+                name, percent, min
+            )),
+            Some(_) => (),
+            None => failures.push(format!("{}: unknown statistic", name)),
+        }
+    }
+    failures
+}
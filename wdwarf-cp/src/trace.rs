@@ -0,0 +1,105 @@
+use gimli::{read, write::Address};
+use wdwarf::{describe_die, find_dies_by_address, AddressTranslator, Symbolizer};
+
+pub enum TraceQuery {
+    Original(u64),
+    Target(u64),
+}
+
+pub struct Trace {
+    pub query: TraceQuery,
+    // The target addresses to show the emitted debug info for.
+    pub targets: Vec<u64>,
+}
+
+pub fn parse_address(s: &str) -> Option<u64> {
+    match s.strip_prefix("0x") {
+        Some(hex) => u64::from_str_radix(hex, 16).ok(),
+        None => s.parse().ok(),
+    }
+}
+
+// Prints the line rows and the DIEs at the address.
+fn print_debug_info<R: gimli::Reader<Offset = usize>>(
+    dwarf: &read::Dwarf<R>,
+    addr: u64,
+) -> read::Result<()> {
+    let symbolizer = Symbolizer::new(dwarf)?;
+    let rows = symbolizer
+        .line_ranges()?
+        .into_iter()
+        .filter(|range| range.begin <= addr && addr < range.end)
+        .collect::<Vec<_>>();
+    if rows.is_empty() {
+        eprintln!("  line row: none");
+    }
+    for row in rows {
+        eprintln!(
+            "  line row 0x{:x}..0x{:x} {}:{}:{}",
+            row.begin,
+            row.end,
+            row.file.as_ref().map_or("??", |file| file.as_str()),
+            row.line,
+            row.column
+        );
+    }
+    let dies = find_dies_by_address(dwarf, addr)?;
+    if dies.is_empty() {
+        eprintln!("  DIE: none");
+    }
+    for offset in dies {
+        eprintln!("  DIE {}", describe_die(dwarf, offset)?);
+    }
+    Ok(())
+}
+
+// Prints the original debug info at the traced address, and the steps of
+// its translation.
+pub fn trace_translation<R: gimli::Reader<Offset = usize>, A: AddressTranslator>(
+    dwarf: &read::Dwarf<R>,
+    at: &A,
+    trace: &mut Trace,
+) -> read::Result<()> {
+    let originals = match trace.query {
+        TraceQuery::Original(addr) => vec![addr],
+        TraceQuery::Target(addr) => {
+            eprintln!("target 0x{:x}:", addr);
+            let (lines, originals) = at.trace_target(addr);
+            for line in lines {
+                eprintln!("  {}", line);
+            }
+            if originals.is_empty() {
+                eprintln!("  original address: none");
+            }
+            trace.targets.push(addr);
+            originals
+        }
+    };
+    for addr in originals {
+        eprintln!("original 0x{:x}:", addr);
+        print_debug_info(dwarf, addr)?;
+        for line in at.trace_address(addr) {
+            eprintln!("  {}", line);
+        }
+        if let TraceQuery::Original(_) = trace.query {
+            for target in at.translate_address(addr) {
+                if let Address::Constant(target) = target {
+                    trace.targets.push(target);
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+// Prints the debug info emitted at the target addresses.
+pub fn trace_output<R: gimli::Reader<Offset = usize>>(
+    dwarf: &read::Dwarf<R>,
+    trace: &Trace,
+) -> read::Result<()> {
+    for addr in trace.targets.iter() {
+        eprintln!("emitted at target 0x{:x}:", addr);
+        print_debug_info(dwarf, *addr)?;
+    }
+    Ok(())
+}
@@ -1,71 +1,231 @@
-use gimli::{self, Dwarf, SectionId};
 use std::boxed::Box;
-use std::collections::HashMap;
-use wasmparser::{ModuleReader, Range, SectionCode};
-use wdwarf;
+use std::collections::{HashMap, HashSet};
+use wasmparser::{
+    DataKind, ExternalKind, ImportSectionEntryType, ModuleReader, Name, Operator, SectionCode,
+};
+use wdwarf::{
+    is_dwarf_section, AddressMap, CodeSection, OriginalAddress, TargetAddress, EXTERNAL_DEBUG_INFO,
+};
 
-pub fn read_dwarf<'a>(bin: &'a [u8]) -> Dwarf<gimli::EndianSlice<'a, gimli::LittleEndian>> {
-    let sections = read_dwarf_sections(bin);
-    wdwarf::read_dwarf(sections).expect("dwarf")
+pub fn read_code_section(bin: &[u8]) -> CodeSection {
+    wdwarf::read_code_section(bin)
+        .expect("wasm reader")
+        .expect("code section was not found")
 }
 
-fn read_dwarf_sections<'a>(bin: &'a [u8]) -> HashMap<&'a str, &'a [u8]> {
-    let mut sections = HashMap::new();
+// Collects the names of the defined functions from the name section and
+// the exports. The imported functions have no code, but shift the indices.
+fn read_function_names(bin: &[u8]) -> HashMap<u32, Vec<String>> {
+    let mut imported_functions = 0;
+    let mut names: HashMap<u32, Vec<String>> = HashMap::new();
     for sect in ModuleReader::new(bin).expect("wasm reader") {
         let sect = sect.expect("section");
         match sect.code {
-            SectionCode::Custom { name, .. } if to_section_id(name).is_some() => {
-                sections.insert(name, sect.range().slice(bin));
+            SectionCode::Import => {
+                for import in sect.get_import_section_reader().expect("import section") {
+                    if let ImportSectionEntryType::Function(_) = import.expect("import").ty {
+                        imported_functions += 1;
+                    }
+                }
+            }
+            SectionCode::Export => {
+                for export in sect.get_export_section_reader().expect("export section") {
+                    let export = export.expect("export");
+                    if let ExternalKind::Function = export.kind {
+                        names
+                            .entry(export.index)
+                            .or_default()
+                            .push(format!("export:{}", export.field));
+                    }
+                }
+            }
+            SectionCode::Custom { name: "name", .. } => {
+                for name in sect.get_name_section_reader().expect("name section") {
+                    if let Ok(Name::Function(functions)) = name {
+                        let mut map = functions.get_map().expect("function names");
+                        for _ in 0..map.get_count() {
+                            let naming = map.read().expect("function name");
+                            names
+                                .entry(naming.index)
+                                .or_default()
+                                .insert(0, format!("name:{}", naming.name));
+                        }
+                    }
+                }
             }
             _ => (),
         }
     }
-    sections
+    names
+        .into_iter()
+        .filter(|(index, _)| *index >= imported_functions)
+        .map(|(index, names)| (index - imported_functions, names))
+        .collect()
+}
+
+// The (original, transformed) function body ranges.
+type FunctionPairs = Vec<((u64, u64), (u64, u64))>;
+
+// Matches the functions of the original and transformed modules by their
+// names (from the name section or exports), and returns the pairs of their
+// ranges and the number of the unmatched original functions.
+pub fn match_functions_by_name(original_bin: &[u8], bin: &[u8]) -> (FunctionPairs, usize) {
+    let ranges = read_code_section(bin).function_ranges;
+    // The names used more than once are ambiguous (None).
+    let mut functions: HashMap<String, Option<(u64, u64)>> = HashMap::new();
+    for (index, names) in read_function_names(bin) {
+        for name in names {
+            let range = ranges.get(index as usize).cloned();
+            functions
+                .entry(name)
+                .and_modify(|e| *e = None)
+                .or_insert(range);
+        }
+    }
+
+    let original_ranges = read_code_section(original_bin).function_ranges;
+    let original_names = read_function_names(original_bin);
+    let mut result = Vec::new();
+    for (index, original_range) in original_ranges.iter().enumerate() {
+        let found = original_names
+            .get(&(index as u32))
+            .and_then(|names| names.iter().find_map(|name| *functions.get(name)?));
+        if let Some(range) = found {
+            result.push((*original_range, range));
+        }
+    }
+    let unmatched = original_ranges.len() - result.len();
+    (result, unmatched)
 }
 
-pub struct CodeSectionOffsets {
-    pub code_section_offset: u64,
-    pub function_ranges: Box<[(u64, u64)]>,
+fn function_display_name(index: usize, names: &HashMap<u32, Vec<String>>) -> String {
+    match names.get(&(index as u32)).and_then(|names| names.first()) {
+        Some(name) => format!("#{} {}", index, name),
+        None => format!("#{}", index),
+    }
 }
 
-pub fn read_code_section_offsets(bin: &[u8]) -> CodeSectionOffsets {
+// Matches the byte-identical function bodies of the original and transformed
+// modules, and maps their code exactly. If the same body is used several
+// times, the functions are matched by name, and the remaining ones only if
+// there is a single pair left. Returns the map and the list of the unmatched
+// original functions.
+pub fn match_function_bodies(original_bin: &[u8], bin: &[u8]) -> (AddressMap, Vec<String>) {
+    let offsets = read_code_section(bin);
+    let mut bodies: HashMap<&[u8], Vec<usize>> = HashMap::new();
+    for (index, (start, end)) in offsets.function_ranges.iter().enumerate() {
+        let base = offsets.offset;
+        let body = &bin[(base + start) as usize..(base + end) as usize];
+        bodies.entry(body).or_default().push(index);
+    }
+
+    let original_offsets = read_code_section(original_bin);
+    let mut original_bodies: HashMap<&[u8], Vec<usize>> = HashMap::new();
+    for (index, (start, end)) in original_offsets.function_ranges.iter().enumerate() {
+        let base = original_offsets.offset;
+        let body = &original_bin[(base + start) as usize..(base + end) as usize];
+        original_bodies.entry(body).or_default().push(index);
+    }
+
+    let (named, _) = match_functions_by_name(original_bin, bin);
+    let named = named.into_iter().collect::<HashMap<_, _>>();
+    let mut matched = Vec::new();
+    for (body, original_indices) in original_bodies.iter() {
+        let indices = match bodies.get(body) {
+            Some(indices) => indices,
+            None => continue,
+        };
+        // The functions with the same body are matched by name first: they
+        // might be reordered.
+        let mut original_left = Vec::new();
+        let mut left = indices.iter().cloned().collect::<HashSet<_>>();
+        for original_index in original_indices {
+            let original_range = original_offsets.function_ranges[*original_index];
+            let found = left
+                .iter()
+                .cloned()
+                .find(|index| named.get(&original_range) == Some(&offsets.function_ranges[*index]));
+            match found {
+                Some(index) => {
+                    left.remove(&index);
+                    matched.push((*original_index, index));
+                }
+                None => original_left.push(*original_index),
+            }
+        }
+        // The rest can be paired only if the pairing is unique, otherwise
+        // the functions stay unmatched.
+        if let ([original_index], [index]) = (
+            &original_left[..],
+            &left.into_iter().collect::<Vec<_>>()[..],
+        ) {
+            matched.push((*original_index, *index));
+        }
+    }
+
+    // The AddressMap expects the target addresses in order.
+    matched.sort_by_key(|(_, index)| *index);
+    let mut map = AddressMap::new();
+    for (original_index, index) in matched.iter() {
+        let (original_start, original_end) = original_offsets.function_ranges[*original_index];
+        let (start, _) = offsets.function_ranges[*index];
+        map.insert_identical_range(
+            TargetAddress(start),
+            OriginalAddress(original_start),
+            original_end - original_start,
+        );
+    }
+
+    let matched = matched.into_iter().map(|(i, _)| i).collect::<HashSet<_>>();
+    let names = read_function_names(original_bin);
+    let unmatched = (0..original_offsets.function_ranges.len())
+        .filter(|index| !matched.contains(index))
+        .map(|index| function_display_name(index, &names))
+        .collect();
+    (map, unmatched)
+}
+
+pub fn read_data_ranges(bin: &[u8]) -> Option<Box<[(u64, u64)]>> {
+    let mut ranges = Vec::new();
     for sect in ModuleReader::new(bin).expect("wasm reader") {
         let sect = sect.expect("section");
-        match sect.code {
-            SectionCode::Code => {
-                let code_section_offset = sect.range().start as u64;
-                let code_reader = sect.get_code_section_reader().expect("code section");
-                let ranges = code_reader
-                    .into_iter()
-                    .map(|f| {
-                        let Range { start, end } = f.expect("function").range();
-                        (
-                            start as u64 - code_section_offset,
-                            end as u64 - code_section_offset,
-                        )
-                    })
-                    .collect::<Vec<_>>();
-                return CodeSectionOffsets {
-                    code_section_offset,
-                    function_ranges: ranges.into_boxed_slice(),
+        if let SectionCode::Data = sect.code {
+            for data in sect.get_data_section_reader().expect("data section") {
+                let data = data.expect("data");
+                let init_expr = match data.kind {
+                    DataKind::Active { init_expr, .. } => init_expr,
+                    // The segment location is known only at runtime.
+                    DataKind::Passive => return None,
                 };
+                match init_expr.get_operators_reader().read().expect("op") {
+                    Operator::I32Const { value } => {
+                        let start = u64::from(value as u32);
+                        ranges.push((start, start + data.data.len() as u64));
+                    }
+                    _ => return None,
+                }
             }
-            _ => (),
         }
     }
-    panic!("code section was not found");
+    Some(ranges.into_boxed_slice())
 }
 
 pub fn remove_debug_sections(bin: &mut Vec<u8>) {
+    remove_custom_sections(bin, |name| {
+        is_dwarf_section(name) || name == EXTERNAL_DEBUG_INFO
+    });
+}
+
+pub fn remove_custom_sections<F: Fn(&str) -> bool>(bin: &mut Vec<u8>, filter: F) {
     let mut reader = ModuleReader::new(bin).expect("wasm reader");
     let mut position = reader.current_position();
-    // Record debug section locations into the sections_to_remove.
+    // Record matching section locations into the sections_to_remove.
     let mut sections_to_remove = Vec::new();
     while !reader.eof() {
         {
             let sect = reader.read().expect("section");
             match sect.code {
-                SectionCode::Custom { name, .. } if to_section_id(name).is_some() => {
+                SectionCode::Custom { name, .. } if filter(name) => {
                     sections_to_remove.push(position..sect.range().end);
                 }
                 _ => (),
@@ -79,29 +239,101 @@ pub fn remove_debug_sections(bin: &mut Vec<u8>) {
     }
 }
 
-fn to_section_id(name: &str) -> Option<SectionId> {
-    Some(match name {
-        ".debug_abbrev" => SectionId::DebugAbbrev,
-        ".debug_addr" => SectionId::DebugAddr,
-        ".debug_aranges" => SectionId::DebugAranges,
-        ".debug_frame" => SectionId::DebugFrame,
-        ".eh_frame" => SectionId::EhFrame,
-        ".eh_frame_hdr" => SectionId::EhFrameHdr,
-        ".debug_info" => SectionId::DebugInfo,
-        ".debug_line" => SectionId::DebugLine,
-        ".debug_line_str" => SectionId::DebugLineStr,
-        ".debug_loc" => SectionId::DebugLoc,
-        ".debug_loclists" => SectionId::DebugLocLists,
-        ".debug_macinfo" => SectionId::DebugMacinfo,
-        ".debug_pubnames" => SectionId::DebugPubNames,
-        ".debug_pubtypes" => SectionId::DebugPubTypes,
-        ".debug_ranges" => SectionId::DebugRanges,
-        ".debug_rnglists" => SectionId::DebugRngLists,
-        ".debug_str" => SectionId::DebugStr,
-        ".debug_str_offsets" => SectionId::DebugStrOffsets,
-        ".debug_types" => SectionId::DebugTypes,
-        _ => return None,
-    })
-}
-
 pub const WASM_HEADER: &[u8] = &[0, b'a', b's', b'm', 1, 0, 0, 0];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Builds the module with the `() -> ()` functions of the bodies (without
+    // the locals and the end), named in the name section if names are given.
+    fn module(bodies: &[&[u8]], names: &[&str]) -> Vec<u8> {
+        let mut bin = vec![0, b'a', b's', b'm', 1, 0, 0, 0];
+        bin.extend_from_slice(&[1, 4, 1, 0x60, 0, 0]);
+        let count = bodies.len() as u8;
+        bin.extend_from_slice(&[3, count + 1, count]);
+        bin.extend(bodies.iter().map(|_| 0));
+        let mut code = vec![count];
+        for body in bodies {
+            code.push(body.len() as u8 + 2);
+            code.push(0);
+            code.extend_from_slice(body);
+            code.push(0x0b);
+        }
+        bin.push(10);
+        bin.push(code.len() as u8);
+        bin.extend(code);
+        if !names.is_empty() {
+            let mut map = vec![names.len() as u8];
+            for (index, name) in names.iter().enumerate() {
+                map.extend_from_slice(&[index as u8, name.len() as u8]);
+                map.extend_from_slice(name.as_bytes());
+            }
+            let mut section = b"\x04name\x01".to_vec();
+            section.push(map.len() as u8);
+            section.extend(map);
+            bin.push(0);
+            bin.push(section.len() as u8);
+            bin.extend(section);
+        }
+        bin
+    }
+
+    // The pairs of the matched original and target function indices.
+    fn matched_indices(original_bin: &[u8], bin: &[u8]) -> (Vec<(usize, usize)>, Vec<String>) {
+        let (map, unmatched) = match_function_bodies(original_bin, bin);
+        let original_ranges = read_code_section(original_bin).function_ranges;
+        let ranges = read_code_section(bin).function_ranges;
+        let index_of =
+            |ranges: &[(u64, u64)], start| ranges.iter().position(|(s, _)| *s == start).unwrap();
+        let mut pairs = map
+            .pairs()
+            .into_iter()
+            .map(|(o, t)| (index_of(&original_ranges, o.0), index_of(&ranges, t.0)))
+            .collect::<Vec<_>>();
+        pairs.sort();
+        (pairs, unmatched)
+    }
+
+    const BODY_A: &[u8] = &[0x41, 1, 0x1a];
+    const BODY_B: &[u8] = &[0x41, 2, 0x1a];
+
+    #[test]
+    fn test_match_unique_bodies() {
+        let original = module(&[BODY_A, BODY_B], &[]);
+        let reordered = module(&[BODY_B, BODY_A], &[]);
+        assert_eq!(
+            matched_indices(&original, &reordered),
+            (vec![(0, 1), (1, 0)], vec![])
+        );
+    }
+
+    #[test]
+    fn test_match_identical_bodies_by_name() {
+        let original = module(&[BODY_A, BODY_A, BODY_B], &["f", "g", "h"]);
+        let reordered = module(&[BODY_B, BODY_A, BODY_A], &["h", "g", "f"]);
+        assert_eq!(
+            matched_indices(&original, &reordered),
+            (vec![(0, 2), (1, 1), (2, 0)], vec![])
+        );
+    }
+
+    #[test]
+    fn test_match_ambiguous_bodies() {
+        // Without the names, the functions with the same body cannot be told
+        // apart, and are left unmatched.
+        let original = module(&[BODY_A, BODY_A, BODY_B], &[]);
+        let reordered = module(&[BODY_B, BODY_A, BODY_A], &[]);
+        assert_eq!(
+            matched_indices(&original, &reordered),
+            (vec![(2, 0)], vec!["#0".to_string(), "#1".to_string()])
+        );
+        // A single pair left after matching by name is unique.
+        let original = module(&[BODY_A, BODY_A], &["f"]);
+        let reordered = module(&[BODY_A, BODY_A], &["g", "f"]);
+        assert_eq!(
+            matched_indices(&original, &reordered),
+            (vec![(0, 1), (1, 0)], vec![])
+        );
+    }
+}
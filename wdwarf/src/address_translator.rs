@@ -24,6 +24,26 @@ impl Into<u64> for OriginalAddress {
 struct Range {
     keypoints: Vec<(OriginalAddress, TargetAddress)>,
     last: TargetAddress,
+    // The identical range has only its start and end keypoints, and the addresses
+    // between them are shifted by the same delta. The addresses after its end
+    // are not mapped.
+    identical: bool,
+}
+
+impl Range {
+    // The target address of the keypoint at or after the address, or
+    // the interpolated one inside of the identical range.
+    fn lookup(&self, addr: OriginalAddress) -> TargetAddress {
+        let (start, key) = self.keypoints[0];
+        if self.identical && start <= addr && addr.0 - start.0 <= self.last.0 - key.0 {
+            return TargetAddress(key.0 + (addr.0 - start.0));
+        }
+        match self.keypoints.binary_search_by(|a| a.0.cmp(&addr)) {
+            Ok(i) => self.keypoints[i].1,
+            Err(i) if i < self.keypoints.len() => self.keypoints[i].1,
+            Err(_) => self.last,
+        }
+    }
 }
 
 #[derive(Debug)]
@@ -40,6 +60,20 @@ impl AddressMap {
         self.ranges.push(Range {
             keypoints: vec![(addr, key)],
             last: key,
+            identical: false,
+        });
+    }
+
+    // Maps the code that was copied as is: every address of the range,
+    // including its end, is mapped.
+    pub fn insert_identical_range(&mut self, key: TargetAddress, addr: OriginalAddress, len: u64) {
+        self.ranges.push(Range {
+            keypoints: vec![
+                (addr, key),
+                (OriginalAddress(addr.0 + len), TargetAddress(key.0 + len)),
+            ],
+            last: TargetAddress(key.0 + len),
+            identical: true,
         });
     }
 
@@ -49,6 +83,10 @@ impl AddressMap {
             return;
         }
         let last_range = self.ranges.last_mut().unwrap();
+        if last_range.identical {
+            self.start_range(key, addr);
+            return;
+        }
         if last_range.keypoints.last().unwrap().0 <= addr {
             last_range.keypoints.push((addr, key));
             last_range.last = key;
@@ -57,10 +95,118 @@ impl AddressMap {
         last_range.last = key;
         self.start_range(key, addr);
     }
+
+    // The mapped (original, target) address pairs, in the map order. Only
+    // the start of the identical range is listed.
+    pub fn pairs(&self) -> Vec<(OriginalAddress, TargetAddress)> {
+        self.ranges
+            .iter()
+            .flat_map(|range| {
+                let len = if range.identical {
+                    1
+                } else {
+                    range.keypoints.len()
+                };
+                range.keypoints[..len].iter().cloned()
+            })
+            .collect()
+    }
+}
+
+// The inverse mapping of the AddressMap, sorted by the target addresses.
+#[derive(Debug)]
+pub struct ReverseAddressMap {
+    // The (target, original) keypoints of the ranges, except the identical ones.
+    keypoints: Box<[(TargetAddress, OriginalAddress)]>,
+    // The (target start, original start, length) of the identical ranges.
+    identical: Box<[(TargetAddress, OriginalAddress, u64)]>,
+}
+
+impl ReverseAddressMap {
+    pub fn new(map: &AddressMap) -> Self {
+        let mut keypoints = Vec::new();
+        let mut identical = Vec::new();
+        for range in map.ranges.iter() {
+            if range.identical {
+                let (addr, key) = range.keypoints[0];
+                identical.push((key, addr, range.last.0 - key.0));
+            } else {
+                keypoints.extend(range.keypoints.iter().map(|(o, t)| (*t, *o)));
+            }
+        }
+        // The stable sort keeps the map order of the same target addresses.
+        keypoints.sort_by_key(|(t, _)| *t);
+        identical.sort_by_key(|(t, _, _)| *t);
+        ReverseAddressMap {
+            keypoints: keypoints.into_boxed_slice(),
+            identical: identical.into_boxed_slice(),
+        }
+    }
+
+    // The original address of the identical range that contains the target one.
+    fn find_identical(&self, key: TargetAddress) -> Option<OriginalAddress> {
+        let i = self.identical.partition_point(|x| x.0 <= key);
+        let (start, addr, len) = *self.identical[..i].last()?;
+        if key.0 - start.0 <= len {
+            Some(OriginalAddress(addr.0 + (key.0 - start.0)))
+        } else {
+            None
+        }
+    }
+
+    // The original address of the instruction that contains the target address.
+    pub fn find_original_address(&self, key: TargetAddress) -> Option<OriginalAddress> {
+        if let Some(addr) = self.find_identical(key) {
+            return Some(addr);
+        }
+        let i = self.keypoints.partition_point(|x| x.0 <= key);
+        let target = self.keypoints[..i].last()?.0;
+        let first = self.keypoints.partition_point(|x| x.0 < target);
+        Some(self.keypoints[first].1)
+    }
+
+    // The original addresses mapped exactly to the target address.
+    fn find_exact(&self, key: TargetAddress) -> Vec<OriginalAddress> {
+        let first = self.keypoints.partition_point(|x| x.0 < key);
+        let last = self.keypoints.partition_point(|x| x.0 <= key);
+        let mut result = self.keypoints[first..last]
+            .iter()
+            .map(|x| x.1)
+            .collect::<Vec<_>>();
+        result.extend(self.find_identical(key));
+        result
+    }
+
+    // The mapped (target, original) addresses in the target range, sorted. The
+    // identical range is listed by its start, or by the range start if it
+    // begins before it.
+    fn entries(
+        &self,
+        start: TargetAddress,
+        end: TargetAddress,
+    ) -> Vec<(TargetAddress, OriginalAddress)> {
+        let first = self.keypoints.partition_point(|x| x.0 < start);
+        let last = self.keypoints.partition_point(|x| x.0 < end);
+        let mut result = self.keypoints[first..last].to_vec();
+        if let Some(addr) = self.find_identical(start) {
+            result.push((start, addr));
+        }
+        let first = self.identical.partition_point(|x| x.0 <= start);
+        let last = self.identical.partition_point(|x| x.0 < end);
+        result.extend(self.identical[first..last].iter().map(|x| (x.0, x.1)));
+        result.sort_by_key(|(t, _)| *t);
+        result
+    }
 }
 
 type AddressMapIndexRanges = Vec<usize>;
 type TargetAddressRange = std::ops::Range<TargetAddress>;
+type OriginalAddressRange = std::ops::Range<OriginalAddress>;
+type InlinedRanges = (
+    OriginalAddress,
+    Option<OriginalAddress>,
+    Vec<TargetAddressRange>,
+);
 
 fn to_addr_len(range: &TargetAddressRange) -> (Address, u64) {
     let start: u64 = range.start.into();
@@ -72,7 +218,16 @@ fn to_addr_len(range: &TargetAddressRange) -> (Address, u64) {
 struct AddressMapIndexed {
     map: AddressMap,
     index: BTreeMap<OriginalAddress, AddressMapIndexRanges>,
+    reverse_index: ReverseAddressMap,
     function_ranges: Box<[TargetAddressRange]>,
+    original_function_ranges: Box<[OriginalAddressRange]>,
+}
+
+#[derive(Debug)]
+pub struct InlinedFunction {
+    pub origin: u64,
+    pub call_site: Option<u64>,
+    pub ranges: Vec<(Address, u64)>,
 }
 
 enum LookupAddressIterator<'a> {
@@ -98,19 +253,7 @@ impl<'a> Iterator for LookupAddressIterator<'a> {
                 while range_indicies.len() > 0 {
                     let range_index = range_indicies[0];
                     *range_indicies = &range_indicies[1..];
-                    let range = &map.ranges[range_index];
-                    let pos = range.keypoints.binary_search_by(|a| a.0.cmp(&addr));
-                    let result = match pos {
-                        Ok(i) => range.keypoints[i].1,
-                        Err(i) => {
-                            if i < range.keypoints.len() {
-                                range.keypoints[i].1
-                            } else {
-                                range.last
-                            }
-                        }
-                    };
-                    return Some(result);
+                    return Some(map.ranges[range_index].lookup(*addr));
                 }
                 None
             }
@@ -133,32 +276,8 @@ impl<'a> Iterator for LookupRangeIterator<'a> {
             let range_index = *self.ranges.iter().next().unwrap();
             self.ranges.take(&range_index);
             let range = &self.map.ranges[range_index];
-            let start = {
-                let pos = range.keypoints.binary_search_by(|a| a.0.cmp(&self.start));
-                match pos {
-                    Ok(i) => range.keypoints[i].1,
-                    Err(i) => {
-                        if i < range.keypoints.len() {
-                            range.keypoints[i].1
-                        } else {
-                            range.last
-                        }
-                    }
-                }
-            };
-            let end = {
-                let pos = range.keypoints.binary_search_by(|a| a.0.cmp(&self.end));
-                match pos {
-                    Ok(i) => range.keypoints[i].1,
-                    Err(i) => {
-                        if i < range.keypoints.len() {
-                            range.keypoints[i].1
-                        } else {
-                            range.last
-                        }
-                    }
-                }
-            };
+            let start = range.lookup(self.start);
+            let end = range.lookup(self.end);
             // Skip empty ranges
             if start.0 < end.0 {
                 return Some(start..end);
@@ -170,70 +289,114 @@ impl<'a> Iterator for LookupRangeIterator<'a> {
 
 impl AddressMapIndexed {
     fn generate_index(map: &AddressMap) -> BTreeMap<OriginalAddress, AddressMapIndexRanges> {
-        // Sorting ranges by first address.
-        let mut sorted_map: BTreeMap<OriginalAddress, (&Range, usize)> = BTreeMap::new();
+        // Collecting ranges first and last addresses.
+        let mut starts: BTreeMap<OriginalAddress, Vec<usize>> = BTreeMap::new();
+        let mut ends: BTreeMap<OriginalAddress, Vec<usize>> = BTreeMap::new();
+        let mut closes: BTreeMap<OriginalAddress, Vec<usize>> = BTreeMap::new();
         for (index, range) in map.ranges.iter().enumerate() {
             let first_addr = range.keypoints.first().unwrap().0;
-            sorted_map.insert(first_addr, (range, index));
+            let last_addr = range.keypoints.last().unwrap().0;
+            starts.entry(first_addr).or_default().push(index);
+            if range.identical {
+                closes
+                    .entry(OriginalAddress(last_addr.0 + 1))
+                    .or_default()
+                    .push(index);
+            } else {
+                ends.entry(last_addr).or_default().push(index);
+            }
         }
-        // Sweeping all sorted by start address ranges and populating the result as
-        // we pass the stored in active_ranges temp values.
-        let mut active_ranges: BTreeMap<OriginalAddress, Vec<usize>> = BTreeMap::new();
+        // Sweeping all boundaries and recording ranges that are active at every
+        // boundary address. The range is still active at its last address, and
+        // the closed range is not active right after it.
+        let points = starts
+            .keys()
+            .chain(ends.keys())
+            .chain(closes.keys())
+            .collect::<BTreeSet<_>>();
+        let mut active_ranges: BTreeSet<usize> = BTreeSet::new();
         let mut result: BTreeMap<OriginalAddress, AddressMapIndexRanges> = BTreeMap::new();
-        for (first_addr, (range, index)) in sorted_map {
-            let last_addr = range.keypoints.last().unwrap().0;
-            loop {
-                // Removing ranges we already passed.
-                let addr: OriginalAddress = *if let Some(addr) = active_ranges.keys().next() {
-                    addr
-                } else {
-                    break;
-                };
-                if addr >= first_addr {
-                    break;
+        for addr in points {
+            if let Some(closed) = closes.get(addr) {
+                for index in closed {
+                    active_ranges.remove(index);
                 }
-                result.insert(addr, active_ranges.remove(&addr).unwrap());
-            }
-            if !active_ranges.contains_key(&first_addr) {
-                let last = active_ranges.range(..first_addr).last();
-                let ranges = if let Some((_, ranges)) = last {
-                    ranges.clone()
-                } else {
-                    Vec::new()
-                };
-                active_ranges.insert(first_addr, ranges);
             }
-            if !active_ranges.contains_key(&last_addr) {
-                let last = active_ranges.range(..last_addr).last();
-                let ranges = if let Some((_, ranges)) = last {
-                    ranges.clone()
-                } else {
-                    Vec::new()
-                };
-                active_ranges.insert(first_addr, ranges);
+            if let Some(started) = starts.get(addr) {
+                active_ranges.extend(started);
             }
-            for (_, ranges) in active_ranges.range_mut(first_addr..=last_addr) {
-                ranges.push(index);
+            result.insert(*addr, active_ranges.iter().cloned().collect());
+            if let Some(ended) = ends.get(addr) {
+                for index in ended {
+                    active_ranges.remove(index);
+                }
             }
         }
-        for (addr, ranges) in active_ranges.into_iter() {
-            result.insert(addr, ranges);
-        }
         result
     }
 
-    fn from(map: AddressMap, mut function_ranges: Box<[(u64, u64)]>) -> AddressMapIndexed {
+    fn from(
+        map: AddressMap,
+        mut function_ranges: Box<[(u64, u64)]>,
+        mut original_function_ranges: Box<[(u64, u64)]>,
+    ) -> AddressMapIndexed {
         function_ranges.sort();
         let function_ranges = function_ranges
             .into_iter()
             .map(|(b, e)| TargetAddress(*b)..TargetAddress(*e))
             .collect::<Vec<_>>()
             .into_boxed_slice();
+        original_function_ranges.sort();
+        let original_function_ranges = Vec::from(original_function_ranges)
+            .into_iter()
+            .map(|(b, e)| OriginalAddress(b)..OriginalAddress(e))
+            .collect::<Vec<_>>()
+            .into_boxed_slice();
         let index = AddressMapIndexed::generate_index(&map);
+        let reverse_index = ReverseAddressMap::new(&map);
         AddressMapIndexed {
             map,
             index,
+            reverse_index,
             function_ranges,
+            original_function_ranges,
+        }
+    }
+
+    fn lookup_function_entry(&self, range: &TargetAddressRange) -> Option<OriginalAddress> {
+        // The first mapped instruction of the function, including its header
+        // (body size) that follows the previous function body.
+        let index = self
+            .function_ranges
+            .partition_point(|x| x.start < range.start);
+        let header_start = if index > 0 {
+            self.function_ranges[index - 1].end
+        } else {
+            TargetAddress(0)
+        };
+        self.reverse_index
+            .entries(header_start, range.end)
+            .first()
+            .map(|x| x.1)
+    }
+
+    fn lookup_original_function(&self, addr: OriginalAddress) -> Option<&OriginalAddressRange> {
+        // Functions are adjacent, so the function header belongs to the range too.
+        let i = self
+            .original_function_ranges
+            .partition_point(|f| f.end <= addr);
+        self.original_function_ranges.get(i)
+    }
+
+    fn is_same_function(
+        &self,
+        start: OriginalAddress,
+        end: OriginalAddress,
+        addr: OriginalAddress,
+    ) -> bool {
+        match self.lookup_original_function(start) {
+            Some(f) => self.lookup_original_function(addr) == Some(f),
+            None => start <= addr && addr < end,
         }
     }
 
@@ -269,6 +432,12 @@ impl AddressMapIndexed {
             }
             for range_index in ranges.unwrap().1 {
                 let range = &self.map.ranges[*range_index];
+                if range.identical {
+                    match self.lookup_function_range_by_target_address(range.lookup(*addr)) {
+                        Some(range) => return Some(range),
+                        None => continue,
+                    }
+                }
                 let pos = range.keypoints.binary_search_by(|x| x.0.cmp(&addr));
                 match pos {
                     Ok(i) => {
@@ -311,6 +480,208 @@ impl AddressMapIndexed {
         None
     }
 
+    fn lookup_function_ranges(
+        &self,
+        start: OriginalAddress,
+        end: OriginalAddress,
+    ) -> Vec<&TargetAddressRange> {
+        let mut result = Vec::new();
+        let addrs = if start == end {
+            vec![start]
+        } else {
+            vec![start, OriginalAddress(end.0 - 1)]
+        };
+        if let Some(range) = self.lookup_function_range(&addrs) {
+            // Skip the function if the code was just inlined into it.
+            match self.lookup_function_entry(range) {
+                Some(entry) if !self.is_same_function(start, end, entry) => (),
+                _ => result.push(range),
+            }
+        }
+        // The code might be split (or outlined) into several functions: adding
+        // all functions that start with the code from the original range. Other
+        // functions that contain the original code just have it inlined.
+        for target in self.lookup_range(start, end) {
+            let i = self
+                .function_ranges
+                .partition_point(|f| f.end <= target.start);
+            for f in self.function_ranges[i..]
+                .iter()
+                .take_while(|f| f.start < target.end)
+            {
+                match self.lookup_function_entry(f) {
+                    Some(entry) if self.is_same_function(start, end, entry) => result.push(f),
+                    _ => (),
+                }
+            }
+        }
+        result.sort_by_key(|f| f.start);
+        result.dedup();
+        result
+    }
+
+    fn lookup_inlined_functions(
+        &self,
+        start: OriginalAddress,
+        end: OriginalAddress,
+    ) -> Vec<InlinedRanges> {
+        let mut result: Vec<InlinedRanges> = Vec::new();
+        for f in self.lookup_function_ranges(start, end) {
+            let entries = self.reverse_index.entries(f.start, f.end);
+            // The last seen code of the function itself is the call site.
+            let mut call_site = None;
+            let mut pending: Vec<usize> = Vec::new();
+            for (i, (target, addr)) in entries.iter().enumerate() {
+                let next = entries.get(i + 1).map_or(f.end, |x| x.0);
+                if *target == next {
+                    continue;
+                }
+                if self.is_same_function(start, end, *addr) {
+                    call_site = Some(*addr);
+                    for j in pending.drain(..) {
+                        result[j].1 = call_site;
+                    }
+                    continue;
+                }
+                let origin = match self.lookup_original_function(*addr) {
+                    Some(origin) => origin.start,
+                    None => continue,
+                };
+                // Extending the last inlined range if it is the same function.
+                if let Some(last) = result.last_mut() {
+                    if last.0 == origin && last.1 == call_site {
+                        let last_range = last.2.last_mut().unwrap();
+                        if last_range.end == *target {
+                            last_range.end = next;
+                            continue;
+                        }
+                    }
+                }
+                if let Some(same) = result
+                    .iter_mut()
+                    .find(|r| r.0 == origin && r.1.is_some() && r.1 == call_site)
+                {
+                    same.2.push(*target..next);
+                    continue;
+                }
+                if call_site.is_none() {
+                    // The call site will be the next function code.
+                    pending.push(result.len());
+                }
+                result.push((origin, call_site, vec![*target..next]));
+            }
+        }
+        result
+    }
+
+    // Describes the steps of the address translation.
+    fn trace_address(&self, addr: OriginalAddress) -> Vec<String> {
+        let mut result = Vec::new();
+        match self.index.range(..=addr).last() {
+            Some((start, indices)) if !indices.is_empty() => {
+                for index in indices {
+                    let range = &self.map.ranges[*index];
+                    let first = range.keypoints.first().unwrap();
+                    let last = range.keypoints.last().unwrap();
+                    let keypoint = match range.keypoints.binary_search_by(|a| a.0.cmp(&addr)) {
+                        Ok(i) => format!("exact keypoint #{}", i),
+                        Err(i) if i < range.keypoints.len() => {
+                            format!("next keypoint #{} 0x{:x}", i, range.keypoints[i].0 .0)
+                        }
+                        Err(_) => "after the last keypoint".to_string(),
+                    };
+                    result.push(format!(
+                        "address map range #{} (indexed at 0x{:x}): 0x{:x}..=0x{:x} -> 0x{:x}..=0x{:x}{}, {} keypoints, {}",
+                        index,
+                        start.0,
+                        first.0 .0,
+                        last.0 .0,
+                        first.1 .0,
+                        range.last.0,
+                        if range.identical { " (identical)" } else { "" },
+                        range.keypoints.len(),
+                        keypoint
+                    ));
+                }
+            }
+            _ => result.push("address map range: none".to_string()),
+        }
+        let candidates = self
+            .lookup_address(addr)
+            .map(|t| format!("0x{:x}", t.0))
+            .collect::<Vec<_>>();
+        result.push(format!(
+            "lookup address candidates: [{}]",
+            candidates.join(", ")
+        ));
+        match self.lookup_address(addr).next() {
+            Some(t) => result.push(format!("translate_base_address: 0x{:x}", t.0)),
+            None => result.push("translate_base_address: none".to_string()),
+        }
+        match self.lookup_function_range(&[addr]) {
+            Some(range) => {
+                let entry = match self.lookup_function_entry(range) {
+                    Some(entry) => format!("0x{:x}", entry.0),
+                    None => "none".to_string(),
+                };
+                result.push(format!(
+                    "lookup_function_range: 0x{:x}..0x{:x}, its entry original address: {}",
+                    range.start.0, range.end.0, entry
+                ));
+            }
+            None => result.push("lookup_function_range: none".to_string()),
+        }
+        match self.lookup_original_function(addr) {
+            Some(f) if f.start <= addr => {
+                let ranges = self
+                    .lookup_function_ranges(f.start, f.end)
+                    .into_iter()
+                    .map(|r| format!("0x{:x}..0x{:x}", r.start.0, r.end.0))
+                    .collect::<Vec<_>>();
+                result.push(format!(
+                    "original function 0x{:x}..0x{:x}, translated function ranges: [{}]",
+                    f.start.0,
+                    f.end.0,
+                    ranges.join(", ")
+                ));
+            }
+            _ => result.push("original function: none".to_string()),
+        }
+        result
+    }
+
+    // Describes the target function, and returns the original addresses
+    // mapped to the target address (or the closest one before it).
+    fn trace_target(&self, addr: TargetAddress) -> (Vec<String>, Vec<OriginalAddress>) {
+        let mut result = Vec::new();
+        match self.lookup_function_range_by_target_address(addr) {
+            Some(range) => {
+                let entry = match self.lookup_function_entry(range) {
+                    Some(entry) => format!("0x{:x}", entry.0),
+                    None => "none".to_string(),
+                };
+                result.push(format!(
+                    "target function 0x{:x}..0x{:x}, its entry original address: {}",
+                    range.start.0, range.end.0, entry
+                ));
+            }
+            None => result.push("target function: none".to_string()),
+        }
+        let mut originals = self.reverse_index.find_exact(addr);
+        if originals.is_empty() {
+            if let Some(original) = self.reverse_index.find_original_address(addr) {
+                result.push(format!(
+                    "no exact keypoint, the closest original address: 0x{:x}",
+                    original.0
+                ));
+                originals.push(original);
+            }
+        }
+        originals.sort();
+        originals.dedup();
+        (result, originals)
+    }
+
     fn lookup_address(&self, addr: OriginalAddress) -> LookupAddressIterator {
         let ranges = self.index.range(..=addr).last();
         if ranges.is_none() {
@@ -364,7 +735,7 @@ fn calc_address_offset(addr1: Address, addr2: Address) -> u64 {
     }
 }
 
-fn compare_addresses(addr1: &Address, addr2: &Address) -> std::cmp::Ordering {
+pub(crate) fn compare_addresses(addr1: &Address, addr2: &Address) -> std::cmp::Ordering {
     match (addr1, addr2) {
         (Address::Constant(val1), Address::Constant(val2)) => val1.cmp(val2),
         (
@@ -386,42 +757,84 @@ pub trait AddressTranslator {
 
     fn translate_range(&self, start: u64, len: u64) -> Vec<(Address, u64)>;
 
-    fn translate_function_range(&self, start: u64, len: u64) -> Option<(Address, u64)>;
+    fn translate_function_ranges(&self, start: u64, len: u64) -> Vec<(Address, u64)>;
+
+    // Translates the range of the location list entry.
+    fn translate_location_range(&self, start: u64, len: u64) -> Vec<(Address, u64)> {
+        self.translate_range(start, len)
+    }
 
     fn translate_base_address(&self, addr: u64) -> Option<Address> {
         let addresses = self.translate_address(addr);
         addresses.into_iter().min_by(compare_addresses)
     }
 
-    fn translate_offset(&self, base: u64, offset: u64) -> Vec<u64> {
-        let translated_base = self.translate_base_address(base);
-        if translated_base.is_none() {
-            return vec![];
-        }
-        let addresses = self.translate_address(base + offset);
-        addresses
+    // Returns the offsets from the translated base address, and the translated
+    // addresses before the base, which cannot be expressed as the offsets.
+    fn translate_offset(&self, base: u64, offset: u64) -> (Vec<u64>, Vec<Address>) {
+        let translated_base = match self.translate_base_address(base) {
+            Some(translated_base) => translated_base,
+            None => return (vec![], vec![]),
+        };
+        let (addresses, before_base) = self
+            .translate_address(base + offset)
             .into_iter()
-            .filter(|a| {
-                match compare_addresses(translated_base.as_ref().unwrap(), &a) {
-                    std::cmp::Ordering::Greater => {
-                        eprintln!("TODO: translated_base.as_ref().unwrap() <= a: {:?} > {:?} (base: {}, offset: {})", translated_base.unwrap(), a, base, offset);
-                        false
-                    }
-                    _ => true,
-                }
-            })
-            .map(|a| calc_address_offset(translated_base.unwrap(), a))
-            .collect::<Vec<_>>()
+            .partition::<Vec<_>, _>(|a| {
+                compare_addresses(&translated_base, a) != std::cmp::Ordering::Greater
+            });
+        let offsets = addresses
+            .into_iter()
+            .map(|a| calc_address_offset(translated_base, a))
+            .collect::<Vec<_>>();
+        (offsets, before_base)
+    }
+
+    fn translate_inlined_functions(&self, _start: u64, _len: u64) -> Vec<InlinedFunction> {
+        vec![]
     }
 
     fn can_translate_address(&self, addr: u64) -> bool {
         self.translate_address(addr).len() > 0
     }
+
+    // Describes the translation of the address, for the diagnostics.
+    fn trace_address(&self, addr: u64) -> Vec<String> {
+        let format = |addresses: Vec<Address>| {
+            addresses
+                .iter()
+                .map(|a| format!("{:?}", a))
+                .collect::<Vec<_>>()
+                .join(", ")
+        };
+        vec![
+            format!(
+                "translate_address: [{}]",
+                format(self.translate_address(addr))
+            ),
+            format!(
+                "translate_base_address: {:?}",
+                self.translate_base_address(addr)
+            ),
+        ]
+    }
+
+    // Describes the target address, and returns the original addresses
+    // that are translated to it.
+    fn trace_target(&self, _addr: u64) -> (Vec<String>, Vec<u64>) {
+        (
+            vec!["the target address cannot be traced back".to_string()],
+            vec![],
+        )
+    }
 }
 
 pub struct IdentityAddressTranslator(pub bool);
 
 impl AddressTranslator for IdentityAddressTranslator {
+    fn trace_target(&self, addr: u64) -> (Vec<String>, Vec<u64>) {
+        (vec![], vec![addr])
+    }
+
     fn translate_address(&self, addr: u64) -> Vec<Address> {
         if addr == 0 && self.0 {
             return vec![];
@@ -436,11 +849,106 @@ impl AddressTranslator for IdentityAddressTranslator {
         vec![(Address::Constant(start), len)]
     }
 
-    fn translate_function_range(&self, start: u64, len: u64) -> Option<(Address, u64)> {
+    fn translate_function_ranges(&self, start: u64, len: u64) -> Vec<(Address, u64)> {
         if start == 0 && self.0 {
-            return None;
+            return vec![];
+        }
+        vec![(Address::Constant(start), len)]
+    }
+}
+
+// Translates the addresses with the function granularity, when only the
+// correspondence of the original and the target functions is known (e.g. by
+// their names): only the function starts and the whole functions ranges
+// can be translated.
+pub struct FunctionAddressTranslator {
+    functions: Box<[(OriginalAddressRange, TargetAddressRange)]>,
+}
+
+impl FunctionAddressTranslator {
+    pub fn new(functions: Vec<((u64, u64), (u64, u64))>) -> Self {
+        let mut functions = functions
+            .into_iter()
+            .map(|((ob, oe), (tb, te))| {
+                (
+                    OriginalAddress(ob)..OriginalAddress(oe),
+                    TargetAddress(tb)..TargetAddress(te),
+                )
+            })
+            .collect::<Vec<_>>();
+        functions.sort_by_key(|(o, _)| o.start);
+        FunctionAddressTranslator {
+            functions: functions.into_boxed_slice(),
+        }
+    }
+
+    fn lookup_function(
+        &self,
+        addr: OriginalAddress,
+    ) -> Option<&(OriginalAddressRange, TargetAddressRange)> {
+        let i = self.functions.partition_point(|(o, _)| o.end <= addr);
+        self.functions.get(i).filter(|(o, _)| o.start <= addr)
+    }
+}
+
+impl AddressTranslator for FunctionAddressTranslator {
+    fn translate_address(&self, addr: u64) -> Vec<Address> {
+        match self.lookup_function(OriginalAddress(addr)) {
+            Some((o, t)) if o.start.0 == addr => vec![from_target_address(t.start)],
+            _ => vec![],
+        }
+    }
+
+    // The code inside of the function cannot be translated: the range (e.g.
+    // of the lexical block) is widened to the whole functions it overlaps.
+    fn translate_range(&self, start: u64, len: u64) -> Vec<(Address, u64)> {
+        let (start, end) = (start, start + len);
+        let i = self.functions.partition_point(|(o, _)| o.end.0 <= start);
+        let mut result: Vec<TargetAddressRange> = Vec::new();
+        for (_, t) in self.functions[i..]
+            .iter()
+            .take_while(|(o, _)| o.start.0 < end)
+        {
+            match result.last_mut() {
+                Some(last) if last.end == t.start => last.end = t.end,
+                _ => result.push(t.clone()),
+            }
+        }
+        result.iter().map(to_addr_len).collect()
+    }
+
+    // The variable locations inside of the changed function are unknown.
+    fn translate_location_range(&self, _start: u64, _len: u64) -> Vec<(Address, u64)> {
+        vec![]
+    }
+
+    fn translate_function_ranges(&self, start: u64, _len: u64) -> Vec<(Address, u64)> {
+        match self.lookup_function(OriginalAddress(start)) {
+            Some((o, t)) if o.start.0 == start => vec![to_addr_len(t)],
+            _ => vec![],
+        }
+    }
+
+    fn trace_target(&self, addr: u64) -> (Vec<String>, Vec<u64>) {
+        let function = self
+            .functions
+            .iter()
+            .find(|(_, t)| t.start.0 <= addr && addr < t.end.0);
+        match function {
+            Some((o, t)) => (
+                vec![format!(
+                    "target function 0x{:x}..0x{:x}, original function 0x{:x}..0x{:x}",
+                    t.start.0, t.end.0, o.start.0, o.end.0
+                )],
+                // Only the function start is translated.
+                if t.start.0 == addr {
+                    vec![o.start.0]
+                } else {
+                    vec![]
+                },
+            ),
+            None => (vec!["target function: none".to_string()], vec![]),
         }
-        Some((Address::Constant(start), len))
     }
 }
 
@@ -449,8 +957,12 @@ pub struct TranformAddressTranslator {
 }
 
 impl TranformAddressTranslator {
-    pub fn new(map: AddressMap, function_ranges: Box<[(u64, u64)]>) -> Self {
-        let map = AddressMapIndexed::from(map, function_ranges);
+    pub fn new(
+        map: AddressMap,
+        function_ranges: Box<[(u64, u64)]>,
+        original_function_ranges: Box<[(u64, u64)]>,
+    ) -> Self {
+        let map = AddressMapIndexed::from(map, function_ranges, original_function_ranges);
         TranformAddressTranslator { map }
     }
 }
@@ -506,14 +1018,219 @@ impl AddressTranslator for TranformAddressTranslator {
         result
     }
 
-    fn translate_function_range(&self, start: u64, len: u64) -> Option<(Address, u64)> {
-        let addrs = if len == 0 {
-            vec![OriginalAddress(start)]
-        } else {
-            vec![OriginalAddress(start), OriginalAddress(start + len - 1)]
-        };
+    fn translate_function_ranges(&self, start: u64, len: u64) -> Vec<(Address, u64)> {
+        if start == 0 {
+            return vec![];
+        }
+        self.map
+            .lookup_function_ranges(OriginalAddress(start), OriginalAddress(start + len))
+            .into_iter()
+            .map(to_addr_len)
+            .collect()
+    }
+    fn translate_inlined_functions(&self, start: u64, len: u64) -> Vec<InlinedFunction> {
+        if start == 0 {
+            return vec![];
+        }
         self.map
-            .lookup_function_range(&addrs)
-            .map(|p| to_addr_len(p))
+            .lookup_inlined_functions(OriginalAddress(start), OriginalAddress(start + len))
+            .into_iter()
+            .map(|(origin, call_site, ranges)| InlinedFunction {
+                origin: origin.0,
+                call_site: call_site.map(|a| a.0),
+                ranges: ranges.iter().map(to_addr_len).collect(),
+            })
+            .collect()
+    }
+
+    fn trace_address(&self, addr: u64) -> Vec<String> {
+        self.map.trace_address(OriginalAddress(addr))
+    }
+
+    fn trace_target(&self, addr: u64) -> (Vec<String>, Vec<u64>) {
+        let (result, originals) = self.map.trace_target(TargetAddress(addr));
+        (result, originals.into_iter().map(|a| a.0).collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn constant(addr: &Address) -> u64 {
+        match addr {
+            Address::Constant(a) => *a,
+            _ => panic!("constant address expected"),
+        }
+    }
+
+    fn ranges(result: Vec<(Address, u64)>) -> Vec<(u64, u64)> {
+        result.iter().map(|(a, len)| (constant(a), *len)).collect()
+    }
+
+    #[test]
+    fn test_function_translate_range() {
+        let at = FunctionAddressTranslator::new(vec![
+            ((10, 20), (100, 110)),
+            ((20, 30), (110, 115)),
+            ((40, 50), (200, 210)),
+        ]);
+        // The whole functions, and the adjacent ones are merged.
+        assert_eq!(ranges(at.translate_range(10, 20)), vec![(100, 15)]);
+        // The part of the function is widened to the whole function.
+        assert_eq!(ranges(at.translate_range(12, 4)), vec![(100, 10)]);
+        assert_eq!(ranges(at.translate_range(5, 10)), vec![(100, 10)]);
+        assert_eq!(ranges(at.translate_range(15, 14)), vec![(100, 15)]);
+        assert_eq!(
+            ranges(at.translate_range(25, 20)),
+            vec![(110, 5), (200, 10)]
+        );
+        // The gap between the functions is not translated.
+        assert_eq!(ranges(at.translate_range(30, 10)), vec![]);
+        // Only the function starts and ranges are translated exactly.
+        assert_eq!(ranges(at.translate_function_ranges(20, 10)), vec![(110, 5)]);
+        assert_eq!(ranges(at.translate_function_ranges(22, 4)), vec![]);
+        assert_eq!(at.translate_address(22), vec![]);
+        assert_eq!(ranges(at.translate_location_range(12, 4)), vec![]);
+    }
+
+    #[test]
+    fn test_identical_range() {
+        let mut map = AddressMap::new();
+        map.insert_identical_range(TargetAddress(100), OriginalAddress(10), 20);
+        map.insert(TargetAddress(200), OriginalAddress(50));
+        map.insert(TargetAddress(204), OriginalAddress(52));
+        map.insert(TargetAddress(208), OriginalAddress(58));
+        assert_eq!(map.ranges[0].keypoints.len(), 2);
+        assert_eq!(
+            map.pairs(),
+            vec![
+                (OriginalAddress(10), TargetAddress(100)),
+                (OriginalAddress(50), TargetAddress(200)),
+                (OriginalAddress(52), TargetAddress(204)),
+                (OriginalAddress(58), TargetAddress(208)),
+            ]
+        );
+
+        let reverse = ReverseAddressMap::new(&map);
+        assert_eq!(
+            reverse.find_original_address(TargetAddress(115)),
+            Some(OriginalAddress(25))
+        );
+        assert_eq!(
+            reverse.find_original_address(TargetAddress(120)),
+            Some(OriginalAddress(30))
+        );
+        assert_eq!(reverse.find_original_address(TargetAddress(150)), None);
+        assert_eq!(
+            reverse.find_original_address(TargetAddress(206)),
+            Some(OriginalAddress(52))
+        );
+        assert_eq!(reverse.find_original_address(TargetAddress(50)), None);
+
+        let indexed = AddressMapIndexed::from(map, Box::new([]), Box::new([]));
+        let lookup = |addr| {
+            indexed
+                .lookup_address(OriginalAddress(addr))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(lookup(10), vec![TargetAddress(100)]);
+        assert_eq!(lookup(17), vec![TargetAddress(107)]);
+        assert_eq!(lookup(30), vec![TargetAddress(120)]);
+        assert_eq!(lookup(31), vec![]);
+        assert_eq!(
+            indexed
+                .lookup_range(OriginalAddress(12), OriginalAddress(18))
+                .collect::<Vec<_>>(),
+            vec![TargetAddress(102)..TargetAddress(108)]
+        );
+    }
+
+    fn sample_map() -> AddressMap {
+        let mut map = AddressMap::new();
+        map.insert(TargetAddress(100), OriginalAddress(10));
+        map.insert(TargetAddress(104), OriginalAddress(12));
+        map.insert(TargetAddress(108), OriginalAddress(20));
+        // The address goes back: the new range is started.
+        map.insert(TargetAddress(200), OriginalAddress(5));
+        map.insert(TargetAddress(204), OriginalAddress(8));
+        map.insert_identical_range(TargetAddress(300), OriginalAddress(30), 4);
+        map
+    }
+
+    #[test]
+    fn test_generate_index() {
+        let index = AddressMapIndexed::generate_index(&sample_map());
+        assert_eq!(
+            index.into_iter().collect::<Vec<_>>(),
+            vec![
+                (OriginalAddress(5), vec![1]),
+                (OriginalAddress(8), vec![1]),
+                (OriginalAddress(10), vec![0]),
+                (OriginalAddress(20), vec![0]),
+                (OriginalAddress(30), vec![2]),
+                // The identical range is closed right after its end.
+                (OriginalAddress(35), vec![]),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_reverse_index() {
+        let reverse = ReverseAddressMap::new(&sample_map());
+        assert_eq!(
+            reverse.find_original_address(TargetAddress(106)),
+            Some(OriginalAddress(12))
+        );
+        assert_eq!(
+            reverse.find_original_address(TargetAddress(200)),
+            Some(OriginalAddress(5))
+        );
+        assert_eq!(
+            reverse.find_original_address(TargetAddress(302)),
+            Some(OriginalAddress(32))
+        );
+        assert_eq!(reverse.find_original_address(TargetAddress(99)), None);
+        assert_eq!(
+            reverse.find_exact(TargetAddress(204)),
+            vec![OriginalAddress(8)]
+        );
+        assert_eq!(reverse.find_exact(TargetAddress(206)), vec![]);
+        assert_eq!(
+            reverse.entries(TargetAddress(104), TargetAddress(204)),
+            vec![
+                (TargetAddress(104), OriginalAddress(12)),
+                (TargetAddress(108), OriginalAddress(20)),
+                (TargetAddress(200), OriginalAddress(5)),
+            ]
+        );
+        // The identical range is listed by the range start inside of it.
+        assert_eq!(
+            reverse.entries(TargetAddress(302), TargetAddress(310)),
+            vec![(TargetAddress(302), OriginalAddress(32))]
+        );
+    }
+
+    #[test]
+    fn test_translate_function_ranges() {
+        // The functions are copied as is, but in the reverse order.
+        let mut map = AddressMap::new();
+        map.insert_identical_range(TargetAddress(0x110), OriginalAddress(0x10), 0x10);
+        map.insert_identical_range(TargetAddress(0x100), OriginalAddress(0x20), 0x10);
+        let at = TranformAddressTranslator::new(
+            map,
+            Box::new([(0x100, 0x110), (0x110, 0x120)]),
+            Box::new([(0x10, 0x20), (0x20, 0x30)]),
+        );
+        assert_eq!(
+            ranges(at.translate_function_ranges(0x10, 0x10)),
+            vec![(0x110, 0x10)]
+        );
+        assert_eq!(
+            ranges(at.translate_function_ranges(0x20, 0x10)),
+            vec![(0x100, 0x10)]
+        );
+        assert_eq!(ranges(at.translate_function_ranges(0x40, 0x10)), vec![]);
+        assert_eq!(ranges(at.translate_function_ranges(0, 0x10)), vec![]);
     }
 }
@@ -1,4 +1,10 @@
-use crate::address_translator::AddressTranslator;
+use crate::address_translator::{compare_addresses, AddressTranslator};
+use crate::diagnostics::{DiagnosticCode, Diagnostics};
+use crate::gc::collect_referenced_entries;
+use crate::glob::glob_match;
+use crate::odr::collect_odr_duplicates;
+use crate::stats::Statistics;
+use crate::symbolize::is_tombstone;
 use gimli::constants;
 use gimli::read;
 use gimli::write::{
@@ -6,12 +12,129 @@ use gimli::write::{
     LineProgram, LineString, LineStringTable, Location, LocationList, Range, RangeList,
     StringTable, Unit, UnitEntryId, UnitId, UnitTable,
 };
-use gimli::{DebugLineOffset, DwTag, Reader, UnitSectionOffset};
-use std::collections::HashMap;
+use gimli::{DebugLineOffset, DwTag, Encoding, Reader, UnitSectionOffset};
+use std::borrow::Cow;
+use std::collections::{HashMap, HashSet};
 use std::vec::Vec;
 
 // Getting logic from gimli's src/write/{unit,range,line}.rs files.
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum DebugInfoLevel {
+    #[default]
+    Full,
+    // Drops the variables, parameters and their locations.
+    NoVariables,
+    // Keeps only the units and the functions (including inlined ones).
+    NamesOnly,
+    // Keeps only the units and their line programs.
+    LineTablesOnly,
+}
+
+impl DebugInfoLevel {
+    fn keeps_tag(self, tag: DwTag) -> bool {
+        match self {
+            DebugInfoLevel::Full => true,
+            DebugInfoLevel::NoVariables => !matches!(
+                tag,
+                constants::DW_TAG_variable
+                    | constants::DW_TAG_formal_parameter
+                    | constants::DW_TAG_constant
+            ),
+            DebugInfoLevel::NamesOnly => matches!(
+                tag,
+                constants::DW_TAG_compile_unit
+                    | constants::DW_TAG_subprogram
+                    | constants::DW_TAG_inlined_subroutine
+            ),
+            DebugInfoLevel::LineTablesOnly => tag == constants::DW_TAG_compile_unit,
+        }
+    }
+
+    fn keeps_locations(self) -> bool {
+        self == DebugInfoLevel::Full
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct ConvertOptions {
+    // Merge the identical named types of the different units.
+    pub odr: bool,
+    pub level: DebugInfoLevel,
+    // The units matching (by DW_AT_name or DW_AT_comp_dir) the include
+    // globs, and not matching the exclude ones, are converted.
+    pub include_units: Vec<String>,
+    pub exclude_units: Vec<String>,
+    // The (old, new) path prefix replacements. The last matching one is used.
+    pub prefix_map: Vec<(String, String)>,
+    // Overrides the DWARF version of the units and line programs, e.g. the
+    // source files can be embedded only in the version 5 line programs.
+    pub version: Option<u16>,
+}
+
+// Returns the rest of the path if it starts with the prefix. The prefix
+// matches only whole path components, e.g. "/src" does not match "/srcfoo".
+pub fn strip_path_prefix<'a>(path: &'a [u8], prefix: &[u8]) -> Option<&'a [u8]> {
+    if !path.starts_with(prefix) {
+        return None;
+    }
+    let rest = &path[prefix.len()..];
+    let is_separator = |c: Option<&u8>| c == Some(&b'/') || c == Some(&b'\\');
+    if prefix.is_empty()
+        || is_separator(prefix.last())
+        || rest.is_empty()
+        || is_separator(rest.first())
+    {
+        Some(rest)
+    } else {
+        None
+    }
+}
+
+fn remap_path<'a>(path: &'a [u8], prefix_map: &[(String, String)]) -> Cow<'a, [u8]> {
+    for (old, new) in prefix_map.iter().rev() {
+        if let Some(rest) = strip_path_prefix(path, old.as_bytes()) {
+            let mut result = new.as_bytes().to_vec();
+            result.extend_from_slice(rest);
+            return Cow::Owned(result);
+        }
+    }
+    Cow::Borrowed(path)
+}
+
+fn is_unit_excluded<R: Reader<Offset = usize>>(
+    unit: &read::Unit<R>,
+    options: &ConvertOptions,
+) -> read::Result<bool> {
+    if options.include_units.is_empty() && options.exclude_units.is_empty() {
+        return Ok(false);
+    }
+    let mut paths = Vec::new();
+    if let Some(ref name) = unit.name {
+        paths.push(name.to_string_lossy()?.into_owned());
+    }
+    if let Some(ref comp_dir) = unit.comp_dir {
+        paths.push(comp_dir.to_string_lossy()?.into_owned());
+    }
+    let matches = |patterns: &[String]| {
+        patterns
+            .iter()
+            .any(|pattern| paths.iter().any(|path| glob_match(pattern, path)))
+    };
+    Ok(
+        (!options.include_units.is_empty() && !matches(&options.include_units))
+            || matches(&options.exclude_units),
+    )
+}
+
+// The statistics of the original and the converted debug info, and the
+// problems found during the conversion.
+#[derive(Debug, Clone, Default)]
+pub struct ConvertReport {
+    pub stats: Statistics,
+    pub diagnostics: Diagnostics,
+}
+
 pub fn from_dwarf<
     R: Reader<Offset = usize>,
     A: AddressTranslator,
@@ -20,10 +143,20 @@ pub fn from_dwarf<
     dwarf: &read::Dwarf<R>,
     at: &A,
     die_filter: &F,
+    options: &ConvertOptions,
+    report: &mut ConvertReport,
 ) -> ConvertResult<Dwarf> {
     let mut line_strings = LineStringTable::default();
     let mut strings = StringTable::default();
-    let units = from_unit_table(dwarf, &mut line_strings, &mut strings, at, die_filter)?;
+    let units = from_unit_table(
+        dwarf,
+        &mut line_strings,
+        &mut strings,
+        at,
+        die_filter,
+        options,
+        report,
+    )?;
     // TODO: convert the line programs that were not referenced by a unit.
     let line_programs = Vec::new();
     Ok(Dwarf {
@@ -34,7 +167,24 @@ pub fn from_dwarf<
     })
 }
 
-pub fn from_unit_table<
+// The state shared by the conversion of all units.
+struct ConvertTableContext<
+    'a,
+    R: Reader<Offset = usize>,
+    A: AddressTranslator,
+    F: Fn(UnitSectionOffset) -> bool,
+> {
+    pub dwarf: &'a read::Dwarf<R>,
+    pub line_strings: &'a mut LineStringTable,
+    pub strings: &'a mut StringTable,
+    pub at: &'a A,
+    pub die_filter: &'a F,
+    pub subprograms: &'a HashMap<u64, UnitSectionOffset>,
+    pub options: &'a ConvertOptions,
+    pub report: &'a mut ConvertReport,
+}
+
+fn from_unit_table<
     R: Reader<Offset = usize>,
     A: AddressTranslator,
     F: Fn(UnitSectionOffset) -> bool,
@@ -44,22 +194,65 @@ pub fn from_unit_table<
     strings: &mut StringTable,
     at: &A,
     die_filter: &F,
+    options: &ConvertOptions,
+    report: &mut ConvertReport,
 ) -> ConvertResult<UnitTable> {
     let mut units = UnitTable::default();
     let mut unit_entry_offsets = HashMap::new();
+    let subprograms = collect_subprograms(dwarf)?;
 
+    let mut excluded_units = HashSet::new();
+    let mut from_units = dwarf.units();
+    while let Some(from_unit) = from_units.next()? {
+        let from_unit = dwarf.unit(from_unit)?;
+        count_entries(dwarf, &from_unit, &mut report.stats)?;
+        if is_unit_excluded(&from_unit, options)? {
+            excluded_units.insert(from_unit.offset);
+        }
+    }
+    // Only the entries referenced from the included units are left
+    // in the excluded units.
+    let referenced = if excluded_units.is_empty() {
+        HashSet::new()
+    } else {
+        collect_referenced_entries(dwarf, &excluded_units, die_filter)?
+    };
+    let die_filter =
+        &|offset| die_filter(offset) && (excluded_units.is_empty() || referenced.contains(&offset));
+
+    // The duplicate types are not converted, and the references to them
+    // are redirected to the canonical types.
+    let duplicates = if options.odr {
+        collect_odr_duplicates(dwarf, die_filter)?
+    } else {
+        HashMap::new()
+    };
+    let die_filter = &|offset| die_filter(offset) && !duplicates.contains_key(&offset);
+
+    let mut context = ConvertTableContext {
+        dwarf,
+        line_strings,
+        strings,
+        at,
+        die_filter,
+        subprograms: &subprograms,
+        options,
+        report,
+    };
     let mut from_units = dwarf.units();
     let mut converted = Vec::new();
     while let Some(from_unit) = from_units.next()? {
+        let from_unit = dwarf.unit(from_unit)?;
+        let excluded = excluded_units.contains(&from_unit.offset);
+        if excluded && !die_filter(unit_root_offset(&from_unit)?) {
+            continue;
+        }
         converted.push(from_unit_entry(
+            &mut context,
             from_unit,
+            excluded,
             &mut units,
             &mut unit_entry_offsets,
-            dwarf,
-            line_strings,
-            strings,
-            at,
-            die_filter,
         )?);
     }
 
@@ -68,11 +261,18 @@ pub fn from_unit_table<
         let unit = units.get_mut(unit_id);
         for entry_id in entries {
             let entry = unit.get_mut(entry_id);
+            let mut dropped_refs = Vec::new();
             for attr in &mut entry.attrs_mut() {
                 let id = match attr.get() {
                     AttributeValue::UnitSectionRef(ref offset) => {
+                        let offset = duplicates.get(offset).unwrap_or(offset);
                         match unit_entry_offsets.get(offset) {
-                            Some(id) => Some(*id),
+                            Some(Some(id)) => Some(*id),
+                            // The entry was removed due to the debug info level.
+                            Some(None) => {
+                                dropped_refs.push(attr.name());
+                                None
+                            }
                             None => return Err(ConvertError::InvalidDebugInfoOffset),
                         }
                     }
@@ -86,6 +286,9 @@ pub fn from_unit_table<
                     }
                 }
             }
+            for name in dropped_refs {
+                entry.delete(name);
+            }
         }
     }
 
@@ -106,7 +309,28 @@ struct ConvertUnitContext<
     pub die_filter: &'a F,
     pub base_address: u64,
     pub line_program_offset: Option<DebugLineOffset>,
-    pub line_program_files: Vec<FileId>,
+    // Indexed by the file index of the line program, `None` for the
+    // indices without a file.
+    pub line_program_files: Vec<Option<FileId>>,
+    pub line_rows: Option<Vec<(u64, Option<u64>, u64)>>,
+    pub subprograms: &'a HashMap<u64, UnitSectionOffset>,
+    pub level: DebugInfoLevel,
+    // The unit is excluded, but has the entries referenced from other units:
+    // the code and the line information are removed.
+    pub excluded: bool,
+    pub prefix_map: &'a [(String, String)],
+    pub report: &'a mut ConvertReport,
+}
+
+fn unit_encoding<R: Reader<Offset = usize>>(
+    unit: &read::Unit<R>,
+    options: &ConvertOptions,
+) -> Encoding {
+    let mut encoding = unit.encoding();
+    if let Some(version) = options.version {
+        encoding.version = version;
+    }
+    encoding
 }
 
 fn from_unit_entry<
@@ -114,28 +338,38 @@ fn from_unit_entry<
     A: AddressTranslator,
     F: Fn(UnitSectionOffset) -> bool,
 >(
-    from_header: read::CompilationUnitHeader<R>,
+    table: &mut ConvertTableContext<R, A, F>,
+    from_unit: read::Unit<R>,
+    excluded: bool,
     units: &mut UnitTable,
-    unit_entry_offsets: &mut HashMap<UnitSectionOffset, (UnitId, UnitEntryId)>,
-    dwarf: &read::Dwarf<R>,
-    line_strings: &mut LineStringTable,
-    strings: &mut StringTable,
-    at: &A,
-    die_filter: &F,
+    unit_entry_offsets: &mut HashMap<UnitSectionOffset, Option<(UnitId, UnitEntryId)>>,
 ) -> ConvertResult<(UnitId, Vec<UnitEntryId>)> {
-    let from_unit = dwarf.unit(from_header)?;
-    let encoding = from_unit.encoding();
+    let encoding = unit_encoding(&from_unit, table.options);
     let base_address = from_unit.low_pc;
 
     let (line_program_offset, line_program, line_program_files) = match from_unit.line_program {
-        Some(ref from_program) => {
+        Some(ref from_program) if !excluded => {
             let from_program = from_program.clone();
             let line_program_offset = from_program.header().offset();
-            let (line_program, line_program_files) =
-                from_line_program(from_program, dwarf, line_strings, strings, at)?;
+            let (line_program, line_program_files, line_stats, line_diagnostics) =
+                from_line_program(
+                    from_program,
+                    table.dwarf,
+                    table.line_strings,
+                    table.strings,
+                    table.at,
+                    &from_unit,
+                    table.options,
+                )?;
+            table.report.stats.add_line_stats(&line_stats);
+            table
+                .report
+                .diagnostics
+                .items
+                .extend(line_diagnostics.items);
             (Some(line_program_offset), line_program, line_program_files)
         }
-        None => (None, LineProgram::none(), Vec::new()),
+        _ => (None, LineProgram::none(), Vec::new()),
     };
 
     let unit = Unit::new(encoding, line_program);
@@ -144,21 +378,27 @@ fn from_unit_entry<
     let mut entries = Vec::new();
 
     let mut context = ConvertUnitContext {
-        dwarf,
+        dwarf: table.dwarf,
         unit: &from_unit,
-        line_strings,
-        strings,
-        at,
-        die_filter,
+        line_strings: table.line_strings,
+        strings: table.strings,
+        at: table.at,
+        die_filter: table.die_filter,
         base_address,
         line_program_offset,
         line_program_files,
+        line_rows: None,
+        subprograms: table.subprograms,
+        level: table.options.level,
+        excluded,
+        prefix_map: &table.options.prefix_map,
+        report: table.report,
     };
     let mut from_tree = from_unit.entries_tree(None)?;
     let from_root = from_tree.root()?;
     let root_id = unit.root();
 
-    if die_filter(
+    if (table.die_filter)(
         from_root
             .entry()
             .offset()
@@ -179,6 +419,47 @@ fn from_unit_entry<
     Ok((unit_id, entries))
 }
 
+fn has_code(ranges: &[(u64, u64)]) -> bool {
+    ranges
+        .iter()
+        .any(|(start, len)| *len > 0 && !is_tombstone(*start))
+}
+
+// Counts the original entries for the statistics.
+fn count_entries<R: Reader<Offset = usize>>(
+    dwarf: &read::Dwarf<R>,
+    unit: &read::Unit<R>,
+    stats: &mut Statistics,
+) -> read::Result<()> {
+    let mut entries = unit.entries();
+    while let Some((_, entry)) = entries.next_dfs()? {
+        stats.dies += 1;
+        match entry.tag() {
+            constants::DW_TAG_subprogram => {
+                stats.subprograms += 1;
+                if let Some(ranges) = read_code_ranges(entry, dwarf, unit)? {
+                    if has_code(&ranges) {
+                        stats.functions += 1;
+                    }
+                }
+            }
+            constants::DW_TAG_variable | constants::DW_TAG_formal_parameter => {
+                stats.variables += 1;
+            }
+            _ => (),
+        }
+    }
+    Ok(())
+}
+
+fn unit_root_offset<R: Reader<Offset = usize>>(
+    unit: &read::Unit<R>,
+) -> read::Result<UnitSectionOffset> {
+    let mut tree = unit.entries_tree(None)?;
+    let root = tree.root()?;
+    Ok(root.entry().offset().to_unit_section_offset(unit))
+}
+
 fn get_tag<R: Reader<Offset = usize>>(from: &read::EntriesTreeNode<R>) -> DwTag {
     let from = from.entry();
     from.tag()
@@ -191,13 +472,22 @@ fn from_die<R: Reader<Offset = usize>, A: AddressTranslator, F: Fn(UnitSectionOf
     unit_id: UnitId,
     entry_id: UnitEntryId,
     entries: &mut Vec<UnitEntryId>,
-    unit_entry_offsets: &mut HashMap<UnitSectionOffset, (UnitId, UnitEntryId)>,
+    unit_entry_offsets: &mut HashMap<UnitSectionOffset, Option<(UnitId, UnitEntryId)>>,
 ) -> ConvertResult<()> {
     {
         let from = from.entry();
 
         let offset = from.offset().to_unit_section_offset(context.unit);
-        unit_entry_offsets.insert(offset, (unit_id, entry_id));
+        unit_entry_offsets.insert(offset, Some((unit_id, entry_id)));
+
+        context.report.stats.dies_kept += 1;
+        match unit.get(entry_id).tag() {
+            constants::DW_TAG_subprogram => context.report.stats.subprograms_kept += 1,
+            constants::DW_TAG_variable | constants::DW_TAG_formal_parameter => {
+                context.report.stats.variables_kept += 1
+            }
+            _ => (),
+        }
 
         let mut from_attrs = from.attrs();
         let is_function_die = match unit.get(entry_id).tag() {
@@ -215,36 +505,98 @@ fn from_die<R: Reader<Offset = usize>, A: AddressTranslator, F: Fn(UnitSectionOf
                 {
                     ()
                 }
+                constants::DW_AT_location | constants::DW_AT_frame_base
+                    if !context.level.keeps_locations() => {}
+                constants::DW_AT_low_pc
+                | constants::DW_AT_high_pc
+                | constants::DW_AT_ranges
+                | constants::DW_AT_stmt_list
+                | constants::DW_AT_decl_file
+                | constants::DW_AT_decl_line
+                | constants::DW_AT_decl_column
+                | constants::DW_AT_call_file
+                | constants::DW_AT_call_line
+                | constants::DW_AT_call_column
+                    if context.excluded => {}
                 _ => {
                     from_entry_attr(context, &from_attr, unit, entry_id)?;
                 }
             }
         }
-        if is_function_die {
-            if let Some(read::AttributeValue::Addr(low_pc)) =
-                from.attr_value(constants::DW_AT_low_pc)?
-            {
-                if let Some((addr, len)) = context.at.translate_function_range(low_pc, 0) {
-                    let entry = unit.get_mut(entry_id);
-                    entry.set(constants::DW_AT_low_pc, AttributeValue::Address(addr));
-                    entry.set(constants::DW_AT_high_pc, AttributeValue::Udata(len));
-                } else {
-                    return Err(ConvertError::InvalidAddress);
+        if is_function_die && !context.excluded {
+            if let Some(ranges) = read_code_ranges(from, context.dwarf, context.unit)? {
+                let mut translated = Vec::new();
+                for (start, len) in ranges.iter() {
+                    let function_ranges = context.at.translate_function_ranges(*start, *len);
+                    context
+                        .report
+                        .stats
+                        .add_range(*start, function_ranges.len());
+                    translated.extend(function_ranges);
+                }
+                if has_code(&ranges) && translated.iter().any(|(_, len)| *len > 0) {
+                    context.report.stats.functions_kept += 1;
+                }
+                set_code_ranges(unit, entry_id, translated);
+                for (start, len) in ranges {
+                    from_inlined_functions(context, start, len, unit, entry_id, entries)?;
                 }
             } else {
-                println!("TODO: low_pc not found, inlined?");
+                context.report.diagnostics.add(
+                    DiagnosticCode::NoCodeRanges,
+                    "the subprogram has no code ranges, inlined?".to_string(),
+                    Some(offset),
+                    None,
+                );
             }
         }
     }
 
+    from_die_children(
+        context,
+        from,
+        unit,
+        unit_id,
+        entry_id,
+        entries,
+        unit_entry_offsets,
+    )
+}
+
+fn from_die_children<
+    R: Reader<Offset = usize>,
+    A: AddressTranslator,
+    F: Fn(UnitSectionOffset) -> bool,
+>(
+    context: &mut ConvertUnitContext<R, A, F>,
+    from: read::EntriesTreeNode<R>,
+    unit: &mut Unit,
+    unit_id: UnitId,
+    entry_id: UnitEntryId,
+    entries: &mut Vec<UnitEntryId>,
+    unit_entry_offsets: &mut HashMap<UnitSectionOffset, Option<(UnitId, UnitEntryId)>>,
+) -> ConvertResult<()> {
     let mut from_children = from.children();
     while let Some(from_child) = from_children.next()? {
-        if !(context.die_filter)(
-            from_child
-                .entry()
-                .offset()
-                .to_unit_section_offset(context.unit),
-        ) {
+        let child_offset = from_child
+            .entry()
+            .offset()
+            .to_unit_section_offset(context.unit);
+        if !(context.die_filter)(child_offset) {
+            continue;
+        }
+        if !context.level.keeps_tag(get_tag(&from_child)) {
+            // Moving the kept descendants to the parent of the removed entry.
+            unit_entry_offsets.insert(child_offset, None);
+            from_die_children(
+                context,
+                from_child,
+                unit,
+                unit_id,
+                entry_id,
+                entries,
+                unit_entry_offsets,
+            )?;
             continue;
         }
         let child_id = unit.add(entry_id, get_tag(&from_child));
@@ -262,6 +614,113 @@ fn from_die<R: Reader<Offset = usize>, A: AddressTranslator, F: Fn(UnitSectionOf
     Ok(())
 }
 
+fn set_code_ranges(unit: &mut Unit, entry_id: UnitEntryId, mut ranges: Vec<(Address, u64)>) {
+    ranges.retain(|(_, len)| *len > 0);
+    ranges.sort_by(|a, b| compare_addresses(&a.0, &b.0));
+    ranges.dedup();
+    match ranges.len() {
+        // No code survived -- leaving the entry without code range.
+        0 => (),
+        1 => {
+            let (addr, len) = ranges[0];
+            let entry = unit.get_mut(entry_id);
+            entry.set(constants::DW_AT_low_pc, AttributeValue::Address(addr));
+            entry.set(constants::DW_AT_high_pc, AttributeValue::Udata(len));
+        }
+        _ => {
+            let range_list = RangeList(
+                ranges
+                    .into_iter()
+                    .map(|(begin, length)| Range::StartLength { begin, length })
+                    .collect(),
+            );
+            let range_id = unit.ranges.add(range_list);
+            unit.get_mut(entry_id).set(
+                constants::DW_AT_ranges,
+                AttributeValue::RangeListRef(range_id),
+            );
+        }
+    }
+}
+
+fn from_inlined_functions<
+    R: Reader<Offset = usize>,
+    A: AddressTranslator,
+    F: Fn(UnitSectionOffset) -> bool,
+>(
+    context: &mut ConvertUnitContext<R, A, F>,
+    start: u64,
+    len: u64,
+    unit: &mut Unit,
+    entry_id: UnitEntryId,
+    entries: &mut Vec<UnitEntryId>,
+) -> ConvertResult<()> {
+    for inlined in context.at.translate_inlined_functions(start, len) {
+        let origin = match context.subprograms.get(&inlined.origin) {
+            Some(origin) if (context.die_filter)(*origin) => *origin,
+            _ => continue,
+        };
+        let child_id = unit.add(entry_id, constants::DW_TAG_inlined_subroutine);
+        entries.push(child_id);
+        unit.get_mut(child_id).set(
+            constants::DW_AT_abstract_origin,
+            AttributeValue::UnitSectionRef(origin),
+        );
+        if let Some(call_site) = inlined.call_site {
+            if let Some((file, line)) = lookup_line(context, call_site)? {
+                let child = unit.get_mut(child_id);
+                child.set(
+                    constants::DW_AT_call_file,
+                    AttributeValue::FileIndex(Some(file)),
+                );
+                child.set(constants::DW_AT_call_line, AttributeValue::Udata(line));
+            }
+        }
+        set_code_ranges(unit, child_id, inlined.ranges);
+    }
+    Ok(())
+}
+
+fn lookup_line<
+    R: Reader<Offset = usize>,
+    A: AddressTranslator,
+    F: Fn(UnitSectionOffset) -> bool,
+>(
+    context: &mut ConvertUnitContext<R, A, F>,
+    addr: u64,
+) -> ConvertResult<Option<(FileId, u64)>> {
+    if context.line_rows.is_none() {
+        let mut line_rows = Vec::new();
+        if let Some(ref program) = context.unit.line_program {
+            let mut rows = program.clone().rows();
+            while let Some((_, row)) = rows.next_row()? {
+                // No file marks the end of sequence.
+                let file = if row.end_sequence() {
+                    None
+                } else {
+                    Some(row.file_index())
+                };
+                line_rows.push((row.address(), file, row.line().unwrap_or(0)));
+            }
+        }
+        line_rows.sort_by_key(|r| r.0);
+        context.line_rows = Some(line_rows);
+    }
+    let line_rows = context.line_rows.as_ref().unwrap();
+    let i = line_rows.partition_point(|r| r.0 <= addr);
+    if i == 0 {
+        return Ok(None);
+    }
+    let (file, line) = match line_rows[i - 1] {
+        (_, Some(file), line) if line != 0 => (file, line),
+        _ => return Ok(None),
+    };
+    Ok(context
+        .line_program_files
+        .get(file as usize)
+        .and_then(|id| id.map(|id| (id, line))))
+}
+
 fn from_entry_attr<
     R: Reader<Offset = usize>,
     A: AddressTranslator,
@@ -272,7 +731,13 @@ fn from_entry_attr<
     unit: &mut Unit,
     entry_id: UnitEntryId,
 ) -> ConvertResult<()> {
-    if let Some(value) = from_attr_value(context, unit, from.value())? {
+    // Only the unit's name and directory contain paths.
+    let is_path = match from.name() {
+        constants::DW_AT_comp_dir => true,
+        constants::DW_AT_name => unit.get(entry_id).tag() == constants::DW_TAG_compile_unit,
+        _ => false,
+    };
+    if let Some(value) = from_attr_value(context, unit, from.value(), is_path)? {
         unit.get_mut(entry_id).set(from.name(), value);
     }
     Ok(())
@@ -286,7 +751,9 @@ fn from_attr_value<
     context: &mut ConvertUnitContext<R, A, F>,
     unit: &mut Unit,
     from: read::AttributeValue<R>,
+    is_path: bool,
 ) -> ConvertResult<Option<AttributeValue>> {
+    let prefix_map = if is_path { context.prefix_map } else { &[] };
     let to = match from {
         read::AttributeValue::Addr(val) => match context.at.translate_base_address(val) {
             Some(val) => AttributeValue::Address(val),
@@ -385,7 +852,8 @@ fn from_attr_value<
         read::AttributeValue::DebugTypesRef(val) => AttributeValue::DebugTypesRef(val),
         read::AttributeValue::DebugStrRef(offset) => {
             let r = context.dwarf.string(offset)?;
-            let id = context.strings.add(r.to_slice()?);
+            let r = r.to_slice()?;
+            let id = context.strings.add(remap_path(&r, prefix_map));
             AttributeValue::StringRef(id)
         }
         read::AttributeValue::DebugStrRefSup(val) => AttributeValue::DebugStrRefSup(val),
@@ -397,15 +865,20 @@ fn from_attr_value<
         read::AttributeValue::DebugStrOffsetsIndex(index) => {
             let offset = context.dwarf.string_offset(context.unit, index)?;
             let r = context.dwarf.string(offset)?;
-            let id = context.strings.add(r.to_slice()?);
+            let r = r.to_slice()?;
+            let id = context.strings.add(remap_path(&r, prefix_map));
             AttributeValue::StringRef(id)
         }
         read::AttributeValue::DebugLineStrRef(offset) => {
             let r = context.dwarf.line_string(offset)?;
-            let id = context.line_strings.add(r.to_slice()?);
+            let r = r.to_slice()?;
+            let id = context.line_strings.add(remap_path(&r, prefix_map));
             AttributeValue::LineStringRef(id)
         }
-        read::AttributeValue::String(r) => AttributeValue::String(r.to_slice()?.into()),
+        read::AttributeValue::String(r) => {
+            let r = r.to_slice()?;
+            AttributeValue::String(remap_path(&r, prefix_map).into())
+        }
         read::AttributeValue::Encoding(val) => AttributeValue::Encoding(val),
         read::AttributeValue::DecimalSign(val) => AttributeValue::DecimalSign(val),
         read::AttributeValue::Endianity(val) => AttributeValue::Endianity(val),
@@ -423,9 +896,9 @@ fn from_attr_value<
                 // 0 means not specified, even for version 5.
                 AttributeValue::FileIndex(None)
             } else {
-                match context.line_program_files.get(val as usize - 1) {
-                    Some(id) => AttributeValue::FileIndex(Some(*id)),
-                    None => return Err(ConvertError::InvalidFileIndex),
+                match context.line_program_files.get(val as usize) {
+                    Some(Some(id)) => AttributeValue::FileIndex(Some(*id)),
+                    _ => return Err(ConvertError::InvalidFileIndex),
                 }
             }
         }
@@ -437,16 +910,87 @@ fn from_attr_value<
     Ok(Some(to))
 }
 
+pub(crate) fn read_code_ranges<R: Reader<Offset = usize>>(
+    from: &read::DebuggingInformationEntry<R>,
+    dwarf: &read::Dwarf<R>,
+    unit: &read::Unit<R>,
+) -> read::Result<Option<Vec<(u64, u64)>>> {
+    let ranges_offset = match from.attr_value(constants::DW_AT_ranges)? {
+        Some(read::AttributeValue::RangeListsRef(val)) => Some(val),
+        Some(read::AttributeValue::DebugRngListsIndex(index)) => {
+            Some(dwarf.ranges_offset(unit, index)?)
+        }
+        _ => None,
+    };
+    if let Some(offset) = ranges_offset {
+        let iter = dwarf.ranges.raw_ranges(offset, unit.encoding())?;
+        return Ok(Some(read_rangelist(iter, dwarf, unit)?));
+    }
+    let low_pc = match from.attr_value(constants::DW_AT_low_pc)? {
+        Some(read::AttributeValue::Addr(val)) => val,
+        Some(read::AttributeValue::DebugAddrIndex(index)) => dwarf.address(unit, index)?,
+        _ => return Ok(None),
+    };
+    let len = match from.attr_value(constants::DW_AT_high_pc)? {
+        Some(read::AttributeValue::Addr(high_pc)) => high_pc.saturating_sub(low_pc),
+        Some(read::AttributeValue::Udata(len)) => len,
+        _ => 0,
+    };
+    Ok(Some(vec![(low_pc, len)]))
+}
+
+// Maps subprograms code start to their DIEs.
+pub(crate) fn collect_subprograms<R: Reader<Offset = usize>>(
+    dwarf: &read::Dwarf<R>,
+) -> read::Result<HashMap<u64, UnitSectionOffset>> {
+    let mut subprograms = HashMap::new();
+    let mut units = dwarf.units();
+    while let Some(header) = units.next()? {
+        let unit = dwarf.unit(header)?;
+        let mut entries = unit.entries();
+        while let Some((_, entry)) = entries.next_dfs()? {
+            if entry.tag() != constants::DW_TAG_subprogram {
+                continue;
+            }
+            if let Some(ranges) = read_code_ranges(entry, dwarf, &unit)? {
+                for (start, _) in ranges {
+                    subprograms
+                        .entry(start)
+                        .or_insert_with(|| entry.offset().to_unit_section_offset(&unit));
+                }
+            }
+        }
+    }
+    Ok(subprograms)
+}
+
 fn from_rangelist<
     R: Reader<Offset = usize>,
     A: AddressTranslator,
     F: Fn(UnitSectionOffset) -> bool,
 >(
-    mut from: read::RawRngListIter<R>,
-    context: &ConvertUnitContext<R, A, F>,
+    from: read::RawRngListIter<R>,
+    context: &mut ConvertUnitContext<R, A, F>,
 ) -> ConvertResult<RangeList> {
-    let mut base_address = if context.base_address != 0 {
-        Some(context.base_address)
+    let ranges = read_rangelist(from, context.dwarf, context.unit)?;
+    let mut range_list = Vec::new();
+    for (start, len) in ranges {
+        let translated = context.at.translate_range(start, len);
+        context.report.stats.add_range(start, translated.len());
+        for (begin, length) in translated {
+            range_list.push(Range::StartLength { begin, length });
+        }
+    }
+    Ok(RangeList(range_list))
+}
+
+fn read_rangelist<R: Reader<Offset = usize>>(
+    mut from: read::RawRngListIter<R>,
+    dwarf: &read::Dwarf<R>,
+    unit: &read::Unit<R>,
+) -> read::Result<Vec<(u64, u64)>> {
+    let mut base_address = if unit.low_pc != 0 {
+        Some(unit.low_pc)
     } else {
         None
     };
@@ -464,16 +1008,16 @@ fn from_rangelist<
                 base_address = Some(addr);
             }
             read::RawRngListEntry::BaseAddressx { addr } => {
-                let address = context.dwarf.address(context.unit, addr)?;
+                let address = dwarf.address(unit, addr)?;
                 base_address = Some(address);
             }
             read::RawRngListEntry::StartxEndx { begin, end } => {
-                let begin = context.dwarf.address(context.unit, begin)?;
-                let end = context.dwarf.address(context.unit, end)?;
+                let begin = dwarf.address(unit, begin)?;
+                let end = dwarf.address(unit, end)?;
                 ranges.push((begin, end - begin));
             }
             read::RawRngListEntry::StartxLength { begin, length } => {
-                let begin = context.dwarf.address(context.unit, begin)?;
+                let begin = dwarf.address(unit, begin)?;
                 ranges.push((begin, length))
             }
             read::RawRngListEntry::OffsetPair { begin, end } => {
@@ -485,14 +1029,7 @@ fn from_rangelist<
             read::RawRngListEntry::StartLength { begin, length } => ranges.push((begin, length)),
         }
     }
-    let mut range_list = Vec::new();
-    for (start, len) in ranges {
-        let translated = context.at.translate_range(start, len);
-        for (begin, length) in translated {
-            range_list.push(Range::StartLength { begin, length });
-        }
-    }
-    Ok(RangeList(range_list))
+    Ok(ranges)
 }
 
 fn from_loclist<
@@ -501,7 +1038,7 @@ fn from_loclist<
     F: Fn(UnitSectionOffset) -> bool,
 >(
     mut from: read::RawLocListIter<R>,
-    context: &ConvertUnitContext<R, A, F>,
+    context: &mut ConvertUnitContext<R, A, F>,
 ) -> ConvertResult<LocationList> {
     let mut base_address = if context.base_address != 0 {
         Some(context.base_address)
@@ -578,7 +1115,8 @@ fn from_loclist<
     }
     let mut loc_list = Vec::new();
     for (start, len, ref data) in locations {
-        let translated = context.at.translate_range(start, len);
+        let translated = context.at.translate_location_range(start, len);
+        context.report.stats.add_location(start, len, &translated);
         for (begin, length) in translated {
             loc_list.push(Location::StartLength {
                 begin,
@@ -638,17 +1176,26 @@ fn from_line_program<R: Reader<Offset = usize>, A: AddressTranslator>(
     line_strings: &mut LineStringTable,
     strings: &mut StringTable,
     at: &A,
-) -> ConvertResult<(LineProgram, Vec<FileId>)> {
+    from_unit: &read::Unit<R>,
+    options: &ConvertOptions,
+) -> ConvertResult<(LineProgram, Vec<Option<FileId>>, Statistics, Diagnostics)> {
+    let mut stats = Statistics::default();
+    let mut diagnostics = Diagnostics::default();
+    let encoding = unit_encoding(from_unit, options);
+    let prefix_map = &options.prefix_map[..];
     // Create mappings in case the source has duplicate files or directories.
     let mut dirs = Vec::new();
     let mut files = Vec::new();
 
+    // The primary source file of version 5, which is only added as a
+    // file when the rows reference file 0.
+    let mut comp_file = None;
+
     let mut program = {
         let from_header = from_program.header();
-        let encoding = from_header.encoding();
 
         let comp_dir = match from_header.directory(0) {
-            Some(comp_dir) => from_line_string(comp_dir, dwarf, line_strings, strings)?,
+            Some(comp_dir) => from_line_string(comp_dir, dwarf, line_strings, strings, prefix_map)?,
             None => LineString::new(&[][..], encoding, line_strings),
         };
 
@@ -658,7 +1205,13 @@ fn from_line_program<R: Reader<Offset = usize>, A: AddressTranslator>(
                     return Err(ConvertError::InvalidDirectoryIndex);
                 }
                 (
-                    from_line_string(comp_file.path_name(), dwarf, line_strings, strings)?,
+                    from_line_string(
+                        comp_file.path_name(),
+                        dwarf,
+                        line_strings,
+                        strings,
+                        prefix_map,
+                    )?,
                     Some(FileInfo {
                         timestamp: comp_file.timestamp(),
                         size: comp_file.size(),
@@ -666,14 +1219,25 @@ fn from_line_program<R: Reader<Offset = usize>, A: AddressTranslator>(
                     }),
                 )
             }
+            // The version 5 line programs need the primary source file.
+            None if encoding.version >= 5 => match from_unit.name {
+                Some(ref name) => (
+                    LineString::String(remap_path(&name.to_slice()?, prefix_map).into_owned()),
+                    None,
+                ),
+                None => (LineString::String(Vec::new()), None),
+            },
             None => (LineString::new(&[][..], encoding, line_strings), None),
         };
 
         if from_header.line_base() > 0 {
             return Err(ConvertError::InvalidLineBase);
         }
+        if from_header.version() >= 5 {
+            comp_file = Some((comp_name.clone(), comp_file_info));
+        }
         let mut program = LineProgram::new(
-            from_header.encoding(),
+            encoding,
             from_header.line_encoding(),
             comp_dir,
             comp_name,
@@ -687,16 +1251,18 @@ fn from_line_program<R: Reader<Offset = usize>, A: AddressTranslator>(
             // A file index of 0 is invalid for version <= 4, but putting
             // something there makes the indexing easier.
             file_skip = 0;
-        // FIXME files.push(FileId::zero());
+            files.push(None);
         } else {
-            // We don't add the first file to `files`, but still allow
-            // it to be referenced from converted instructions.
+            // We don't add the first file to `files` yet: gimli has no id
+            // for the file 0 of the written program, so the rows that
+            // reference it get a copy of the primary source file.
             file_skip = 1;
-            // FIXME files.push(FileId::zero());
+            files.push(None);
         }
 
         for from_dir in from_header.include_directories() {
-            let from_dir = from_line_string(from_dir.clone(), dwarf, line_strings, strings)?;
+            let from_dir =
+                from_line_string(from_dir.clone(), dwarf, line_strings, strings, prefix_map)?;
             dirs.push(program.add_directory(from_dir));
         }
 
@@ -704,7 +1270,13 @@ fn from_line_program<R: Reader<Offset = usize>, A: AddressTranslator>(
         program.file_has_size = from_header.file_has_size();
         program.file_has_md5 = from_header.file_has_md5();
         for from_file in from_header.file_names().iter().skip(file_skip) {
-            let from_name = from_line_string(from_file.path_name(), dwarf, line_strings, strings)?;
+            let from_name = from_line_string(
+                from_file.path_name(),
+                dwarf,
+                line_strings,
+                strings,
+                prefix_map,
+            )?;
             let from_dir = from_file.directory_index();
             if from_dir >= dirs.len() as u64 {
                 return Err(ConvertError::InvalidDirectoryIndex);
@@ -715,7 +1287,7 @@ fn from_line_program<R: Reader<Offset = usize>, A: AddressTranslator>(
                 size: from_file.size(),
                 md5: *from_file.md5(),
             });
-            files.push(program.add_file(from_name, from_dir, from_info));
+            files.push(Some(program.add_file(from_name, from_dir, from_info)));
         }
 
         program
@@ -742,6 +1314,14 @@ fn from_line_program<R: Reader<Offset = usize>, A: AddressTranslator>(
                 if from_row.execute(instruction, &mut from_program) {
                     if from_row.end_sequence() {
                         assert!(!program.in_sequence());
+                        // The removed code sequences are not counted.
+                        let mut removed_stats = Statistics::default();
+                        let stats = match temp_line_sequence.base_address {
+                            Some(addr) if !is_tombstone(addr) => &mut stats,
+                            _ => &mut removed_stats,
+                        };
+                        stats.line_sequences += 1;
+                        stats.line_rows += temp_line_sequence.rows.len() as u64;
                         let translate_address = temp_line_sequence.translate_base_address(at);
                         // Process sequence only with valid translated address.
                         // TODO rely on translate_address() to return None.
@@ -755,17 +1335,35 @@ fn from_line_program<R: Reader<Offset = usize>, A: AddressTranslator>(
                             program.begin_sequence(translate_address);
                             let mut translated_rows = Vec::new();
                             for row in temp_line_sequence.rows.iter() {
-                                let translated_offsets = at.translate_offset(
-                                    temp_line_sequence.base_address.unwrap(),
-                                    row.address_offset,
-                                );
+                                let base_address = temp_line_sequence.base_address.unwrap();
+                                let (translated_offsets, before_base) =
+                                    at.translate_offset(base_address, row.address_offset);
+                                for target in before_base {
+                                    diagnostics.add(
+                                        DiagnosticCode::RowBeforeSequence,
+                                        format!(
+                                            "the line row is translated to {:?}, before its sequence start",
+                                            target
+                                        ),
+                                        None,
+                                        Some(base_address + row.address_offset),
+                                    );
+                                }
+                                if translated_offsets.is_empty() {
+                                    stats.line_rows_dropped += 1;
+                                } else {
+                                    stats.line_rows_mapped += 1;
+                                }
                                 for translated_offset in translated_offsets {
                                     translated_rows.push((translated_offset, row));
                                 }
                             }
                             translated_rows.sort_by(|(a, _), (b, _)| a.cmp(b));
                             // TODO do we need to dedup row addresses?
+                            let translated_count = translated_rows.len();
                             translated_rows.dedup_by(|(a, _), (b, _)| a == b);
+                            stats.line_rows_merged +=
+                                (translated_count - translated_rows.len()) as u64;
                             let last = translated_rows
                                 .last()
                                 .map(|r| r.0)
@@ -787,22 +1385,32 @@ fn from_line_program<R: Reader<Offset = usize>, A: AddressTranslator>(
                             }
                             // TODO use real end-of-function address (instead of last + 1)
                             program.end_sequence(last + 1);
+                        } else {
+                            stats.line_sequences_dropped += 1;
+                            stats.line_rows_dropped += temp_line_sequence.rows.len() as u64;
                         }
                         temp_line_sequence.clear();
                     } else {
                         let temp_line_row = TempLineRow {
-                            address_offset: from_row.address(),
+                            // The row address is relative to the sequence base address.
+                            address_offset: from_row.address()
+                                - temp_line_sequence.base_address.unwrap_or(0),
                             op_index: from_row.op_index(),
                             file: {
                                 let file = from_row.file_index();
-                                if file > files.len() as u64 {
-                                    return Err(ConvertError::InvalidFileIndex);
-                                }
-                                if file == 0 && program.version() <= 4 {
-                                    return Err(ConvertError::InvalidFileIndex);
+                                match files.get(file as usize) {
+                                    Some(Some(id)) => *id,
+                                    Some(None) if file == 0 => match comp_file.take() {
+                                        Some((name, info)) => {
+                                            let directory = program.default_directory();
+                                            let id = program.add_file(name, directory, info);
+                                            files[0] = Some(id);
+                                            id
+                                        }
+                                        None => return Err(ConvertError::InvalidFileIndex),
+                                    },
+                                    _ => return Err(ConvertError::InvalidFileIndex),
                                 }
-                                assert!(file > 0, "not implemented for versio 5's file == 0");
-                                files[file as usize - 1]
                             },
                             line: from_row.line().unwrap_or(0),
                             column: match from_row.column() {
@@ -823,7 +1431,7 @@ fn from_line_program<R: Reader<Offset = usize>, A: AddressTranslator>(
             }
         };
     }
-    Ok((program, files))
+    Ok((program, files, stats, diagnostics))
 }
 
 fn from_line_string<R: Reader<Offset = usize>>(
@@ -831,19 +1439,277 @@ fn from_line_string<R: Reader<Offset = usize>>(
     dwarf: &read::Dwarf<R>,
     line_strings: &mut LineStringTable,
     strings: &mut StringTable,
+    prefix_map: &[(String, String)],
 ) -> ConvertResult<LineString> {
     Ok(match from_attr {
-        read::AttributeValue::String(r) => LineString::String(r.to_slice()?.to_vec()),
+        read::AttributeValue::String(r) => {
+            let r = r.to_slice()?;
+            LineString::String(remap_path(&r, prefix_map).into_owned())
+        }
         read::AttributeValue::DebugStrRef(offset) => {
             let r = dwarf.debug_str.get_str(offset)?;
-            let id = strings.add(r.to_slice()?);
+            let r = r.to_slice()?;
+            let id = strings.add(remap_path(&r, prefix_map));
             LineString::StringRef(id)
         }
         read::AttributeValue::DebugLineStrRef(offset) => {
             let r = dwarf.debug_line_str.get_str(offset)?;
-            let id = line_strings.add(r.to_slice()?);
+            let r = r.to_slice()?;
+            let id = line_strings.add(remap_path(&r, prefix_map));
             LineString::LineStringRef(id)
         }
         _ => return Err(ConvertError::UnsupportedLineStringForm),
     })
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::address_translator::InlinedFunction;
+    use crate::test_util::{add_entry, read_module, set_code_range, write_module, ENCODING};
+
+    #[test]
+    fn test_strip_path_prefix() {
+        assert_eq!(strip_path_prefix(b"/src/a.c", b"/src"), Some(&b"/a.c"[..]));
+        assert_eq!(strip_path_prefix(b"/src/a.c", b"/src/"), Some(&b"a.c"[..]));
+        assert_eq!(strip_path_prefix(b"/src", b"/src"), Some(&b""[..]));
+        assert_eq!(strip_path_prefix(b"/srcfoo/a.c", b"/src"), None);
+        assert_eq!(strip_path_prefix(b"/lib/a.c", b"/src"), None);
+    }
+
+    #[test]
+    fn test_remap_path() {
+        let prefix_map = vec![
+            ("/build".to_string(), "/home".to_string()),
+            ("/build/src".to_string(), ".".to_string()),
+        ];
+        assert_eq!(&*remap_path(b"/build/src/a.c", &prefix_map), b"./a.c");
+        assert_eq!(
+            &*remap_path(b"/build/lib/b.c", &prefix_map),
+            b"/home/lib/b.c"
+        );
+        assert_eq!(
+            &*remap_path(b"/buildfoo/c.c", &prefix_map),
+            b"/buildfoo/c.c"
+        );
+    }
+
+    // Keeps the addresses, but the function at 0x10 was split into two
+    // target functions, e.g. by the outlining.
+    struct SplitTranslator;
+
+    impl AddressTranslator for SplitTranslator {
+        fn translate_address(&self, addr: u64) -> Vec<Address> {
+            vec![Address::Constant(addr)]
+        }
+
+        fn translate_range(&self, start: u64, len: u64) -> Vec<(Address, u64)> {
+            vec![(Address::Constant(start), len)]
+        }
+
+        fn translate_function_ranges(&self, start: u64, len: u64) -> Vec<(Address, u64)> {
+            if start == 0x10 {
+                vec![(Address::Constant(0x30), 8), (Address::Constant(0x10), 8)]
+            } else {
+                vec![(Address::Constant(start), len)]
+            }
+        }
+    }
+
+    #[test]
+    fn test_split_function_ranges() {
+        let mut dwarf = Dwarf::new();
+        let unit_id = dwarf.units.add(Unit::new(ENCODING, LineProgram::none()));
+        let unit = dwarf.units.get_mut(unit_id);
+        let root = unit.root();
+        let split = add_entry(unit, root, constants::DW_TAG_subprogram, "split");
+        set_code_range(unit, split, 0x10, 0x10);
+        let whole = add_entry(unit, root, constants::DW_TAG_subprogram, "whole");
+        set_code_range(unit, whole, 0x40, 8);
+        let bin = write_module(&mut dwarf);
+        let original = read_module(&bin);
+
+        let mut report = ConvertReport::default();
+        let mut converted = from_dwarf(
+            &original,
+            &SplitTranslator,
+            &|_| true,
+            &ConvertOptions::default(),
+            &mut report,
+        )
+        .unwrap();
+        let bin = write_module(&mut converted);
+        let dwarf = read_module(&bin);
+
+        let unit = dwarf.unit(dwarf.units().next().unwrap().unwrap()).unwrap();
+        let mut functions = Vec::new();
+        let mut entries = unit.entries();
+        while let Some((_, entry)) = entries.next_dfs().unwrap() {
+            if entry.tag() != constants::DW_TAG_subprogram {
+                continue;
+            }
+            let mut ranges = Vec::new();
+            let mut it = dwarf.die_ranges(&unit, entry).unwrap();
+            while let Some(range) = it.next().unwrap() {
+                ranges.push((range.begin, range.end));
+            }
+            let has_ranges = entry.attr(constants::DW_AT_ranges).unwrap().is_some();
+            functions.push((has_ranges, ranges));
+        }
+        assert_eq!(
+            functions,
+            vec![
+                // The split function gets DW_AT_ranges with all its pieces.
+                (true, vec![(0x10, 0x18), (0x30, 0x38)]),
+                (false, vec![(0x40, 0x48)]),
+            ]
+        );
+        assert_eq!(report.stats.functions_kept, 2);
+    }
+
+    // Keeps the addresses, and the function at 0x40 was inlined twice into
+    // the one at 0x10.
+    struct InlineTranslator;
+
+    impl AddressTranslator for InlineTranslator {
+        fn translate_address(&self, addr: u64) -> Vec<Address> {
+            vec![Address::Constant(addr)]
+        }
+
+        fn translate_range(&self, start: u64, len: u64) -> Vec<(Address, u64)> {
+            vec![(Address::Constant(start), len)]
+        }
+
+        fn translate_function_ranges(&self, start: u64, len: u64) -> Vec<(Address, u64)> {
+            vec![(Address::Constant(start), len)]
+        }
+
+        fn translate_inlined_functions(&self, start: u64, _len: u64) -> Vec<InlinedFunction> {
+            if start != 0x10 {
+                return vec![];
+            }
+            vec![
+                InlinedFunction {
+                    origin: 0x40,
+                    call_site: Some(0x10),
+                    ranges: vec![(Address::Constant(0x18), 2)],
+                },
+                InlinedFunction {
+                    origin: 0x40,
+                    call_site: Some(0x14),
+                    ranges: vec![(Address::Constant(0x1a), 2), (Address::Constant(0x1e), 2)],
+                },
+            ]
+        }
+    }
+
+    #[test]
+    fn test_inlined_functions() {
+        let encoding = Encoding {
+            version: 5,
+            ..ENCODING
+        };
+        let mut program = LineProgram::new(
+            encoding,
+            gimli::LineEncoding::default(),
+            LineString::String(b"/src".to_vec()),
+            LineString::String(b"a.c".to_vec()),
+            None,
+        );
+        let dir = program.default_directory();
+        let a = program.add_file(LineString::String(b"a.c".to_vec()), dir, None);
+        let b = program.add_file(LineString::String(b"b.h".to_vec()), dir, None);
+        program.begin_sequence(Some(Address::Constant(0x10)));
+        // Will be file 0, see below.
+        program.row().file = b;
+        program.row().line = 3;
+        program.generate_row();
+        program.row().address_offset = 4;
+        program.row().file = a;
+        program.row().line = 7;
+        program.generate_row();
+        program.end_sequence(0x10);
+
+        let mut dwarf = Dwarf::new();
+        let unit_id = dwarf.units.add(Unit::new(encoding, program));
+        let unit = dwarf.units.get_mut(unit_id);
+        let root = unit.root();
+        let caller = add_entry(unit, root, constants::DW_TAG_subprogram, "caller");
+        set_code_range(unit, caller, 0x10, 0x10);
+        let callee = add_entry(unit, root, constants::DW_TAG_subprogram, "callee");
+        set_code_range(unit, callee, 0x40, 8);
+        let mut bin = write_module(&mut dwarf);
+        // The gimli writer has no id for the file 0 of version 5: making
+        // the first row reference it by patching its DW_LNS_set_file 2.
+        let debug_line = bin.windows(11).position(|w| w == b".debug_line").unwrap();
+        let set_file = bin[debug_line..]
+            .windows(2)
+            .position(|w| w == [constants::DW_LNS_set_file.0, 2])
+            .unwrap();
+        bin[debug_line + set_file + 1] = 0;
+        let original = read_module(&bin);
+
+        let mut report = ConvertReport::default();
+        let mut converted = from_dwarf(
+            &original,
+            &InlineTranslator,
+            &|_| true,
+            &ConvertOptions::default(),
+            &mut report,
+        )
+        .unwrap();
+        let bin = write_module(&mut converted);
+        let dwarf = read_module(&bin);
+
+        let unit = dwarf.unit(dwarf.units().next().unwrap().unwrap()).unwrap();
+        let header = unit.line_program.as_ref().unwrap().header().clone();
+        let mut inlined = Vec::new();
+        let mut entries = unit.entries();
+        while let Some((_, entry)) = entries.next_dfs().unwrap() {
+            if entry.tag() != constants::DW_TAG_inlined_subroutine {
+                continue;
+            }
+            let origin = match entry.attr_value(constants::DW_AT_abstract_origin).unwrap() {
+                Some(read::AttributeValue::UnitRef(offset)) => offset,
+                value => panic!("unexpected origin {:?}", value),
+            };
+            let mut origin_entries = unit.entries_at_offset(origin).unwrap();
+            let (_, origin) = origin_entries.next_dfs().unwrap().unwrap();
+            let name = origin.attr_value(constants::DW_AT_name).unwrap().unwrap();
+            let name = dwarf.attr_string(&unit, name).unwrap().to_string_lossy();
+            let call_file = match entry.attr_value(constants::DW_AT_call_file).unwrap() {
+                Some(read::AttributeValue::FileIndex(file)) => file,
+                value => panic!("unexpected call_file {:?}", value),
+            };
+            let call_file = header.file(call_file).unwrap().path_name();
+            let call_file = dwarf
+                .attr_string(&unit, call_file)
+                .unwrap()
+                .to_string_lossy();
+            let call_line = entry.attr_value(constants::DW_AT_call_line).unwrap();
+            let mut ranges = Vec::new();
+            let mut it = dwarf.die_ranges(&unit, entry).unwrap();
+            while let Some(range) = it.next().unwrap() {
+                ranges.push((range.begin, range.end));
+            }
+            inlined.push((name.into_owned(), call_file.into_owned(), call_line, ranges));
+        }
+        assert_eq!(
+            inlined,
+            vec![
+                (
+                    "callee".to_string(),
+                    "a.c".to_string(),
+                    Some(read::AttributeValue::Udata(3)),
+                    vec![(0x18, 0x1a)],
+                ),
+                (
+                    "callee".to_string(),
+                    "a.c".to_string(),
+                    Some(read::AttributeValue::Udata(7)),
+                    vec![(0x1a, 0x1c), (0x1e, 0x20)],
+                ),
+            ]
+        );
+    }
+}
@@ -0,0 +1,1248 @@
+// Demangling of the Rust (legacy and v0) and the Itanium C++ symbol names.
+// Only the forms that are commonly found in the DWARF linkage names are
+// supported, `None` is returned for anything else.
+
+pub fn demangle(name: &str) -> Option<String> {
+    let name = name
+        .strip_prefix("_")
+        .filter(|n| n.starts_with("_Z"))
+        .unwrap_or(name);
+    if name.starts_with("_ZN") {
+        if let Some(result) = demangle_rust_legacy(name) {
+            return Some(result);
+        }
+    }
+    if let Some(name) = name.strip_prefix("_R") {
+        return V0Parser::new(name).demangle();
+    }
+    if let Some(name) = name.strip_prefix("_Z") {
+        return ItaniumParser::new(name).demangle();
+    }
+    None
+}
+
+// The legacy Rust names are the Itanium nested names with the hash as
+// the last component, e.g. `_ZN3foo3bar17h0123456789abcdefE`.
+fn demangle_rust_legacy(name: &str) -> Option<String> {
+    let mut rest = &name[3..];
+    let mut parts = Vec::new();
+    while !rest.starts_with('E') {
+        let digits = rest.bytes().take_while(u8::is_ascii_digit).count();
+        let len: usize = rest[..digits].parse().ok()?;
+        rest = &rest[digits..];
+        parts.push(rest.get(..len)?);
+        rest = &rest[len..];
+    }
+    let hash = parts.pop()?;
+    if hash.len() != 17
+        || !hash.starts_with('h')
+        || !hash[1..].bytes().all(|b| b.is_ascii_hexdigit())
+    {
+        return None;
+    }
+    let parts = parts
+        .into_iter()
+        .map(decode_rust_legacy_escapes)
+        .collect::<Option<Vec<_>>>()?;
+    Some(parts.join("::"))
+}
+
+fn decode_rust_legacy_escapes(part: &str) -> Option<String> {
+    let mut part = part;
+    if part.starts_with("_$") {
+        part = &part[1..];
+    }
+    let mut result = String::new();
+    let mut rest = part;
+    while !rest.is_empty() {
+        if rest.starts_with("..") {
+            result.push_str("::");
+            rest = &rest[2..];
+        } else if rest.starts_with('$') {
+            let end = rest[1..].find('$')? + 1;
+            let escape = &rest[1..end];
+            let ch = match escape {
+                "SP" => '@',
+                "BP" => '*',
+                "RF" => '&',
+                "LT" => '<',
+                "GT" => '>',
+                "LP" => '(',
+                "RP" => ')',
+                "C" => ',',
+                _ if escape.starts_with('u') => {
+                    std::char::from_u32(u32::from_str_radix(&escape[1..], 16).ok()?)?
+                }
+                _ => return None,
+            };
+            result.push(ch);
+            rest = &rest[end + 1..];
+        } else {
+            let ch = rest.chars().next().unwrap();
+            result.push(ch);
+            rest = &rest[ch.len_utf8()..];
+        }
+    }
+    Some(result)
+}
+
+// The Rust v0 mangling, see the RFC 2603.
+struct V0Parser<'a> {
+    s: &'a [u8],
+    pos: usize,
+    depth: u32,
+}
+
+const MAX_DEPTH: u32 = 64;
+
+impl<'a> V0Parser<'a> {
+    fn new(s: &'a str) -> Self {
+        V0Parser {
+            s: s.as_bytes(),
+            pos: 0,
+            depth: 0,
+        }
+    }
+
+    fn demangle(mut self) -> Option<String> {
+        if self.peek()?.is_ascii_digit() {
+            // The encoding versions other than the default are not supported.
+            return None;
+        }
+        self.path(false)
+    }
+
+    fn peek(&self) -> Option<u8> {
+        self.s.get(self.pos).cloned()
+    }
+
+    fn next(&mut self) -> Option<u8> {
+        let b = self.peek()?;
+        self.pos += 1;
+        Some(b)
+    }
+
+    fn eat(&mut self, b: u8) -> bool {
+        if self.peek() == Some(b) {
+            self.pos += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn base62(&mut self) -> Option<u64> {
+        if self.eat(b'_') {
+            return Some(0);
+        }
+        let mut value: u64 = 0;
+        loop {
+            let digit = match self.next()? {
+                b @ b'0'..=b'9' => b - b'0',
+                b @ b'a'..=b'z' => b - b'a' + 10,
+                b @ b'A'..=b'Z' => b - b'A' + 36,
+                b'_' => return value.checked_add(1),
+                _ => return None,
+            };
+            value = value.checked_mul(62)?.checked_add(digit as u64)?;
+        }
+    }
+
+    fn decimal(&mut self) -> Option<usize> {
+        let start = self.pos;
+        // A leading zero is the whole number, so "00" is two empty names.
+        if self.eat(b'0') {
+            return Some(0);
+        }
+        while let Some(b'0'..=b'9') = self.peek() {
+            self.pos += 1;
+        }
+        std::str::from_utf8(&self.s[start..self.pos])
+            .ok()?
+            .parse()
+            .ok()
+    }
+
+    fn disambiguator(&mut self) -> Option<u64> {
+        if self.eat(b's') {
+            Some(self.base62()? + 1)
+        } else {
+            Some(0)
+        }
+    }
+
+    fn ident(&mut self) -> Option<String> {
+        let _punycode = self.eat(b'u');
+        let len = self.decimal()?;
+        self.eat(b'_');
+        let bytes = self.s.get(self.pos..self.pos + len)?;
+        self.pos += len;
+        Some(String::from_utf8_lossy(bytes).into_owned())
+    }
+
+    fn backref<T, F: FnOnce(&mut Self) -> Option<T>>(&mut self, f: F) -> Option<T> {
+        let start = self.pos - 1;
+        let target = self.base62()? as usize;
+        if target >= start || self.depth >= MAX_DEPTH {
+            return None;
+        }
+        let saved = self.pos;
+        self.pos = target;
+        self.depth += 1;
+        let result = f(self);
+        self.depth -= 1;
+        self.pos = saved;
+        result
+    }
+
+    fn path(&mut self, in_type: bool) -> Option<String> {
+        Some(match self.next()? {
+            b'C' => {
+                self.disambiguator()?;
+                self.ident()?
+            }
+            b'M' => {
+                self.disambiguator()?;
+                self.path(false)?;
+                format!("<{}>", self.ty()?)
+            }
+            b'X' => {
+                self.disambiguator()?;
+                self.path(false)?;
+                let ty = self.ty()?;
+                format!("<{} as {}>", ty, self.path(true)?)
+            }
+            b'Y' => {
+                let ty = self.ty()?;
+                format!("<{} as {}>", ty, self.path(true)?)
+            }
+            b'N' => {
+                let ns = self.next()?;
+                let parent = self.path(in_type)?;
+                let dis = self.disambiguator()?;
+                let name = self.ident()?;
+                if ns.is_ascii_lowercase() {
+                    if name.is_empty() {
+                        parent
+                    } else {
+                        format!("{}::{}", parent, name)
+                    }
+                } else {
+                    let kind = match ns {
+                        b'C' => "closure",
+                        b'S' => "shim",
+                        _ => "",
+                    };
+                    if name.is_empty() {
+                        format!("{}::{{{}#{}}}", parent, kind, dis)
+                    } else {
+                        format!("{}::{{{}:{}#{}}}", parent, kind, name, dis)
+                    }
+                }
+            }
+            b'I' => {
+                let path = self.path(in_type)?;
+                let args = self.generic_args()?;
+                let separator = if in_type { "" } else { "::" };
+                format!("{}{}<{}>", path, separator, args.join(", "))
+            }
+            b'B' => self.backref(|p| p.path(in_type))?,
+            _ => return None,
+        })
+    }
+
+    fn generic_args(&mut self) -> Option<Vec<String>> {
+        let mut args = Vec::new();
+        while !self.eat(b'E') {
+            if self.eat(b'L') {
+                // The lifetimes are erased.
+                self.base62()?;
+            } else if self.eat(b'K') {
+                args.push(self.konst()?);
+            } else {
+                args.push(self.ty()?);
+            }
+        }
+        Some(args)
+    }
+
+    fn basic_type(b: u8) -> Option<&'static str> {
+        Some(match b {
+            b'a' => "i8",
+            b'b' => "bool",
+            b'c' => "char",
+            b'd' => "f64",
+            b'e' => "str",
+            b'f' => "f32",
+            b'h' => "u8",
+            b'i' => "isize",
+            b'j' => "usize",
+            b'l' => "i32",
+            b'm' => "u32",
+            b'n' => "i128",
+            b'o' => "u128",
+            b'p' => "_",
+            b's' => "i16",
+            b't' => "u16",
+            b'u' => "()",
+            b'v' => "...",
+            b'x' => "i64",
+            b'y' => "u64",
+            b'z' => "!",
+            _ => return None,
+        })
+    }
+
+    fn ty(&mut self) -> Option<String> {
+        let b = self.peek()?;
+        if let Some(basic) = Self::basic_type(b) {
+            self.pos += 1;
+            return Some(basic.to_string());
+        }
+        Some(match b {
+            b'A' => {
+                self.pos += 1;
+                let ty = self.ty()?;
+                format!("[{}; {}]", ty, self.konst()?)
+            }
+            b'S' => {
+                self.pos += 1;
+                format!("[{}]", self.ty()?)
+            }
+            b'T' => {
+                self.pos += 1;
+                let mut types = Vec::new();
+                while !self.eat(b'E') {
+                    types.push(self.ty()?);
+                }
+                if types.len() == 1 {
+                    format!("({},)", types[0])
+                } else {
+                    format!("({})", types.join(", "))
+                }
+            }
+            b'R' | b'Q' => {
+                self.pos += 1;
+                if self.eat(b'L') {
+                    self.base62()?;
+                }
+                let prefix = if b == b'R' { "&" } else { "&mut " };
+                format!("{}{}", prefix, self.ty()?)
+            }
+            b'P' => {
+                self.pos += 1;
+                format!("*const {}", self.ty()?)
+            }
+            b'O' => {
+                self.pos += 1;
+                format!("*mut {}", self.ty()?)
+            }
+            b'F' => {
+                self.pos += 1;
+                self.fn_sig()?
+            }
+            b'D' => {
+                self.pos += 1;
+                self.dyn_bounds()?
+            }
+            b'B' => {
+                self.pos += 1;
+                self.backref(|p| p.ty())?
+            }
+            _ => self.path(true)?,
+        })
+    }
+
+    fn fn_sig(&mut self) -> Option<String> {
+        if self.eat(b'G') {
+            self.base62()?;
+        }
+        let mut result = String::new();
+        if self.eat(b'U') {
+            result.push_str("unsafe ");
+        }
+        if self.eat(b'K') {
+            let abi = if self.eat(b'C') {
+                "C".to_string()
+            } else {
+                self.ident()?.replace('_', "-")
+            };
+            result.push_str(&format!("extern \"{}\" ", abi));
+        }
+        let mut params = Vec::new();
+        while !self.eat(b'E') {
+            params.push(self.ty()?);
+        }
+        result.push_str(&format!("fn({})", params.join(", ")));
+        let ret = self.ty()?;
+        if ret != "()" {
+            result.push_str(&format!(" -> {}", ret));
+        }
+        Some(result)
+    }
+
+    fn dyn_bounds(&mut self) -> Option<String> {
+        if self.eat(b'G') {
+            self.base62()?;
+        }
+        let mut traits = Vec::new();
+        while !self.eat(b'E') {
+            let mut path = self.path(true)?;
+            let mut bindings = Vec::new();
+            while self.eat(b'p') {
+                let name = self.ident()?;
+                bindings.push(format!("{} = {}", name, self.ty()?));
+            }
+            if !bindings.is_empty() {
+                if path.ends_with('>') {
+                    path.pop();
+                    path.push_str(&format!(", {}>", bindings.join(", ")));
+                } else {
+                    path.push_str(&format!("<{}>", bindings.join(", ")));
+                }
+            }
+            traits.push(path);
+        }
+        if !self.eat(b'L') {
+            return None;
+        }
+        self.base62()?;
+        Some(format!("dyn {}", traits.join(" + ")))
+    }
+
+    fn konst(&mut self) -> Option<String> {
+        if self.eat(b'p') {
+            return Some("_".to_string());
+        }
+        if self.eat(b'B') {
+            return self.backref(|p| p.konst());
+        }
+        let ty = self.next()?;
+        let negative = self.eat(b'n');
+        let start = self.pos;
+        while self.peek()? != b'_' {
+            self.pos += 1;
+        }
+        let hex = std::str::from_utf8(&self.s[start..self.pos]).ok()?;
+        self.pos += 1;
+        let value = if hex.is_empty() {
+            0
+        } else {
+            u128::from_str_radix(hex, 16).ok()?
+        };
+        Some(match ty {
+            b'b' => (value != 0).to_string(),
+            b'c' => format!("{:?}", std::char::from_u32(value as u32)?),
+            b'a' | b's' | b'l' | b'x' | b'n' | b'i' if negative => format!("-{}", value),
+            b'a' | b's' | b'l' | b'x' | b'n' | b'i' | b'h' | b't' | b'm' | b'y' | b'o' | b'j' => {
+                value.to_string()
+            }
+            _ => return None,
+        })
+    }
+}
+
+// The Itanium C++ ABI mangling. The types are formatted as the c++filt
+// does, e.g. `char const*`.
+struct ItaniumParser<'a> {
+    s: &'a [u8],
+    pos: usize,
+    depth: u32,
+    substitutions: Vec<String>,
+    template_args: Vec<String>,
+}
+
+struct ItaniumName {
+    name: String,
+    // The function template names are followed by the return type.
+    is_template: bool,
+    is_ctor_dtor: bool,
+    cv_qualifiers: String,
+}
+
+const OPERATORS: &[(&str, &str)] = &[
+    ("nw", "new"),
+    ("na", "new[]"),
+    ("dl", "delete"),
+    ("da", "delete[]"),
+    ("ps", "+"),
+    ("ng", "-"),
+    ("ad", "&"),
+    ("de", "*"),
+    ("co", "~"),
+    ("pl", "+"),
+    ("mi", "-"),
+    ("ml", "*"),
+    ("dv", "/"),
+    ("rm", "%"),
+    ("an", "&"),
+    ("or", "|"),
+    ("eo", "^"),
+    ("aS", "="),
+    ("pL", "+="),
+    ("mI", "-="),
+    ("mL", "*="),
+    ("dV", "/="),
+    ("rM", "%="),
+    ("aN", "&="),
+    ("oR", "|="),
+    ("eO", "^="),
+    ("ls", "<<"),
+    ("rs", ">>"),
+    ("lS", "<<="),
+    ("rS", ">>="),
+    ("eq", "=="),
+    ("ne", "!="),
+    ("lt", "<"),
+    ("gt", ">"),
+    ("le", "<="),
+    ("ge", ">="),
+    ("ss", "<=>"),
+    ("nt", "!"),
+    ("aa", "&&"),
+    ("oo", "||"),
+    ("pp", "++"),
+    ("mm", "--"),
+    ("cm", ","),
+    ("pm", "->*"),
+    ("pt", "->"),
+    ("cl", "()"),
+    ("ix", "[]"),
+    ("qu", "?"),
+];
+
+impl<'a> ItaniumParser<'a> {
+    fn new(s: &'a str) -> Self {
+        ItaniumParser {
+            s: s.as_bytes(),
+            pos: 0,
+            depth: 0,
+            substitutions: Vec::new(),
+            template_args: Vec::new(),
+        }
+    }
+
+    fn demangle(mut self) -> Option<String> {
+        let result = self.encoding()?;
+        // The clone suffixes, e.g. `.constprop.0`, are ignored.
+        match self.peek() {
+            None | Some(b'.') => Some(result),
+            _ => None,
+        }
+    }
+
+    fn peek(&self) -> Option<u8> {
+        self.s.get(self.pos).cloned()
+    }
+
+    fn peek_at(&self, i: usize) -> Option<u8> {
+        self.s.get(self.pos + i).cloned()
+    }
+
+    fn next(&mut self) -> Option<u8> {
+        let b = self.peek()?;
+        self.pos += 1;
+        Some(b)
+    }
+
+    fn eat(&mut self, b: u8) -> bool {
+        if self.peek() == Some(b) {
+            self.pos += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn number(&mut self) -> Option<i64> {
+        let negative = self.eat(b'n');
+        let start = self.pos;
+        while let Some(b'0'..=b'9') = self.peek() {
+            self.pos += 1;
+        }
+        let value: i64 = std::str::from_utf8(&self.s[start..self.pos])
+            .ok()?
+            .parse()
+            .ok()?;
+        Some(if negative { -value } else { value })
+    }
+
+    // The sequence ID is the base 36 number, `_` is the first one.
+    fn seq_id(&mut self) -> Option<usize> {
+        if self.eat(b'_') {
+            return Some(0);
+        }
+        let mut value = 0usize;
+        loop {
+            let digit = match self.next()? {
+                b @ b'0'..=b'9' => b - b'0',
+                b @ b'A'..=b'Z' => b - b'A' + 10,
+                b'_' => return Some(value + 1),
+                _ => return None,
+            };
+            value = value.checked_mul(36)?.checked_add(digit as usize)?;
+        }
+    }
+
+    fn encoding(&mut self) -> Option<String> {
+        self.depth += 1;
+        if self.depth > MAX_DEPTH {
+            return None;
+        }
+        let name = self.name()?;
+        let result = match self.peek() {
+            None | Some(b'E') | Some(b'.') => name.name,
+            Some(_) => {
+                let ret = if name.is_template && !name.is_ctor_dtor {
+                    format!("{} ", self.ty()?)
+                } else {
+                    String::new()
+                };
+                let params = self.bare_function_type()?;
+                format!("{}{}({}){}", ret, name.name, params, name.cv_qualifiers)
+            }
+        };
+        self.depth -= 1;
+        Some(result)
+    }
+
+    fn bare_function_type(&mut self) -> Option<String> {
+        let mut params = Vec::new();
+        while let Some(b) = self.peek() {
+            if b == b'E' || b == b'.' {
+                break;
+            }
+            params.push(self.ty()?);
+        }
+        if params.len() == 1 && params[0] == "void" {
+            params.clear();
+        }
+        Some(params.join(", "))
+    }
+
+    fn name(&mut self) -> Option<ItaniumName> {
+        match self.peek()? {
+            b'N' => {
+                self.pos += 1;
+                self.nested_name(false)
+            }
+            b'Z' => {
+                self.pos += 1;
+                let function = self.encoding()?;
+                if !self.eat(b'E') {
+                    return None;
+                }
+                let mut name = if self.eat(b's') {
+                    ItaniumName::plain("string literal".to_string())
+                } else {
+                    self.name()?
+                };
+                if self.eat(b'_') {
+                    if self.eat(b'_') {
+                        self.number()?;
+                        self.eat(b'_');
+                    } else {
+                        self.number()?;
+                    }
+                }
+                name.name = format!("{}::{}", function, name.name);
+                Some(name)
+            }
+            _ => {
+                // The internal linkage names, e.g. of the static functions.
+                self.eat(b'L');
+                let mut is_substitution = false;
+                let name = if self.peek() == Some(b'S') && self.peek_at(1) == Some(b't') {
+                    self.pos += 2;
+                    format!("std::{}", self.unqualified_name()?)
+                } else if self.peek() == Some(b'S') {
+                    is_substitution = true;
+                    self.substitution()?
+                } else {
+                    self.unqualified_name()?
+                };
+                if self.peek() == Some(b'I') {
+                    if !is_substitution {
+                        self.substitutions.push(name.clone());
+                    }
+                    let args = self.template_args()?;
+                    let mut name = ItaniumName::plain(format!("{}{}", name, args));
+                    name.is_template = true;
+                    return Some(name);
+                }
+                Some(ItaniumName::plain(name))
+            }
+        }
+    }
+
+    fn nested_name(&mut self, is_type: bool) -> Option<ItaniumName> {
+        let mut cv_qualifiers = String::new();
+        loop {
+            match self.peek()? {
+                b'r' => cv_qualifiers.push_str(" restrict"),
+                b'V' => cv_qualifiers.push_str(" volatile"),
+                b'K' => cv_qualifiers.push_str(" const"),
+                _ => break,
+            }
+            self.pos += 1;
+        }
+        if self.eat(b'R') {
+            cv_qualifiers.push_str(" &");
+        } else if self.eat(b'O') {
+            cv_qualifiers.push_str(" &&");
+        }
+        let mut prefix = String::new();
+        let mut last = String::new();
+        let mut is_template = false;
+        let mut is_ctor_dtor = false;
+        while !self.eat(b'E') {
+            is_template = false;
+            is_ctor_dtor = false;
+            let b = self.peek()?;
+            let component = match b {
+                b'S' if self.peek_at(1) == Some(b't') => {
+                    self.pos += 2;
+                    prefix = "std".to_string();
+                    continue;
+                }
+                b'S' => {
+                    prefix = self.substitution()?;
+                    last = prefix.rsplit("::").next().unwrap().to_string();
+                    continue;
+                }
+                b'I' => {
+                    let args = self.template_args()?;
+                    prefix.push_str(&args);
+                    is_template = true;
+                    if self.peek() != Some(b'E') || is_type {
+                        self.substitutions.push(prefix.clone());
+                    }
+                    continue;
+                }
+                b'T' => {
+                    self.pos += 1;
+                    prefix = self.template_param()?;
+                    self.substitutions.push(prefix.clone());
+                    continue;
+                }
+                b'C' if self.peek_at(1) != Some(b'v') => {
+                    self.pos += 1;
+                    self.eat(b'I');
+                    self.next()?;
+                    is_ctor_dtor = true;
+                    strip_template_args(&last).to_string()
+                }
+                b'D' if matches!(self.peek_at(1), Some(b'0'..=b'5')) => {
+                    self.pos += 2;
+                    is_ctor_dtor = true;
+                    format!("~{}", strip_template_args(&last))
+                }
+                // The internal linkage and the data member prefix markers,
+                // e.g. of the lambdas in the variable initializers.
+                b'L' | b'M' => {
+                    self.pos += 1;
+                    continue;
+                }
+                _ => self.unqualified_name()?,
+            };
+            last = component.clone();
+            if !prefix.is_empty() {
+                prefix.push_str("::");
+            }
+            prefix.push_str(&component);
+            if self.peek() != Some(b'E') || is_type {
+                self.substitutions.push(prefix.clone());
+            }
+        }
+        Some(ItaniumName {
+            name: prefix,
+            is_template,
+            is_ctor_dtor,
+            cv_qualifiers,
+        })
+    }
+
+    fn unqualified_name(&mut self) -> Option<String> {
+        let b = self.peek()?;
+        let mut name = if b.is_ascii_digit() {
+            self.source_name()?
+        } else if b == b'U' && self.peek_at(1) == Some(b't') {
+            // The unnamed types, e.g. `Ut_`.
+            self.pos += 2;
+            let id = if self.eat(b'_') {
+                1
+            } else {
+                self.number()? + 2
+            };
+            if id > 1 && !self.eat(b'_') {
+                return None;
+            }
+            format!("{{unnamed type#{}}}", id)
+        } else if b == b'U' && self.peek_at(1) == Some(b'l') {
+            self.pos += 2;
+            let mut params = Vec::new();
+            while !self.eat(b'E') {
+                params.push(self.ty()?);
+            }
+            if params.len() == 1 && params[0] == "void" {
+                params.clear();
+            }
+            let id = if self.eat(b'_') {
+                1
+            } else {
+                let id = self.number()? + 2;
+                if !self.eat(b'_') {
+                    return None;
+                }
+                id
+            };
+            format!("{{lambda({})#{}}}", params.join(", "), id)
+        } else {
+            self.operator_name()?
+        };
+        while self.peek() == Some(b'B') {
+            self.pos += 1;
+            name.push_str(&format!("[abi:{}]", self.source_name()?));
+        }
+        Some(name)
+    }
+
+    fn source_name(&mut self) -> Option<String> {
+        let len = self.number()?;
+        if len < 0 {
+            return None;
+        }
+        let bytes = self.s.get(self.pos..self.pos + len as usize)?;
+        self.pos += len as usize;
+        if bytes.starts_with(b"_GLOBAL__N") {
+            return Some("(anonymous namespace)".to_string());
+        }
+        Some(String::from_utf8_lossy(bytes).into_owned())
+    }
+
+    fn operator_name(&mut self) -> Option<String> {
+        let code = self.s.get(self.pos..self.pos + 2)?;
+        self.pos += 2;
+        if code == b"cv" {
+            return Some(format!("operator {}", self.ty()?));
+        }
+        if code == b"li" {
+            return Some(format!("operator\"\" {}", self.source_name()?));
+        }
+        let (_, op) = OPERATORS.iter().find(|(c, _)| c.as_bytes() == code)?;
+        let separator = if op.as_bytes()[0].is_ascii_alphabetic() {
+            " "
+        } else {
+            ""
+        };
+        Some(format!("operator{}{}", separator, op))
+    }
+
+    fn substitution(&mut self) -> Option<String> {
+        if !self.eat(b'S') {
+            return None;
+        }
+        let special = match self.peek()? {
+            b'a' => Some("std::allocator"),
+            b'b' => Some("std::basic_string"),
+            b's' => Some("std::string"),
+            b'i' => Some("std::istream"),
+            b'o' => Some("std::ostream"),
+            b'd' => Some("std::iostream"),
+            _ => None,
+        };
+        if let Some(special) = special {
+            self.pos += 1;
+            return Some(special.to_string());
+        }
+        let id = self.seq_id()?;
+        self.substitutions.get(id).cloned()
+    }
+
+    fn template_param(&mut self) -> Option<String> {
+        let id = self.seq_id()?;
+        self.template_args.get(id).cloned()
+    }
+
+    fn template_args(&mut self) -> Option<String> {
+        if !self.eat(b'I') {
+            return None;
+        }
+        let mut args = Vec::new();
+        while !self.eat(b'E') {
+            args.push(self.template_arg()?);
+        }
+        self.template_args = args.clone();
+        let args = args.join(", ");
+        // The closing brackets are separated, as c++filt does.
+        let separator = if args.ends_with('>') { " " } else { "" };
+        Some(format!("<{}{}>", args, separator))
+    }
+
+    fn template_arg(&mut self) -> Option<String> {
+        match self.peek()? {
+            b'L' => {
+                self.pos += 1;
+                self.literal()
+            }
+            b'J' => {
+                self.pos += 1;
+                let mut args = Vec::new();
+                while !self.eat(b'E') {
+                    args.push(self.template_arg()?);
+                }
+                Some(args.join(", "))
+            }
+            _ => self.ty(),
+        }
+    }
+
+    fn literal(&mut self) -> Option<String> {
+        if self.peek() == Some(b'_') && self.peek_at(1) == Some(b'Z') {
+            self.pos += 2;
+            let result = self.encoding()?;
+            return if self.eat(b'E') { Some(result) } else { None };
+        }
+        let ty = self.ty()?;
+        let value = self.number()?;
+        if !self.eat(b'E') {
+            return None;
+        }
+        Some(match ty.as_str() {
+            "bool" => (value != 0).to_string(),
+            "int" => value.to_string(),
+            "unsigned int" => format!("{}u", value),
+            "long" => format!("{}l", value),
+            "unsigned long" => format!("{}ul", value),
+            "long long" => format!("{}ll", value),
+            "unsigned long long" => format!("{}ull", value),
+            _ => format!("({}){}", ty, value),
+        })
+    }
+
+    fn builtin_type(b: u8) -> Option<&'static str> {
+        Some(match b {
+            b'v' => "void",
+            b'w' => "wchar_t",
+            b'b' => "bool",
+            b'c' => "char",
+            b'a' => "signed char",
+            b'h' => "unsigned char",
+            b's' => "short",
+            b't' => "unsigned short",
+            b'i' => "int",
+            b'j' => "unsigned int",
+            b'l' => "long",
+            b'm' => "unsigned long",
+            b'x' => "long long",
+            b'y' => "unsigned long long",
+            b'n' => "__int128",
+            b'o' => "unsigned __int128",
+            b'f' => "float",
+            b'd' => "double",
+            b'e' => "long double",
+            b'g' => "__float128",
+            b'z' => "...",
+            _ => return None,
+        })
+    }
+
+    fn ty(&mut self) -> Option<String> {
+        self.depth += 1;
+        if self.depth > MAX_DEPTH {
+            return None;
+        }
+        // The template parameters refer to the function template arguments,
+        // and not to the ones of the types.
+        let template_args = self.template_args.clone();
+        let result = self.ty_inner();
+        self.template_args = template_args;
+        self.depth -= 1;
+        result
+    }
+
+    fn ty_inner(&mut self) -> Option<String> {
+        let b = self.peek()?;
+        if let Some(builtin) = Self::builtin_type(b) {
+            self.pos += 1;
+            return Some(builtin.to_string());
+        }
+        let result = match b {
+            b'r' | b'V' | b'K' => {
+                self.pos += 1;
+                let qualifier = match b {
+                    b'r' => "restrict",
+                    b'V' => "volatile",
+                    _ => "const",
+                };
+                format!("{} {}", self.ty()?, qualifier)
+            }
+            b'P' | b'R' | b'O' => {
+                self.pos += 1;
+                let suffix = match b {
+                    b'P' => "*",
+                    b'R' => "&",
+                    _ => "&&",
+                };
+                if self.peek() == Some(b'F') {
+                    self.pos += 1;
+                    let (ret, params) = self.function_type()?;
+                    self.substitutions.push(format!("{} ({})", ret, params));
+                    format!("{} ({})({})", ret, suffix, params)
+                } else if self.peek() == Some(b'A') {
+                    let array = self.ty()?;
+                    let i = array.find(" [")?;
+                    format!("{} ({}){}", &array[..i], suffix, &array[i..])
+                } else {
+                    format!("{}{}", self.ty()?, suffix)
+                }
+            }
+            b'F' => {
+                self.pos += 1;
+                let (ret, params) = self.function_type()?;
+                format!("{} ({})", ret, params)
+            }
+            b'A' => {
+                self.pos += 1;
+                let size = self.number()?;
+                if !self.eat(b'_') {
+                    return None;
+                }
+                format!("{} [{}]", self.ty()?, size)
+            }
+            b'M' => {
+                self.pos += 1;
+                let class = self.ty()?;
+                let start = self.pos;
+                let mut cv_qualifiers = String::new();
+                loop {
+                    match self.peek()? {
+                        b'r' => cv_qualifiers.push_str(" restrict"),
+                        b'V' => cv_qualifiers.push_str(" volatile"),
+                        b'K' => cv_qualifiers.push_str(" const"),
+                        _ => break,
+                    }
+                    self.pos += 1;
+                }
+                if self.eat(b'F') {
+                    let (ret, params) = self.function_type()?;
+                    let function = format!("{} ({})", ret, params);
+                    self.substitutions.push(function.clone());
+                    if !cv_qualifiers.is_empty() {
+                        self.substitutions
+                            .push(format!("{}{}", function, cv_qualifiers));
+                    }
+                    format!("{} ({}::*)({}){}", ret, class, params, cv_qualifiers)
+                } else {
+                    self.pos = start;
+                    format!("{} {}::*", self.ty()?, class)
+                }
+            }
+            b'T' => {
+                self.pos += 1;
+                let param = self.template_param()?;
+                self.substitutions.push(param.clone());
+                if self.peek() == Some(b'I') {
+                    format!("{}{}", param, self.template_args()?)
+                } else {
+                    return Some(param);
+                }
+            }
+            b'D' => {
+                self.pos += 1;
+                match self.next()? {
+                    b'n' => "decltype(nullptr)".to_string(),
+                    b'i' => "char32_t".to_string(),
+                    b's' => "char16_t".to_string(),
+                    b'u' => "char8_t".to_string(),
+                    b'a' => "auto".to_string(),
+                    b'c' => "decltype(auto)".to_string(),
+                    b'p' => return self.ty(),
+                    _ => return None,
+                }
+            }
+            b'u' => {
+                self.pos += 1;
+                self.source_name()?
+            }
+            b'S' if self.peek_at(1) != Some(b't') => {
+                let name = self.substitution()?;
+                if self.peek() == Some(b'I') {
+                    format!("{}{}", name, self.template_args()?)
+                } else {
+                    // The substitutions are not added again.
+                    return Some(name);
+                }
+            }
+            b'N' => {
+                self.pos += 1;
+                return Some(self.nested_name(true)?.name);
+            }
+            _ => {
+                let name = self.name()?;
+                if !name.is_template {
+                    self.substitutions.push(name.name.clone());
+                    return Some(name.name);
+                }
+                name.name
+            }
+        };
+        self.substitutions.push(result.clone());
+        Some(result)
+    }
+
+    fn function_type(&mut self) -> Option<(String, String)> {
+        self.eat(b'Y');
+        let ret = self.ty()?;
+        let params = self.bare_function_type()?;
+        if !self.eat(b'E') {
+            return None;
+        }
+        Some((ret, params))
+    }
+}
+
+impl ItaniumName {
+    fn plain(name: String) -> Self {
+        ItaniumName {
+            name,
+            is_template: false,
+            is_ctor_dtor: false,
+            cv_qualifiers: String::new(),
+        }
+    }
+}
+
+fn strip_template_args(name: &str) -> &str {
+    match name.find('<') {
+        Some(i) => &name[..i],
+        None => name,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::demangle;
+
+    // The expected names are from the c++filt output, and for the Rust
+    // names from rustfilt, i.e. without the hashes.
+    const NAMES: &[(&str, &str)] = &[
+        ("_Z2f5z", "f5(...)"),
+        ("_ZL3barv", "bar()"),
+        ("_ZL9local_var", "local_var"),
+        ("_ZL8local_fnPc", "local_fn(char*)"),
+        (
+            "_Z2f1PKcmxshfdebwDsDi",
+            "f1(char const*, unsigned long, long long, short, unsigned char, \
+             float, double, long double, bool, wchar_t, char16_t, char32_t)",
+        ),
+        (
+            "_Z2f2PFiicERA3_iMN2ns5inner6WidgetEiMS5_VFviE",
+            "f2(int (*)(int, char), int (&) [3], int ns::inner::Widget::*, \
+             void (ns::inner::Widget::*)(int) volatile)",
+        ),
+        (
+            "_Z2f4St10unique_ptrIN2ns5inner6WidgetESt14default_deleteIS2_EESt8functionIFviEE",
+            "f4(std::unique_ptr<ns::inner::Widget, std::default_delete<ns::inner::Widget> >, \
+             std::function<void (int)>)",
+        ),
+        ("_Z2f6DnOin", "f6(decltype(nullptr), int&&, __int128)"),
+        (
+            "_Z2f7St6vectorIS_IiSaIiEESaIS1_EESt4pairIiNSt7__cxx1112basic_stringIcSt11char_traitsIcESaIcEEEE",
+            "f7(std::vector<std::vector<int, std::allocator<int> >, \
+             std::allocator<std::vector<int, std::allocator<int> > > >, \
+             std::pair<int, std::__cxx11::basic_string<char, std::char_traits<char>, \
+             std::allocator<char> > >)",
+        ),
+        (
+            "_Z1gIN2ns5inner6WidgetEEvT_PS3_RKS3_",
+            "void g<ns::inner::Widget>(ns::inner::Widget, ns::inner::Widget*, \
+             ns::inner::Widget const&)",
+        ),
+        ("_Z1hILb0ELln2EEvv", "void h<false, -2l>()"),
+        ("_ZN1S4convIlEET_S1_", "long S::conv<long>(long)"),
+        (
+            "_ZN2ns5inner3addIdEET_S2_S2_",
+            "double ns::inner::add<double>(double, double)",
+        ),
+        (
+            "_ZN2ns5inner3ArrIcLi4EE3getEj",
+            "ns::inner::Arr<char, 4>::get(unsigned int)",
+        ),
+        ("_ZN12_GLOBAL__N_14anonEi", "(anonymous namespace)::anon(int)"),
+        ("_ZN2ns5inner6WidgetC2Ev", "ns::inner::Widget::Widget()"),
+        ("_ZN2ns5inner6WidgetD1Ev", "ns::inner::Widget::~Widget()"),
+        (
+            "_ZN2ns5inner6WidgetaSERKS1_",
+            "ns::inner::Widget::operator=(ns::inner::Widget const&)",
+        ),
+        (
+            "_ZNK2ns5inner6WidgetcvbEv",
+            "ns::inner::Widget::operator bool() const",
+        ),
+        ("_ZNO2ns5inner6Widget4rrefEv", "ns::inner::Widget::rref() &&"),
+        ("_ZNV2ns5inner6Widget3setEi", "ns::inner::Widget::set(int) volatile"),
+        (
+            "_ZN9__gnu_cxxL21__default_lock_policyE",
+            "__gnu_cxx::__default_lock_policy",
+        ),
+        (
+            "_ZNK3lamMUliE_clEi",
+            "lam::{lambda(int)#1}::operator()(int) const",
+        ),
+        (
+            "_ZZ8call_lamvENKUldE_clEd",
+            "call_lam()::{lambda(double)#1}::operator()(double) const",
+        ),
+        ("_ZZ9use_localvE7counter", "use_local()::counter"),
+        ("_ZN3fib6helper17h29fccc310ca3bd83E", "fib::helper"),
+        (
+            "_ZN47_$LT$u32$u20$as$u20$core..iter..range..Step$GT$17forward_unchecked17hb48f8308e2ba16edE",
+            "<u32 as core::iter::range::Step>::forward_unchecked",
+        ),
+        (
+            "_RNvMs7_NtCsgXGp5Oqx2Ny_4core3numy11rotate_left",
+            "<u64>::rotate_left",
+        ),
+        (
+            "_RNvXsM_NtNtCsgXGp5Oqx2Ny_4core3fmt3numoNtB7_8UpperHex3fmt",
+            "<u128 as core::fmt::UpperHex>::fmt",
+        ),
+        (
+            "_RINvMNtCsgXGp5Oqx2Ny_4core5sliceSh16align_to_offsetsjEB5_",
+            "<[u8]>::align_to_offsets::<usize>",
+        ),
+        (
+            "_RINvNtCsgXGp5Oqx2Ny_4core6escape9backslashKj4_EB4_",
+            "core::escape::backslash::<4>",
+        ),
+        (
+            "_RINvMNtCsgXGp5Oqx2Ny_4core5sliceSAcj3_13get_uncheckedjEB5_",
+            "<[[char; 3]]>::get_unchecked::<usize>",
+        ),
+        (
+            "_RINvMNtCsgXGp5Oqx2Ny_4core6optionINtB3_6OptionNtNtNtB5_3net7ip_addr8Ipv4AddrE3mapNtBK_6IpAddrNcNtB1h_2V40EB5_",
+            "<core::option::Option<core::net::ip_addr::Ipv4Addr>>::map::\
+             <core::net::ip_addr::IpAddr, core::net::ip_addr::IpAddr::V4>",
+        ),
+        (
+            "_RINvMNtNtCsgXGp5Oqx2Ny_4core3net6parserNtB3_6Parser15read_atomicallymNCNCINvB2_11read_numberhE00EB7_",
+            "<core::net::parser::Parser>::read_atomically::<u32, \
+             <core::net::parser::Parser>::read_number<u8>::{closure#0}::{closure#0}>",
+        ),
+    ];
+
+    #[test]
+    fn test_demangle() {
+        for (name, expected) in NAMES {
+            assert_eq!(demangle(name).as_deref(), Some(*expected), "{}", name);
+        }
+    }
+
+    #[test]
+    fn test_demangle_invalid() {
+        assert_eq!(demangle("main"), None);
+        assert_eq!(demangle("_ZN3fooE1"), None);
+        assert_eq!(demangle("_RNvC"), None);
+    }
+}
@@ -0,0 +1,206 @@
+use gimli::UnitSectionOffset;
+use std::fmt;
+
+use crate::explain::offset_value;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Severity {
+    Note,
+    Warning,
+    Error,
+}
+
+impl Severity {
+    pub fn name(self) -> &'static str {
+        match self {
+            Severity::Note => "note",
+            Severity::Warning => "warning",
+            Severity::Error => "error",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum DiagnosticCode {
+    // The subprogram has no DW_AT_low_pc or DW_AT_ranges, e.g. it is
+    // a declaration or the abstract instance of the inlined function.
+    NoCodeRanges,
+    // The line row is translated before the start of its sequence, and
+    // cannot be expressed as the offset from it.
+    RowBeforeSequence,
+    // The subprogram was not accepted as a dependencies root, since its code
+    // cannot be translated.
+    RejectedRoot,
+
+    // The problems of the DWARF found by the verification.
+    SubprogramOutsideFunction,
+    LineNotMonotonic,
+    LineOutsideCode,
+    InvalidRangeList,
+    InvalidLocationList,
+    UnresolvedReference,
+    TombstoneAddress,
+
+    // The original functions that were not found in the transformed module,
+    // by their names or by their identical bodies. Their code is removed.
+    UnmatchedFunctionName,
+    UnmatchedFunctionBody,
+
+    // The source files that cannot be embedded, or that do not match
+    // the recorded MD5 checksum.
+    SourceNotFound,
+    SourceTooLarge,
+    SourceUnreadable,
+    SourceHasNulBytes,
+    SourceChecksumMismatch,
+}
+
+impl DiagnosticCode {
+    pub const ALL: &'static [DiagnosticCode] = &[
+        DiagnosticCode::NoCodeRanges,
+        DiagnosticCode::RowBeforeSequence,
+        DiagnosticCode::RejectedRoot,
+        DiagnosticCode::SubprogramOutsideFunction,
+        DiagnosticCode::LineNotMonotonic,
+        DiagnosticCode::LineOutsideCode,
+        DiagnosticCode::InvalidRangeList,
+        DiagnosticCode::InvalidLocationList,
+        DiagnosticCode::UnresolvedReference,
+        DiagnosticCode::TombstoneAddress,
+        DiagnosticCode::UnmatchedFunctionName,
+        DiagnosticCode::UnmatchedFunctionBody,
+        DiagnosticCode::SourceNotFound,
+        DiagnosticCode::SourceTooLarge,
+        DiagnosticCode::SourceUnreadable,
+        DiagnosticCode::SourceHasNulBytes,
+        DiagnosticCode::SourceChecksumMismatch,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            DiagnosticCode::NoCodeRanges => "no-code-ranges",
+            DiagnosticCode::RowBeforeSequence => "row-before-sequence",
+            DiagnosticCode::RejectedRoot => "rejected-root",
+            DiagnosticCode::SubprogramOutsideFunction => "subprogram-outside-function",
+            DiagnosticCode::LineNotMonotonic => "line-not-monotonic",
+            DiagnosticCode::LineOutsideCode => "line-outside-code",
+            DiagnosticCode::InvalidRangeList => "invalid-range-list",
+            DiagnosticCode::InvalidLocationList => "invalid-location-list",
+            DiagnosticCode::UnresolvedReference => "unresolved-reference",
+            DiagnosticCode::TombstoneAddress => "tombstone-address",
+            DiagnosticCode::UnmatchedFunctionName => "unmatched-function-name",
+            DiagnosticCode::UnmatchedFunctionBody => "unmatched-function-body",
+            DiagnosticCode::SourceNotFound => "source-not-found",
+            DiagnosticCode::SourceTooLarge => "source-too-large",
+            DiagnosticCode::SourceUnreadable => "source-unreadable",
+            DiagnosticCode::SourceHasNulBytes => "source-has-nul-bytes",
+            DiagnosticCode::SourceChecksumMismatch => "source-checksum-mismatch",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<DiagnosticCode> {
+        DiagnosticCode::ALL
+            .iter()
+            .cloned()
+            .find(|code| code.name() == name)
+    }
+
+    pub fn severity(self) -> Severity {
+        match self {
+            DiagnosticCode::NoCodeRanges | DiagnosticCode::RejectedRoot => Severity::Note,
+            DiagnosticCode::RowBeforeSequence
+            | DiagnosticCode::UnmatchedFunctionName
+            | DiagnosticCode::UnmatchedFunctionBody
+            | DiagnosticCode::SourceNotFound
+            | DiagnosticCode::SourceTooLarge
+            | DiagnosticCode::SourceUnreadable
+            | DiagnosticCode::SourceHasNulBytes
+            | DiagnosticCode::SourceChecksumMismatch => Severity::Warning,
+            _ => Severity::Error,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Diagnostic {
+    pub code: DiagnosticCode,
+    pub message: String,
+    // The original DIE, and the original code address (relative to the code
+    // section) the diagnostic is about.
+    pub die: Option<UnitSectionOffset>,
+    pub address: Option<u64>,
+}
+
+impl Diagnostic {
+    pub fn severity(&self) -> Severity {
+        self.code.severity()
+    }
+}
+
+// Formats the diagnostic as "<severity>[<code>]: <message> (<context>)".
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{}[{}]: {}",
+            self.severity().name(),
+            self.code.name(),
+            self.message
+        )?;
+        let mut context = Vec::new();
+        if let Some(die) = self.die {
+            context.push(format!("DIE 0x{:08x}", offset_value(die)));
+        }
+        if let Some(address) = self.address {
+            context.push(format!("address 0x{:x}", address));
+        }
+        if !context.is_empty() {
+            write!(f, " ({})", context.join(", "))?;
+        }
+        Ok(())
+    }
+}
+
+// The diagnostics sink of the conversion, instead of printing the warnings
+// the library collects them for the caller. The tools add their own ones,
+// e.g. of the functions matching, to report all of them the same way.
+#[derive(Debug, Clone, Default)]
+pub struct Diagnostics {
+    pub items: Vec<Diagnostic>,
+}
+
+impl Diagnostics {
+    pub fn add(
+        &mut self,
+        code: DiagnosticCode,
+        message: String,
+        die: Option<UnitSectionOffset>,
+        address: Option<u64>,
+    ) {
+        self.items.push(Diagnostic {
+            code,
+            message,
+            die,
+            address,
+        });
+    }
+
+    pub fn has_warnings(&self) -> bool {
+        self.items
+            .iter()
+            .any(|item| item.severity() >= Severity::Warning)
+    }
+
+    pub fn has_errors(&self) -> bool {
+        self.items
+            .iter()
+            .any(|item| item.severity() == Severity::Error)
+    }
+
+    pub fn count(&self, severity: Severity) -> usize {
+        self.items
+            .iter()
+            .filter(|item| item.severity() == severity)
+            .count()
+    }
+}
@@ -0,0 +1,188 @@
+use gimli::constants;
+use gimli::read;
+use gimli::{Reader, UnitOffset, UnitSectionOffset};
+use std::vec::Vec;
+
+use crate::gc::has_matching_name;
+use crate::symbolize::is_tombstone;
+
+fn find_unit<R: Reader<Offset = usize>>(
+    dwarf: &read::Dwarf<R>,
+    offset: UnitSectionOffset,
+) -> read::Result<Option<read::Unit<R>>> {
+    let mut units = dwarf.units();
+    while let Some(header) = units.next()? {
+        let start = header.offset().0;
+        if start <= offset_value(offset)
+            && offset_value(offset) < start + header.length_including_self()
+        {
+            return Ok(Some(dwarf.unit(header)?));
+        }
+    }
+    Ok(None)
+}
+
+pub(crate) fn offset_value(offset: UnitSectionOffset) -> usize {
+    match offset {
+        UnitSectionOffset::DebugInfoOffset(o) => o.0,
+        UnitSectionOffset::DebugTypesOffset(o) => o.0,
+    }
+}
+
+// Finds the unit of the DIE, if the offset is at a DIE of the unit.
+fn find_die_unit<R: Reader<Offset = usize>>(
+    dwarf: &read::Dwarf<R>,
+    offset: UnitSectionOffset,
+) -> read::Result<Option<(read::Unit<R>, UnitOffset)>> {
+    let unit = match find_unit(dwarf, offset)? {
+        Some(unit) => unit,
+        None => return Ok(None),
+    };
+    let unit_offset = match offset.to_unit_offset(&unit) {
+        Some(unit_offset) => unit_offset,
+        None => return Ok(None),
+    };
+    let mut entries = unit.entries();
+    while let Some((_, entry)) = entries.next_dfs()? {
+        if entry.offset() == unit_offset {
+            return Ok(Some((unit, unit_offset)));
+        }
+    }
+    Ok(None)
+}
+
+// Checks that the .debug_info offset is at a DIE (and not e.g. in the middle
+// of its attributes, or in a unit header).
+pub fn is_die_offset<R: Reader<Offset = usize>>(
+    dwarf: &read::Dwarf<R>,
+    offset: UnitSectionOffset,
+) -> read::Result<bool> {
+    Ok(find_die_unit(dwarf, offset)?.is_some())
+}
+
+// Formats the DIE as "<offset> <tag> <name>", e.g. for the GC explanations.
+pub fn describe_die<R: Reader<Offset = usize>>(
+    dwarf: &read::Dwarf<R>,
+    offset: UnitSectionOffset,
+) -> read::Result<String> {
+    let unknown = format!("0x{:08x} <unknown>", offset_value(offset));
+    let (unit, unit_offset) = match find_die_unit(dwarf, offset)? {
+        Some(found) => found,
+        None => return Ok(unknown),
+    };
+    let mut entries = unit.entries_at_offset(unit_offset)?;
+    let entry = match entries.next_dfs()? {
+        Some((_, entry)) => entry,
+        None => return Ok(unknown),
+    };
+    let mut description = format!("0x{:08x} {}", offset_value(offset), entry.tag());
+    if let Some(name) = read_die_name(entry, dwarf, &unit)? {
+        description.push_str(&format!(" \"{}\"", name));
+    }
+    Ok(description)
+}
+
+pub(crate) fn read_die_name<R: Reader<Offset = usize>>(
+    die: &read::DebuggingInformationEntry<R>,
+    dwarf: &read::Dwarf<R>,
+    unit: &read::Unit<R>,
+) -> read::Result<Option<String>> {
+    let name = match die.attr_value(constants::DW_AT_name)? {
+        Some(name) => name,
+        None => match die.attr_value(constants::DW_AT_linkage_name)? {
+            Some(name) => name,
+            None => return Ok(None),
+        },
+    };
+    let name = dwarf.attr_string(unit, name)?;
+    let name = name.to_string_lossy()?.into_owned();
+    Ok(Some(name))
+}
+
+// Finds all DIEs with DW_AT_name or DW_AT_linkage_name matching the pattern.
+pub fn find_dies_by_name<R: Reader<Offset = usize>>(
+    dwarf: &read::Dwarf<R>,
+    pattern: &str,
+) -> read::Result<Vec<UnitSectionOffset>> {
+    let pattern = pattern.to_string();
+    let mut result = Vec::new();
+    let mut units = dwarf.units();
+    while let Some(header) = units.next()? {
+        let unit = dwarf.unit(header)?;
+        let mut entries = unit.entries();
+        while let Some((_, entry)) = entries.next_dfs()? {
+            if has_matching_name(entry, dwarf, &unit, &[&pattern])? {
+                result.push(entry.offset().to_unit_section_offset(&unit));
+            }
+        }
+    }
+    Ok(result)
+}
+
+// Finds the DIEs with the code ranges (e.g. the subprograms, the inlined
+// subroutines and the lexical blocks) that contain the address.
+pub fn find_dies_by_address<R: Reader<Offset = usize>>(
+    dwarf: &read::Dwarf<R>,
+    addr: u64,
+) -> read::Result<Vec<UnitSectionOffset>> {
+    let mut result = Vec::new();
+    let mut units = dwarf.units();
+    while let Some(header) = units.next()? {
+        let unit = dwarf.unit(header)?;
+        let mut entries = unit.entries();
+        while let Some((_, entry)) = entries.next_dfs()? {
+            if entry.tag() == constants::DW_TAG_compile_unit {
+                continue;
+            }
+            let mut ranges = dwarf.die_ranges(&unit, entry)?;
+            while let Some(range) = ranges.next()? {
+                if !is_tombstone(range.begin) && range.begin <= addr && addr < range.end {
+                    result.push(entry.offset().to_unit_section_offset(&unit));
+                    break;
+                }
+            }
+        }
+    }
+    Ok(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::{add_entry, read_module, write_module, ENCODING};
+    use gimli::write::{self, LineProgram, Unit};
+    use gimli::DebugInfoOffset;
+
+    #[test]
+    fn test_describe_die() {
+        let mut dwarf = write::Dwarf::new();
+        let unit_id = dwarf.units.add(Unit::new(ENCODING, LineProgram::none()));
+        let unit = dwarf.units.get_mut(unit_id);
+        let root = unit.root();
+        add_entry(unit, root, constants::DW_TAG_subprogram, "main");
+        let bin = write_module(&mut dwarf);
+        let dwarf = read_module(&bin);
+
+        let units = dwarf.units().next().unwrap().unwrap();
+        let unit = dwarf.unit(units).unwrap();
+        let mut entries = unit.entries();
+        entries.next_dfs().unwrap();
+        let (_, main) = entries.next_dfs().unwrap().unwrap();
+        let main = main.offset().to_unit_section_offset(&unit);
+        assert!(is_die_offset(&dwarf, main).unwrap());
+        assert_eq!(
+            describe_die(&dwarf, main).unwrap(),
+            format!("0x{:08x} DW_TAG_subprogram \"main\"", offset_value(main))
+        );
+
+        // In the unit header, in the middle of the DIE, and out of range.
+        for offset in &[1, offset_value(main) + 1, bin.len()] {
+            let offset = UnitSectionOffset::DebugInfoOffset(DebugInfoOffset(*offset));
+            assert!(!is_die_offset(&dwarf, offset).unwrap());
+            assert_eq!(
+                describe_die(&dwarf, offset).unwrap(),
+                format!("0x{:08x} <unknown>", offset_value(offset))
+            );
+        }
+    }
+}
@@ -1,15 +1,19 @@
 use gimli::constants;
 use gimli::read;
 use gimli::{Reader, UnitSectionOffset};
-use std::collections::{HashMap, HashSet};
+use std::collections::{HashMap, HashSet, VecDeque};
 use std::vec::Vec;
 
 use crate::address_translator::AddressTranslator;
+use crate::convert::{collect_subprograms, read_code_ranges, ConvertReport};
+use crate::diagnostics::DiagnosticCode;
+use crate::glob::glob_match;
 
 #[derive(Debug)]
 pub struct Dependencies {
     edges: HashMap<UnitSectionOffset, HashSet<UnitSectionOffset>>,
     roots: HashSet<UnitSectionOffset>,
+    rejected_roots: HashSet<UnitSectionOffset>,
 }
 
 impl Dependencies {
@@ -17,6 +21,7 @@ impl Dependencies {
         Dependencies {
             edges: HashMap::new(),
             roots: HashSet::new(),
+            rejected_roots: HashSet::new(),
         }
     }
 
@@ -38,6 +43,10 @@ impl Dependencies {
         self.roots.insert(root);
     }
 
+    fn add_rejected_root(&mut self, root: UnitSectionOffset) {
+        self.rejected_roots.insert(root);
+    }
+
     pub fn get_reachable(&self) -> HashSet<UnitSectionOffset> {
         let mut reachable = self.roots.clone();
         let mut queue = Vec::new();
@@ -65,30 +74,255 @@ impl Dependencies {
         }
         reachable
     }
+
+    pub fn get_reachable_from(&self, roots: &[UnitSectionOffset]) -> HashSet<UnitSectionOffset> {
+        let mut reachable = roots.iter().cloned().collect::<HashSet<_>>();
+        let mut queue = roots.to_vec();
+        while let Some(i) = queue.pop() {
+            if let Some(deps) = self.edges.get(&i) {
+                for j in deps {
+                    if reachable.insert(*j) {
+                        queue.push(*j);
+                    }
+                }
+            }
+        }
+        reachable
+    }
+
+    pub fn get_edges(&self, offset: UnitSectionOffset) -> Vec<UnitSectionOffset> {
+        let mut edges = match self.edges.get(&offset) {
+            Some(deps) => deps.iter().cloned().collect::<Vec<_>>(),
+            None => vec![],
+        };
+        edges.sort();
+        edges
+    }
+
+    pub fn is_root(&self, offset: UnitSectionOffset) -> bool {
+        self.roots.contains(&offset)
+    }
+
+    // Finds the shortest chain of edges from one of the roots to the target.
+    pub fn find_path(&self, target: UnitSectionOffset) -> Option<Vec<UnitSectionOffset>> {
+        let mut roots = self.roots.iter().cloned().collect::<Vec<_>>();
+        roots.sort();
+        let mut parents = HashMap::new();
+        let mut queue = VecDeque::new();
+        for root in roots {
+            parents.insert(root, None);
+            queue.push_back(root);
+        }
+        while let Some(i) = queue.pop_front() {
+            if i == target {
+                let mut path = vec![i];
+                let mut current = i;
+                while let Some(Some(parent)) = parents.get(&current) {
+                    path.push(*parent);
+                    current = *parent;
+                }
+                path.reverse();
+                return Some(path);
+            }
+            if let Some(deps) = self.edges.get(&i) {
+                let mut deps = deps.iter().cloned().collect::<Vec<_>>();
+                deps.sort();
+                for j in deps {
+                    if parents.contains_key(&j) {
+                        continue;
+                    }
+                    parents.insert(j, Some(i));
+                    queue.push_back(j);
+                }
+            }
+        }
+        None
+    }
+
+    // Finds the subprograms that were not accepted as roots because their code
+    // cannot be translated, but that would make the target reachable.
+    pub fn find_rejected_roots(&self, target: UnitSectionOffset) -> Vec<UnitSectionOffset> {
+        let mut reverse_edges: HashMap<UnitSectionOffset, Vec<UnitSectionOffset>> = HashMap::new();
+        for (i, deps) in self.edges.iter() {
+            for j in deps {
+                reverse_edges.entry(*j).or_default().push(*i);
+            }
+        }
+        let mut visited = HashSet::new();
+        visited.insert(target);
+        let mut queue = vec![target];
+        while let Some(i) = queue.pop() {
+            if let Some(deps) = reverse_edges.get(&i) {
+                for j in deps {
+                    if visited.insert(*j) {
+                        queue.push(*j);
+                    }
+                }
+            }
+        }
+        let mut rejected = self
+            .rejected_roots
+            .iter()
+            .filter(|root| visited.contains(root))
+            .cloned()
+            .collect::<Vec<_>>();
+        rejected.sort();
+        rejected
+    }
 }
 
-pub fn build_dependencies<R: Reader<Offset = usize>, A: AddressTranslator>(
+#[derive(Debug, Clone)]
+pub enum RootFilter {
+    // Keep all types declared at the global scope.
+    Types,
+    // Keep all DIEs of the compilation units with matching DW_AT_name.
+    Unit(String),
+    // Keep DIEs with matching DW_AT_name or DW_AT_linkage_name.
+    Name(String),
+}
+
+struct BuildUnitContext<'a, R: Reader<Offset = usize>, A: AddressTranslator, D: Fn(u64) -> bool> {
+    dwarf: &'a read::Dwarf<R>,
+    unit: &'a read::Unit<R>,
+    at: &'a A,
+    data_filter: &'a D,
+    root_filters: &'a [RootFilter],
+    subprograms: &'a HashMap<u64, UnitSectionOffset>,
+    keep_unit: bool,
+}
+
+// Builds the dependencies, collects the roots statistics, and reports
+// the rejected roots.
+pub fn build_dependencies<R: Reader<Offset = usize>, A: AddressTranslator, D: Fn(u64) -> bool>(
     dwarf: &read::Dwarf<R>,
     at: &A,
+    data_filter: &D,
+    root_filters: &[RootFilter],
+    report: &mut ConvertReport,
 ) -> read::Result<Dependencies> {
     let mut deps = Dependencies::new();
+    let subprograms = collect_subprograms(dwarf)?;
     let mut units = dwarf.units();
     while let Some(unit) = units.next()? {
-        build_unit_dependencies(unit, dwarf, at, &mut deps)?;
+        build_unit_dependencies(
+            unit,
+            dwarf,
+            at,
+            data_filter,
+            root_filters,
+            &subprograms,
+            &mut deps,
+        )?;
+    }
+    report.stats.roots = deps.roots.len() as u64;
+    report.stats.rejected_roots = deps.rejected_roots.len() as u64;
+    let mut rejected_roots = deps.rejected_roots.iter().cloned().collect::<Vec<_>>();
+    rejected_roots.sort();
+    for root in rejected_roots {
+        report.diagnostics.add(
+            DiagnosticCode::RejectedRoot,
+            "the subprogram code cannot be translated, it is not a root".to_string(),
+            Some(root),
+            None,
+        );
     }
     Ok(deps)
 }
 
-fn build_unit_dependencies<R: Reader<Offset = usize>, A: AddressTranslator>(
+fn build_unit_dependencies<R: Reader<Offset = usize>, A: AddressTranslator, D: Fn(u64) -> bool>(
     header: read::CompilationUnitHeader<R>,
     dwarf: &read::Dwarf<R>,
     at: &A,
+    data_filter: &D,
+    root_filters: &[RootFilter],
+    subprograms: &HashMap<u64, UnitSectionOffset>,
     deps: &mut Dependencies,
 ) -> read::Result<()> {
     let unit = dwarf.unit(header)?;
     let mut tree = unit.entries_tree(None)?;
     let root = tree.root()?;
-    build_die_dependencies(root, dwarf, &unit, at, deps)?;
+    let keep_unit = match unit.name {
+        Some(ref name) => {
+            let name = name.to_string_lossy()?;
+            root_filters.iter().any(|filter| match filter {
+                RootFilter::Unit(pattern) => glob_match(pattern, &name),
+                _ => false,
+            })
+        }
+        None => false,
+    };
+    let context = BuildUnitContext {
+        dwarf,
+        unit: &unit,
+        at,
+        data_filter,
+        root_filters,
+        subprograms,
+        keep_unit,
+    };
+    build_die_dependencies(root, &context, deps)?;
+    Ok(())
+}
+
+// Collects the entries of the included units, and the entries of the excluded
+// units that are referenced from them (with their parents and dependencies).
+pub(crate) fn collect_referenced_entries<
+    R: Reader<Offset = usize>,
+    F: Fn(UnitSectionOffset) -> bool,
+>(
+    dwarf: &read::Dwarf<R>,
+    excluded_units: &HashSet<UnitSectionOffset>,
+    die_filter: &F,
+) -> read::Result<HashSet<UnitSectionOffset>> {
+    let mut deps = Dependencies::new();
+    let mut units = dwarf.units();
+    while let Some(header) = units.next()? {
+        let unit = dwarf.unit(header)?;
+        let is_root = !excluded_units.contains(&unit.offset);
+        let mut tree = unit.entries_tree(None)?;
+        let root = tree.root()?;
+        build_reference_dependencies(root, &unit, is_root, die_filter, &mut deps)?;
+    }
+    Ok(deps.get_reachable())
+}
+
+fn build_reference_dependencies<R: Reader<Offset = usize>, F: Fn(UnitSectionOffset) -> bool>(
+    die: read::EntriesTreeNode<R>,
+    unit: &read::Unit<R>,
+    is_root: bool,
+    die_filter: &F,
+    deps: &mut Dependencies,
+) -> read::Result<()> {
+    let entry = die.entry();
+    let offset = entry.offset().to_unit_section_offset(unit);
+    if !die_filter(offset) {
+        return Ok(());
+    }
+    if is_root {
+        deps.add_root(offset);
+    }
+    let mut attrs = entry.attrs();
+    while let Some(attr) = attrs.next()? {
+        match attr.value() {
+            read::AttributeValue::UnitRef(val) => {
+                deps.add_edge(offset, val.to_unit_section_offset(unit));
+            }
+            read::AttributeValue::DebugInfoRef(val) => {
+                deps.add_edge(offset, UnitSectionOffset::DebugInfoOffset(val));
+            }
+            _ => (),
+        }
+    }
+    let mut children = die.children();
+    while let Some(child) = children.next()? {
+        let child_entry = child.entry();
+        let child_offset = child_entry.offset().to_unit_section_offset(unit);
+        deps.add_edge(child_offset, offset);
+        if has_die_back_edge(child_entry) {
+            deps.add_edge(offset, child_offset);
+        }
+        build_reference_dependencies(child, unit, is_root, die_filter, deps)?;
+    }
     Ok(())
 }
 
@@ -180,13 +414,113 @@ fn has_valid_code_range<R: Reader<Offset = usize>, A: AddressTranslator>(
     Ok(false)
 }
 
-fn build_die_dependencies<R: Reader<Offset = usize>, A: AddressTranslator>(
-    die: read::EntriesTreeNode<R>,
+fn has_global_scope<R: Reader<Offset = usize>>(die: &read::DebuggingInformationEntry<R>) -> bool {
+    !matches!(
+        die.tag(),
+        constants::DW_TAG_subprogram
+            | constants::DW_TAG_lexical_block
+            | constants::DW_TAG_inlined_subroutine
+    )
+}
+
+fn read_data_address<R: Reader<Offset = usize>>(
+    die: &read::DebuggingInformationEntry<R>,
+    unit: &read::Unit<R>,
+) -> read::Result<Option<u64>> {
+    if die.tag() != constants::DW_TAG_variable {
+        return Ok(None);
+    }
+    let expr = match die.attr_value(constants::DW_AT_location)? {
+        Some(read::AttributeValue::Exprloc(expr)) => expr,
+        _ => return Ok(None),
+    };
+    // Looking for the DW_OP_addr of the variable storage in the linear memory.
+    // The expression with an unknown operation, e.g. DW_OP_WASM_location,
+    // has no static address.
+    let mut bytes = expr.0.clone();
+    while !bytes.is_empty() {
+        match read::Operation::parse(&mut bytes, &expr.0, unit.encoding()) {
+            Ok(read::Operation::Address { address }) => return Ok(Some(address)),
+            Ok(_) => (),
+            Err(_) => return Ok(None),
+        }
+    }
+    Ok(None)
+}
+
+pub(crate) fn is_type<R: Reader<Offset = usize>>(die: &read::DebuggingInformationEntry<R>) -> bool {
+    matches!(
+        die.tag(),
+        constants::DW_TAG_base_type
+            | constants::DW_TAG_unspecified_type
+            | constants::DW_TAG_typedef
+            | constants::DW_TAG_structure_type
+            | constants::DW_TAG_class_type
+            | constants::DW_TAG_union_type
+            | constants::DW_TAG_enumeration_type
+            | constants::DW_TAG_array_type
+            | constants::DW_TAG_subroutine_type
+            | constants::DW_TAG_pointer_type
+            | constants::DW_TAG_reference_type
+            | constants::DW_TAG_rvalue_reference_type
+            | constants::DW_TAG_ptr_to_member_type
+            | constants::DW_TAG_const_type
+            | constants::DW_TAG_volatile_type
+            | constants::DW_TAG_restrict_type
+            | constants::DW_TAG_atomic_type
+    )
+}
+
+pub(crate) fn has_matching_name<R: Reader<Offset = usize>>(
+    die: &read::DebuggingInformationEntry<R>,
     dwarf: &read::Dwarf<R>,
     unit: &read::Unit<R>,
-    at: &A,
+    patterns: &[&String],
+) -> read::Result<bool> {
+    for name in &[constants::DW_AT_name, constants::DW_AT_linkage_name] {
+        if let Some(attr) = die.attr_value(*name)? {
+            let name = dwarf.attr_string(unit, attr)?;
+            let name = name.to_string_lossy()?;
+            if patterns.iter().any(|pattern| glob_match(pattern, &name)) {
+                return Ok(true);
+            }
+        }
+    }
+    Ok(false)
+}
+
+fn is_filtered_root<R: Reader<Offset = usize>, A: AddressTranslator, D: Fn(u64) -> bool>(
+    die: &read::DebuggingInformationEntry<R>,
+    is_global_scope: bool,
+    context: &BuildUnitContext<R, A, D>,
+) -> read::Result<bool> {
+    if context.keep_unit {
+        return Ok(true);
+    }
+    let mut names = Vec::new();
+    for filter in context.root_filters {
+        match filter {
+            RootFilter::Types => {
+                if is_global_scope && is_type(die) {
+                    return Ok(true);
+                }
+            }
+            RootFilter::Name(pattern) => names.push(pattern),
+            RootFilter::Unit(_) => (),
+        }
+    }
+    if names.is_empty() {
+        return Ok(false);
+    }
+    has_matching_name(die, context.dwarf, context.unit, &names)
+}
+
+fn build_die_dependencies<R: Reader<Offset = usize>, A: AddressTranslator, D: Fn(u64) -> bool>(
+    die: read::EntriesTreeNode<R>,
+    context: &BuildUnitContext<R, A, D>,
     deps: &mut Dependencies,
 ) -> read::Result<()> {
+    let (dwarf, unit, at) = (context.dwarf, context.unit, context.at);
     let entry = die.entry();
     let offset = entry.offset().to_unit_section_offset(unit);
     let mut attrs = entry.attrs();
@@ -194,18 +528,65 @@ fn build_die_dependencies<R: Reader<Offset = usize>, A: AddressTranslator>(
         build_attr_dependencies(&attr, offset, dwarf, unit, at, deps)?;
     }
 
+    let is_global_scope = has_global_scope(entry);
     let mut children = die.children();
     while let Some(child) = children.next()? {
         let child_entry = child.entry();
         let child_offset = child_entry.offset().to_unit_section_offset(unit);
         deps.add_edge(child_offset, offset);
-        if has_die_back_edge(child_entry) {
+        // Global and static variables, including the function-scope ones,
+        // are kept only if their data is still valid.
+        let data_address = read_data_address(child_entry, unit)?;
+        if has_die_back_edge(child_entry) && data_address.is_none() {
             deps.add_edge(offset, child_offset);
         }
         if has_valid_code_range(child_entry, dwarf, unit, at)? {
             deps.add_root(child_offset);
+            build_inlined_dependencies(
+                child_entry,
+                child_offset,
+                dwarf,
+                unit,
+                at,
+                context.subprograms,
+                deps,
+            )?;
+        } else if let Some(address) = data_address {
+            if address != 0 && (context.data_filter)(address) {
+                deps.add_root(child_offset);
+            }
+        } else if child_entry.tag() == constants::DW_TAG_subprogram
+            && read_code_ranges(child_entry, dwarf, unit)?.is_some()
+        {
+            deps.add_rejected_root(child_offset);
+        }
+        if is_filtered_root(child_entry, is_global_scope, context)? {
+            deps.add_root(child_offset);
+        }
+        build_die_dependencies(child, context, deps)?;
+    }
+    Ok(())
+}
+
+fn build_inlined_dependencies<R: Reader<Offset = usize>, A: AddressTranslator>(
+    die: &read::DebuggingInformationEntry<R>,
+    offset: UnitSectionOffset,
+    dwarf: &read::Dwarf<R>,
+    unit: &read::Unit<R>,
+    at: &A,
+    subprograms: &HashMap<u64, UnitSectionOffset>,
+    deps: &mut Dependencies,
+) -> read::Result<()> {
+    // The functions inlined into the subprogram will be referenced by
+    // the synthesized DW_TAG_inlined_subroutine entries.
+    if let Some(ranges) = read_code_ranges(die, dwarf, unit)? {
+        for (start, len) in ranges {
+            for inlined in at.translate_inlined_functions(start, len) {
+                if let Some(origin) = subprograms.get(&inlined.origin) {
+                    deps.add_edge(offset, *origin);
+                }
+            }
         }
-        build_die_dependencies(child, dwarf, unit, at, deps)?;
     }
     Ok(())
 }
@@ -231,3 +612,37 @@ fn build_attr_dependencies<R: Reader<Offset = usize>, A: AddressTranslator>(
     }
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use gimli::DebugInfoOffset;
+
+    fn die(offset: usize) -> UnitSectionOffset {
+        UnitSectionOffset::DebugInfoOffset(DebugInfoOffset(offset))
+    }
+
+    #[test]
+    fn test_find_path() {
+        let mut deps = Dependencies::new();
+        deps.add_root(die(1));
+        deps.add_root(die(2));
+        deps.add_edge(die(1), die(3));
+        deps.add_edge(die(3), die(4));
+        deps.add_edge(die(4), die(5));
+        deps.add_edge(die(2), die(5));
+        deps.add_edge(die(6), die(1));
+        deps.add_rejected_root(die(6));
+
+        assert_eq!(deps.find_path(die(1)), Some(vec![die(1)]));
+        assert_eq!(deps.find_path(die(4)), Some(vec![die(1), die(3), die(4)]));
+        // The shortest chain is found.
+        assert_eq!(deps.find_path(die(5)), Some(vec![die(2), die(5)]));
+        // The edges into the unreachable DIE are not followed backwards.
+        assert_eq!(deps.find_path(die(6)), None);
+        assert_eq!(deps.find_path(die(7)), None);
+
+        assert_eq!(deps.find_rejected_roots(die(4)), vec![die(6)]);
+        assert_eq!(deps.find_rejected_roots(die(2)), vec![]);
+    }
+}
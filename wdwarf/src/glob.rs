@@ -0,0 +1,49 @@
+// Simple wildcard matching: `*` matches any sequence of characters,
+// and `?` matches a single character.
+pub fn glob_match(pattern: &str, text: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let text: Vec<char> = text.chars().collect();
+    let (mut p, mut t) = (0, 0);
+    let mut backtrack = None;
+    while t < text.len() {
+        if p < pattern.len() && (pattern[p] == '?' || pattern[p] == text[t]) {
+            p += 1;
+            t += 1;
+        } else if p < pattern.len() && pattern[p] == '*' {
+            backtrack = Some((p, t));
+            p += 1;
+        } else if let Some((star_p, star_t)) = backtrack {
+            // Let the last `*` consume one more character.
+            backtrack = Some((star_p, star_t + 1));
+            p = star_p + 1;
+            t = star_t + 1;
+        } else {
+            return false;
+        }
+    }
+    pattern[p..].iter().all(|c| *c == '*')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_glob_match() {
+        assert!(glob_match("main", "main"));
+        assert!(!glob_match("main", "main2"));
+        assert!(!glob_match("main2", "main"));
+        assert!(glob_match("*", ""));
+        assert!(glob_match("*", "anything"));
+        assert!(glob_match("std::*", "std::vec::Vec"));
+        assert!(!glob_match("std::*", "core::vec::Vec"));
+        assert!(glob_match("*::new", "Vec::new"));
+        assert!(glob_match("f?o", "foo"));
+        assert!(!glob_match("f?o", "fo"));
+        // The `*` backtracks to match the last occurrence.
+        assert!(glob_match("*a*b", "aXbaYb"));
+        assert!(!glob_match("*a*b", "aXbaY"));
+        assert!(glob_match("a**", "a"));
+        assert!(!glob_match("", "a"));
+    }
+}
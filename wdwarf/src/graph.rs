@@ -0,0 +1,93 @@
+use gimli::read;
+use gimli::{Reader, UnitSectionOffset};
+use std::collections::HashSet;
+use std::vec::Vec;
+
+use crate::explain::read_die_name;
+use crate::gc::Dependencies;
+use crate::glob::glob_match;
+
+#[derive(Debug)]
+pub struct GraphNode {
+    pub offset: UnitSectionOffset,
+    pub unit: String,
+    pub tag: String,
+    pub name: Option<String>,
+    // Size of the DIE encoding in the .debug_info section, without children.
+    pub size: usize,
+    pub reachable: bool,
+    pub root: bool,
+}
+
+#[derive(Debug)]
+pub struct DependencyGraph {
+    pub nodes: Vec<GraphNode>,
+    pub edges: Vec<(UnitSectionOffset, UnitSectionOffset)>,
+}
+
+pub fn build_dependency_graph<R: Reader<Offset = usize>>(
+    dwarf: &read::Dwarf<R>,
+    deps: &Dependencies,
+    unit_filter: Option<&str>,
+    root_filter: Option<&[UnitSectionOffset]>,
+) -> read::Result<DependencyGraph> {
+    let reachable = deps.get_reachable();
+    let selected = root_filter.map(|roots| deps.get_reachable_from(roots));
+    let mut nodes = Vec::new();
+    let mut units = dwarf.units();
+    while let Some(header) = units.next()? {
+        let unit = dwarf.unit(header)?;
+        let unit_name = match unit.name {
+            Some(ref name) => name.to_string_lossy()?.into_owned(),
+            None => String::from("<unknown>"),
+        };
+        if let Some(pattern) = unit_filter {
+            if !glob_match(pattern, &unit_name) {
+                continue;
+            }
+        }
+        let unit_end = unit.header.length_including_self();
+        let mut unit_nodes: Vec<GraphNode> = Vec::new();
+        let mut entries = unit.entries();
+        while let Some((_, entry)) = entries.next_dfs()? {
+            let offset = entry.offset().to_unit_section_offset(&unit);
+            if let Some(last) = unit_nodes.last_mut() {
+                last.size = entry.offset().0 - unit_offset_value(&last.offset, &unit);
+            }
+            unit_nodes.push(GraphNode {
+                offset,
+                unit: unit_name.clone(),
+                tag: entry.tag().to_string(),
+                name: read_die_name(entry, dwarf, &unit)?,
+                size: 0,
+                reachable: reachable.contains(&offset),
+                root: deps.is_root(offset),
+            });
+        }
+        if let Some(last) = unit_nodes.last_mut() {
+            last.size = unit_end - unit_offset_value(&last.offset, &unit);
+        }
+        nodes.extend(unit_nodes.into_iter().filter(|node| match selected {
+            Some(ref selected) => selected.contains(&node.offset),
+            None => true,
+        }));
+    }
+
+    let included = nodes.iter().map(|node| node.offset).collect::<HashSet<_>>();
+    let mut edges = Vec::new();
+    for node in nodes.iter() {
+        for dep in deps.get_edges(node.offset) {
+            if included.contains(&dep) {
+                edges.push((node.offset, dep));
+            }
+        }
+    }
+    Ok(DependencyGraph { nodes, edges })
+}
+
+fn unit_offset_value<R: Reader<Offset = usize>>(
+    offset: &UnitSectionOffset,
+    unit: &read::Unit<R>,
+) -> usize {
+    offset.to_unit_offset(unit).expect("unit offset").0
+}
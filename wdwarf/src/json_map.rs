@@ -0,0 +1,105 @@
+use serde_json::Value;
+use std::fmt;
+use std::io::Read;
+
+use crate::address_translator::{AddressMap, OriginalAddress, TargetAddress};
+
+// The source maps-like transform errors: the JSON cannot be parsed, or it is
+// not the version 3 map with a single line of mappings.
+#[derive(Debug)]
+pub enum JsonMapError {
+    Json(serde_json::Error),
+    InvalidVersion,
+    InvalidMappings,
+    InvalidSegment(vlq::Error),
+}
+
+impl From<serde_json::Error> for JsonMapError {
+    fn from(e: serde_json::Error) -> Self {
+        JsonMapError::Json(e)
+    }
+}
+
+impl From<vlq::Error> for JsonMapError {
+    fn from(e: vlq::Error) -> Self {
+        JsonMapError::InvalidSegment(e)
+    }
+}
+
+impl fmt::Display for JsonMapError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            JsonMapError::Json(e) => write!(f, "{}", e),
+            JsonMapError::InvalidVersion => write!(f, "invalid map version"),
+            JsonMapError::InvalidMappings => write!(f, "invalid mappings"),
+            JsonMapError::InvalidSegment(e) => write!(f, "invalid mappings segment: {:?}", e),
+        }
+    }
+}
+
+pub type JsonMapResult<T> = Result<T, JsonMapError>;
+
+// Reads the transform: the generated column of every segment is the target
+// module offset, and the original column is the original code address.
+pub fn read_json_map_transform<R: Read>(
+    reader: R,
+    code_section_offset: u64,
+) -> JsonMapResult<AddressMap> {
+    let map: Value = serde_json::from_reader(reader)?;
+    if map["version"] != 3 {
+        return Err(JsonMapError::InvalidVersion);
+    }
+    let mappings = match map["mappings"].as_str() {
+        Some(mappings) if !mappings.contains(';') => mappings,
+        _ => return Err(JsonMapError::InvalidMappings),
+    };
+
+    let mut decoded = AddressMap::new();
+    let mut last_addr = -(code_section_offset as i64);
+    let mut last_col = 0;
+    for entry in mappings.split(',') {
+        let mut it = entry.bytes();
+        let addr_delta = vlq::decode(&mut it)?;
+        let _source = vlq::decode(&mut it)?;
+        let _line = vlq::decode(&mut it)?;
+        let col_delta = vlq::decode(&mut it)?;
+        last_addr += addr_delta;
+        last_col += col_delta;
+        decoded.insert(
+            TargetAddress(last_addr as u64),
+            OriginalAddress(last_col as u64),
+        );
+    }
+    Ok(decoded)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn read(json: &str) -> JsonMapResult<AddressMap> {
+        read_json_map_transform(json.as_bytes(), 8)
+    }
+
+    #[test]
+    fn test_read_json_map_transform() {
+        let map =
+            read(r#"{"version": 3, "sources": [], "names": [], "mappings": "UAAC,EAAE"}"#).unwrap();
+        // The target offsets are relative to the code section.
+        assert_eq!(
+            map.pairs(),
+            vec![
+                (OriginalAddress(1), TargetAddress(2)),
+                (OriginalAddress(3), TargetAddress(4)),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_read_json_map_transform_errors() {
+        assert!(read("{").is_err());
+        assert!(read(r#"{"version": 2, "mappings": "UAAC"}"#).is_err());
+        assert!(read(r#"{"version": 3, "mappings": "UAAC;EAAE"}"#).is_err());
+        assert!(read(r#"{"version": 3, "mappings": "UA"}"#).is_err());
+    }
+}
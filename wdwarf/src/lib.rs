@@ -1,12 +1,40 @@
 mod address_translator;
 mod convert;
+mod demangle;
+mod diagnostics;
+mod explain;
 mod gc;
+mod glob;
+mod graph;
+mod json_map;
+mod line_source;
+mod odr;
+mod stats;
+mod symbolize;
+#[cfg(test)]
+mod test_util;
+mod verify;
 mod wasm;
 
 pub use address_translator::{
-    AddressMap, AddressTranslator, IdentityAddressTranslator, OriginalAddress, TargetAddress,
-    TranformAddressTranslator,
+    AddressMap, AddressTranslator, FunctionAddressTranslator, IdentityAddressTranslator,
+    InlinedFunction, OriginalAddress, ReverseAddressMap, TargetAddress, TranformAddressTranslator,
+};
+pub use convert::{from_dwarf, strip_path_prefix, ConvertOptions, ConvertReport, DebugInfoLevel};
+pub use demangle::demangle;
+pub use diagnostics::{Diagnostic, DiagnosticCode, Diagnostics, Severity};
+pub use explain::{describe_die, find_dies_by_address, find_dies_by_name, is_die_offset};
+pub use gc::{build_dependencies, Dependencies, RootFilter};
+pub use glob::glob_match;
+pub use graph::{build_dependency_graph, DependencyGraph, GraphNode};
+pub use json_map::{read_json_map_transform, JsonMapError, JsonMapResult};
+pub use line_source::{EmbedSourcesError, SourceFile};
+pub use stats::Statistics;
+pub use symbolize::{Frame, FunctionInfo, InlinedCall, LineRange, Symbolizer};
+pub use verify::verify_dwarf;
+pub use wasm::{
+    create_dwarf_sections, create_dwarf_sections_with_sources, is_dwarf_section, load_dwarf,
+    read_build_id, read_code_section, read_custom_section, read_dwarf, read_dwarf_sections,
+    read_external_debug_info, write_custom_section, write_leb128, CodeSection, ReadError,
+    ReadResult, BUILD_ID, EXTERNAL_DEBUG_INFO,
 };
-pub use convert::from_dwarf;
-pub use gc::build_dependencies;
-pub use wasm::{create_dwarf_sections, read_dwarf};
@@ -0,0 +1,500 @@
+use gimli::constants;
+use gimli::read::{self, ReaderOffsetId};
+use gimli::write::{self, EndianVec, Result, Writer};
+use gimli::{LittleEndian, SectionId};
+use std::collections::HashMap;
+use std::fmt;
+use std::vec::Vec;
+
+// The LLVM extension for the source file contents in the file entries.
+const DW_LNCT_LLVM_SOURCE: constants::DwLnct = constants::DwLnct(0x2001);
+
+// Writes into the vector, and remembers the locations (and sizes) of the
+// .debug_line offsets, so they can be fixed after the line programs are changed.
+#[derive(Debug, Clone)]
+pub(crate) struct LineRefsWriter {
+    data: EndianVec<LittleEndian>,
+    pub line_refs: Vec<(usize, u8)>,
+}
+
+impl LineRefsWriter {
+    pub fn new() -> Self {
+        LineRefsWriter {
+            data: EndianVec::new(LittleEndian),
+            line_refs: Vec::new(),
+        }
+    }
+
+    pub fn slice(&self) -> &[u8] {
+        self.data.slice()
+    }
+}
+
+impl Writer for LineRefsWriter {
+    type Endian = LittleEndian;
+
+    fn endian(&self) -> Self::Endian {
+        LittleEndian
+    }
+
+    fn len(&self) -> usize {
+        self.data.len()
+    }
+
+    fn write(&mut self, bytes: &[u8]) -> Result<()> {
+        self.data.write(bytes)
+    }
+
+    fn write_at(&mut self, offset: usize, bytes: &[u8]) -> Result<()> {
+        self.data.write_at(offset, bytes)
+    }
+
+    fn write_offset(&mut self, val: usize, section: SectionId, size: u8) -> Result<()> {
+        if section == SectionId::DebugLine {
+            self.line_refs.push((self.data.len(), size));
+        }
+        self.data.write_udata(val as u64, size)
+    }
+
+    fn write_offset_at(
+        &mut self,
+        offset: usize,
+        val: usize,
+        section: SectionId,
+        size: u8,
+    ) -> Result<()> {
+        if section == SectionId::DebugLine {
+            self.line_refs.push((offset, size));
+        }
+        self.data.write_udata_at(offset, val as u64, size)
+    }
+}
+
+// The error of the writing of the sections with the embedded sources: the line
+// programs are written, and then parsed again to add the sources.
+#[derive(Debug)]
+pub enum EmbedSourcesError {
+    Write(write::Error),
+    Read(read::Error),
+    // The line programs are rewritten with the 32-bit offsets and lengths.
+    Dwarf64,
+}
+
+impl From<write::Error> for EmbedSourcesError {
+    fn from(e: write::Error) -> Self {
+        EmbedSourcesError::Write(e)
+    }
+}
+
+impl From<read::Error> for EmbedSourcesError {
+    fn from(e: read::Error) -> Self {
+        EmbedSourcesError::Read(e)
+    }
+}
+
+impl fmt::Display for EmbedSourcesError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            EmbedSourcesError::Write(e) => write!(f, "{}", e),
+            EmbedSourcesError::Read(e) => write!(f, "{}", e),
+            EmbedSourcesError::Dwarf64 => {
+                write!(f, "the sources cannot be embedded into the 64-bit DWARF")
+            }
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct SourceFile<'a> {
+    // The file path, combined with its directory and the compilation directory.
+    pub path: &'a str,
+    pub md5: Option<[u8; 16]>,
+}
+
+struct Input<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Input<'a> {
+    fn bytes(&mut self, len: usize) -> read::Result<&'a [u8]> {
+        if self.pos + len > self.data.len() {
+            return Err(read::Error::UnexpectedEof(ReaderOffsetId(self.pos as u64)));
+        }
+        let result = &self.data[self.pos..self.pos + len];
+        self.pos += len;
+        Ok(result)
+    }
+
+    fn u8(&mut self) -> read::Result<u8> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    fn u16(&mut self) -> read::Result<u16> {
+        let b = self.bytes(2)?;
+        Ok(u16::from(b[0]) | (u16::from(b[1]) << 8))
+    }
+
+    fn u32(&mut self) -> read::Result<u32> {
+        let b = self.bytes(4)?;
+        Ok(u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+    }
+
+    fn uleb128(&mut self) -> read::Result<u64> {
+        let mut result = 0;
+        let mut shift = 0;
+        loop {
+            let byte = self.u8()?;
+            if shift == 63 && byte > 1 {
+                return Err(read::Error::BadUnsignedLeb128);
+            }
+            result |= u64::from(byte & 0x7f) << shift;
+            if byte & 0x80 == 0 {
+                return Ok(result);
+            }
+            shift += 7;
+        }
+    }
+
+    fn cstr(&mut self) -> read::Result<&'a [u8]> {
+        let len = match self.data[self.pos..].iter().position(|b| *b == 0) {
+            Some(len) => len,
+            None => return Err(read::Error::UnexpectedEof(ReaderOffsetId(self.pos as u64))),
+        };
+        let result = self.bytes(len)?;
+        self.pos += 1;
+        Ok(result)
+    }
+}
+
+fn read_cstr_at(data: &[u8], offset: usize) -> &[u8] {
+    let data = data.get(offset..).unwrap_or(&[]);
+    let len = data.iter().position(|b| *b == 0).unwrap_or(data.len());
+    &data[..len]
+}
+
+enum FormValue<'a> {
+    String(&'a [u8]),
+    Udata(u64),
+    Block(&'a [u8]),
+}
+
+fn read_form_value<'a>(
+    input: &mut Input<'a>,
+    form: u64,
+    debug_str: &'a [u8],
+    debug_line_str: &'a [u8],
+) -> read::Result<FormValue<'a>> {
+    let form = constants::DwForm(form);
+    Ok(match form {
+        constants::DW_FORM_string => FormValue::String(input.cstr()?),
+        constants::DW_FORM_line_strp => {
+            FormValue::String(read_cstr_at(debug_line_str, input.u32()? as usize))
+        }
+        constants::DW_FORM_strp => {
+            FormValue::String(read_cstr_at(debug_str, input.u32()? as usize))
+        }
+        constants::DW_FORM_udata => FormValue::Udata(input.uleb128()?),
+        constants::DW_FORM_data1 => FormValue::Udata(u64::from(input.u8()?)),
+        constants::DW_FORM_data2 => FormValue::Udata(u64::from(input.u16()?)),
+        constants::DW_FORM_data4 => FormValue::Udata(u64::from(input.u32()?)),
+        constants::DW_FORM_data8 => FormValue::Block(input.bytes(8)?),
+        constants::DW_FORM_data16 => FormValue::Block(input.bytes(16)?),
+        constants::DW_FORM_block => {
+            let len = input.uleb128()? as usize;
+            FormValue::Block(input.bytes(len)?)
+        }
+        _ => return Err(read::Error::UnknownForm),
+    })
+}
+
+fn write_uleb128(out: &mut Vec<u8>, mut value: u64) {
+    loop {
+        let mut byte = (value & 0x7f) as u8;
+        value >>= 7;
+        if value != 0 {
+            byte |= 0x80;
+        }
+        out.push(byte);
+        if value == 0 {
+            break;
+        }
+    }
+}
+
+fn join_path(dir: &[u8], name: &[u8]) -> Vec<u8> {
+    if name.starts_with(b"/") || dir.is_empty() {
+        return name.to_vec();
+    }
+    let mut path = dir.to_vec();
+    if !path.ends_with(b"/") {
+        path.push(b'/');
+    }
+    path.extend_from_slice(name);
+    path
+}
+
+type EntryFormat = Vec<(u64, u64)>;
+
+fn read_entry_format(input: &mut Input) -> read::Result<EntryFormat> {
+    let count = input.u8()?;
+    let mut format = Vec::new();
+    for _ in 0..count {
+        format.push((input.uleb128()?, input.uleb128()?));
+    }
+    Ok(format)
+}
+
+// Adds the DW_LNCT_LLVM_source content to the file entries of the version 5
+// line program. Returns None if the program cannot be changed.
+fn embed_program_sources<F: FnMut(&SourceFile) -> Option<Vec<u8>>>(
+    program: &[u8],
+    debug_str: &[u8],
+    debug_line_str: &[u8],
+    sources: &mut F,
+) -> read::Result<Option<Vec<u8>>> {
+    let mut input = Input {
+        data: program,
+        pos: 0,
+    };
+    let unit_length = input.u32()?;
+    if unit_length == 0xffff_ffff || input.u16()? != 5 {
+        return Ok(None);
+    }
+    let _address_size = input.u8()?;
+    let _segment_selector_size = input.u8()?;
+    let header_length = input.u32()? as usize;
+    let header_start = input.pos;
+    let _minimum_instruction_length = input.u8()?;
+    let _maximum_operations_per_instruction = input.u8()?;
+    let _default_is_stmt = input.u8()?;
+    let _line_base = input.u8()?;
+    let _line_range = input.u8()?;
+    let opcode_base = input.u8()?;
+    input.bytes(opcode_base.saturating_sub(1) as usize)?;
+
+    let directory_format = read_entry_format(&mut input)?;
+    let directory_count = input.uleb128()?;
+    let mut directories = Vec::new();
+    for _ in 0..directory_count {
+        let mut path = &[][..];
+        for (content, form) in directory_format.iter() {
+            let value = read_form_value(&mut input, *form, debug_str, debug_line_str)?;
+            if let (constants::DW_LNCT_path, FormValue::String(s)) =
+                (constants::DwLnct(*content as u16), value)
+            {
+                path = s;
+            }
+        }
+        directories.push(path);
+    }
+
+    let file_format_start = input.pos;
+    let file_format = read_entry_format(&mut input)?;
+    if file_format
+        .iter()
+        .any(|(content, _)| *content == u64::from(DW_LNCT_LLVM_SOURCE.0))
+    {
+        return Ok(None);
+    }
+    let file_format_end = input.pos;
+    let file_count = input.uleb128()?;
+    let files_start = input.pos;
+    let mut files = Vec::new();
+    for _ in 0..file_count {
+        let entry_start = input.pos;
+        let (mut path, mut directory, mut md5) = (&[][..], 0, None);
+        for (content, form) in file_format.iter() {
+            let value = read_form_value(&mut input, *form, debug_str, debug_line_str)?;
+            match (constants::DwLnct(*content as u16), value) {
+                (constants::DW_LNCT_path, FormValue::String(s)) => path = s,
+                (constants::DW_LNCT_directory_index, FormValue::Udata(i)) => directory = i,
+                (constants::DW_LNCT_MD5, FormValue::Block(b)) if b.len() == 16 => {
+                    let mut digest = [0; 16];
+                    digest.copy_from_slice(b);
+                    md5 = Some(digest);
+                }
+                _ => (),
+            }
+        }
+        // The relative directories are relative to the compilation directory.
+        let mut dir = directories
+            .get(directory as usize)
+            .cloned()
+            .unwrap_or(&[])
+            .to_vec();
+        if directory != 0 && !dir.starts_with(b"/") {
+            dir = join_path(directories.first().cloned().unwrap_or(&[]), &dir);
+        }
+        let path = String::from_utf8_lossy(&join_path(&dir, path)).into_owned();
+        let source = sources(&SourceFile { path: &path, md5 });
+        files.push((&program[entry_start..input.pos], source));
+    }
+    let header_end = header_start + header_length;
+    if input.pos != header_end {
+        return Ok(None);
+    }
+
+    let mut header = Vec::new();
+    header.extend_from_slice(&program[header_start..file_format_start]);
+    header.push(file_format.len() as u8 + 1);
+    header.extend_from_slice(&program[file_format_start + 1..file_format_end]);
+    write_uleb128(&mut header, u64::from(DW_LNCT_LLVM_SOURCE.0));
+    write_uleb128(&mut header, constants::DW_FORM_string.0);
+    header.extend_from_slice(&program[file_format_end..files_start]);
+    for (entry, source) in files {
+        header.extend_from_slice(entry);
+        // The source text is NUL-terminated, an empty string means no source.
+        // The text with NUL bytes cannot be embedded unchanged (and would not
+        // match its MD5 checksum), so it is not embedded.
+        if let Some(source) = source.filter(|source| !source.contains(&0)) {
+            header.extend(source);
+        }
+        header.push(0);
+    }
+
+    let mut result = Vec::new();
+    let body = &program[header_end..];
+    let unit_length = 2 + 1 + 1 + 4 + header.len() + body.len();
+    result.extend_from_slice(&(unit_length as u32).to_le_bytes());
+    result.extend_from_slice(&program[4..header_start - 4]);
+    result.extend_from_slice(&(header.len() as u32).to_le_bytes());
+    result.extend_from_slice(&header);
+    result.extend_from_slice(body);
+    Ok(Some(result))
+}
+
+// Rewrites the version 5 line programs with the embedded sources, and returns
+// the new section and the mapping of the moved line programs offsets.
+pub(crate) fn embed_line_sources<F: FnMut(&SourceFile) -> Option<Vec<u8>>>(
+    debug_line: &[u8],
+    debug_str: &[u8],
+    debug_line_str: &[u8],
+    mut sources: F,
+) -> std::result::Result<(Vec<u8>, HashMap<usize, usize>), EmbedSourcesError> {
+    let mut result = Vec::new();
+    let mut offsets = HashMap::new();
+    let mut pos = 0;
+    while pos + 4 <= debug_line.len() {
+        let mut input = Input {
+            data: debug_line,
+            pos,
+        };
+        let unit_length = input.u32()?;
+        if unit_length == 0xffff_ffff {
+            return Err(EmbedSourcesError::Dwarf64);
+        }
+        let end = pos + 4 + unit_length as usize;
+        if end > debug_line.len() {
+            return Err(read::Error::BadLength.into());
+        }
+        let program = &debug_line[pos..end];
+        offsets.insert(pos, result.len());
+        match embed_program_sources(program, debug_str, debug_line_str, &mut sources)? {
+            Some(program) => result.extend_from_slice(&program),
+            None => result.extend_from_slice(program),
+        }
+        pos = end;
+    }
+    Ok((result, offsets))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::{read_module, ENCODING};
+    use crate::wasm::create_dwarf_sections_with_sources;
+    use gimli::write::{Address, LineProgram, LineString, Unit};
+    use gimli::{Encoding, Format, Section};
+
+    fn write_dwarf(encoding: Encoding) -> write::Dwarf {
+        let string = |s: &str| LineString::String(s.as_bytes().to_vec());
+        let mut program = LineProgram::new(
+            encoding,
+            gimli::LineEncoding::default(),
+            string("/src"),
+            string("a.c"),
+            None,
+        );
+        let dir = program.default_directory();
+        program.add_file(string("b.h"), dir, None);
+        let a = program.add_file(string("a.c"), dir, None);
+        program.begin_sequence(Some(Address::Constant(0x10)));
+        program.row().file = a;
+        program.row().line = 3;
+        program.generate_row();
+        program.end_sequence(8);
+        let mut dwarf = write::Dwarf::new();
+        dwarf.units.add(Unit::new(encoding, program));
+        dwarf
+    }
+
+    #[test]
+    fn test_embed_sources() {
+        let encoding = Encoding {
+            version: 5,
+            ..ENCODING
+        };
+        let mut dwarf = write_dwarf(encoding);
+        let mut paths = Vec::new();
+        let mut bin = vec![0, b'a', b's', b'm', 1, 0, 0, 0];
+        bin.extend(
+            create_dwarf_sections_with_sources(&mut dwarf, |file| {
+                paths.push(file.path.to_string());
+                if file.path == "/src/a.c" {
+                    Some(b"int a;\n".to_vec())
+                } else {
+                    None
+                }
+            })
+            .unwrap(),
+        );
+        assert_eq!(paths, vec!["/src/a.c", "/src/b.h", "/src/a.c"]);
+
+        // The DW_AT_stmt_list still points to the program, and the rows and
+        // the file entries are read as before.
+        let dwarf = read_module(&bin);
+        let unit = dwarf.unit(dwarf.units().next().unwrap().unwrap()).unwrap();
+        let program = unit.line_program.clone().unwrap();
+        let header = program.header();
+        assert!(header
+            .file_name_entry_format()
+            .iter()
+            .any(|format| format.content_type == DW_LNCT_LLVM_SOURCE
+                && format.form == constants::DW_FORM_string));
+        let names = header
+            .file_names()
+            .iter()
+            .map(|file| {
+                let name = dwarf.attr_string(&unit, file.path_name()).unwrap();
+                name.to_string_lossy().into_owned()
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(names, vec!["a.c", "b.h", "a.c"]);
+        let mut rows = program.clone().rows();
+        let (_, row) = rows.next_row().unwrap().unwrap();
+        assert_eq!(
+            (row.address(), row.file_index(), row.line()),
+            (0x10, 2, Some(3))
+        );
+
+        // The source of the last file entry ends the header.
+        let debug_line = dwarf.debug_line.reader().slice();
+        let header_end = debug_line.len() - header.raw_program_buf().len();
+        assert!(debug_line[..header_end].ends_with(b"int a;\n\0"));
+    }
+
+    #[test]
+    fn test_embed_sources_dwarf64() {
+        let encoding = Encoding {
+            version: 5,
+            format: Format::Dwarf64,
+            ..ENCODING
+        };
+        let mut dwarf = write_dwarf(encoding);
+        match create_dwarf_sections_with_sources(&mut dwarf, |_| None) {
+            Err(EmbedSourcesError::Dwarf64) => (),
+            result => panic!("unexpected result {:?}", result.map(|_| ())),
+        }
+    }
+}
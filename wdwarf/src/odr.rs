@@ -0,0 +1,434 @@
+use gimli::constants;
+use gimli::read::{self, AttributeValue};
+use gimli::{DwTag, Reader, UnitSectionOffset};
+use std::collections::HashMap;
+use std::vec::Vec;
+
+use crate::explain::offset_value;
+use crate::gc::is_type;
+
+// The type identity: the declaration context path, the tag, the name, and
+// the shape of the type subtree.
+type TypeKey = (String, DwTag, String, Vec<TypeShapeItem>);
+// The depth, tag, name, byte size, member location, and the identity of
+// the referenced (DW_AT_type) type of the subtree entry.
+type TypeShapeItem = (
+    usize,
+    DwTag,
+    Option<String>,
+    Option<u64>,
+    Option<u64>,
+    Option<String>,
+);
+
+// The unnamed types (e.g. pointers) are identified by the types they refer
+// to, up to this depth.
+const MAX_TYPE_REF_DEPTH: usize = 8;
+
+// Finds the named types that are defined more than once in the same
+// declaration context (in the spirit of the ODR), and maps the entries of
+// the duplicate type subtrees to the corresponding entries of the first
+// (canonical) definition.
+pub(crate) fn collect_odr_duplicates<
+    R: Reader<Offset = usize>,
+    F: Fn(UnitSectionOffset) -> bool,
+>(
+    dwarf: &read::Dwarf<R>,
+    die_filter: &F,
+) -> read::Result<HashMap<UnitSectionOffset, UnitSectionOffset>> {
+    let type_names = collect_type_names(dwarf)?;
+    let mut canonical = HashMap::new();
+    let mut duplicates = HashMap::new();
+    let mut units = dwarf.units();
+    while let Some(header) = units.next()? {
+        let unit = dwarf.unit(header)?;
+        let mut tree = unit.entries_tree(None)?;
+        let root = tree.root()?;
+        // Other languages (e.g. C) have no ODR: the types of the same name can
+        // be different in the different units.
+        if !has_odr(root.entry())? {
+            continue;
+        }
+        let mut context = DuplicatesContext {
+            dwarf,
+            unit: &unit,
+            die_filter,
+            type_names: &type_names,
+            canonical: &mut canonical,
+            duplicates: &mut duplicates,
+        };
+        collect_die_duplicates(&mut context, root, Some(String::new()))?;
+    }
+    Ok(duplicates)
+}
+
+fn has_odr<R: Reader<Offset = usize>>(
+    root: &read::DebuggingInformationEntry<R>,
+) -> read::Result<bool> {
+    Ok(matches!(
+        root.attr_value(constants::DW_AT_language)?,
+        Some(AttributeValue::Language(
+            constants::DW_LANG_C_plus_plus
+                | constants::DW_LANG_C_plus_plus_03
+                | constants::DW_LANG_C_plus_plus_11
+                | constants::DW_LANG_C_plus_plus_14
+                | constants::DW_LANG_Rust
+        ))
+    ))
+}
+
+// Maps the named types declared outside of functions to their qualified
+// names, which identify the types referenced from the type subtrees. The
+// anonymous namespaces are unique to their units.
+fn collect_type_names<R: Reader<Offset = usize>>(
+    dwarf: &read::Dwarf<R>,
+) -> read::Result<HashMap<UnitSectionOffset, String>> {
+    let mut names = HashMap::new();
+    let mut units = dwarf.units();
+    while let Some(header) = units.next()? {
+        let unit = dwarf.unit(header)?;
+        let mut tree = unit.entries_tree(None)?;
+        let root = tree.root()?;
+        collect_die_type_names(root, String::new(), dwarf, &unit, &mut names)?;
+    }
+    Ok(names)
+}
+
+fn collect_die_type_names<R: Reader<Offset = usize>>(
+    node: read::EntriesTreeNode<R>,
+    context_path: String,
+    dwarf: &read::Dwarf<R>,
+    unit: &read::Unit<R>,
+    names: &mut HashMap<UnitSectionOffset, String>,
+) -> read::Result<()> {
+    let mut children = node.children();
+    while let Some(child) = children.next()? {
+        let entry = child.entry();
+        let child_path = match read_name(entry, dwarf, unit)? {
+            Some(name) if is_type(entry) || has_decl_context(entry.tag()) => {
+                let path = format!("{}::{}:{}", context_path, entry.tag().0, name);
+                if is_type(entry) {
+                    names.insert(entry.offset().to_unit_section_offset(unit), path.clone());
+                }
+                path
+            }
+            None if entry.tag() == constants::DW_TAG_namespace => format!(
+                "{}::(anonymous 0x{:x})",
+                context_path,
+                offset_value(unit.offset)
+            ),
+            _ => continue,
+        };
+        if has_decl_context(entry.tag()) {
+            collect_die_type_names(child, child_path, dwarf, unit, names)?;
+        }
+    }
+    Ok(())
+}
+
+// Identifies the type referenced by the entry: by the qualified name of
+// the named type, or by the tag and the referenced type of the unnamed
+// modifier type. Other types are identified by their offsets, so their
+// referrers are never merged.
+fn read_type_ref<R: Reader<Offset = usize>>(
+    die: &read::DebuggingInformationEntry<R>,
+    depth: usize,
+    unit: &read::Unit<R>,
+    type_names: &HashMap<UnitSectionOffset, String>,
+) -> read::Result<Option<String>> {
+    let target = match die.attr_value(constants::DW_AT_type)? {
+        Some(AttributeValue::UnitRef(offset)) => offset,
+        Some(AttributeValue::DebugInfoRef(offset)) => {
+            let target = UnitSectionOffset::DebugInfoOffset(offset);
+            return Ok(Some(match type_names.get(&target) {
+                Some(name) => name.clone(),
+                None => format!("0x{:x}", offset.0),
+            }));
+        }
+        Some(value) => return Ok(Some(format!("{:?}", value))),
+        None => return Ok(None),
+    };
+    let target_offset = target.to_unit_section_offset(unit);
+    if let Some(name) = type_names.get(&target_offset) {
+        return Ok(Some(name.clone()));
+    }
+    let mut entries = unit.entries_at_offset(target)?;
+    let entry = match entries.next_dfs()? {
+        Some((_, entry)) if is_modifier_type(entry.tag()) && depth < MAX_TYPE_REF_DEPTH => entry,
+        _ => return Ok(Some(format!("0x{:x}", offset_value(target_offset)))),
+    };
+    let inner = read_type_ref(entry, depth + 1, unit, type_names)?;
+    Ok(Some(format!(
+        "{}({})",
+        entry.tag().0,
+        inner.unwrap_or_default()
+    )))
+}
+
+fn read_name<R: Reader<Offset = usize>>(
+    die: &read::DebuggingInformationEntry<R>,
+    dwarf: &read::Dwarf<R>,
+    unit: &read::Unit<R>,
+) -> read::Result<Option<String>> {
+    match die.attr_value(constants::DW_AT_name)? {
+        Some(name) => {
+            let name = dwarf.attr_string(unit, name)?;
+            Ok(Some(name.to_string_lossy()?.into_owned()))
+        }
+        None => Ok(None),
+    }
+}
+
+fn read_shape_item<R: Reader<Offset = usize>>(
+    die: &read::DebuggingInformationEntry<R>,
+    depth: usize,
+    dwarf: &read::Dwarf<R>,
+    unit: &read::Unit<R>,
+    type_names: &HashMap<UnitSectionOffset, String>,
+) -> read::Result<TypeShapeItem> {
+    let byte_size = match die.attr(constants::DW_AT_byte_size)? {
+        Some(attr) => attr.udata_value(),
+        None => None,
+    };
+    let member_location = match die.attr(constants::DW_AT_data_member_location)? {
+        Some(attr) => attr.udata_value(),
+        None => None,
+    };
+    Ok((
+        depth,
+        die.tag(),
+        read_name(die, dwarf, unit)?,
+        byte_size,
+        member_location,
+        read_type_ref(die, 0, unit, type_names)?,
+    ))
+}
+
+// The state of the duplicates search in a unit.
+struct DuplicatesContext<'a, R: Reader<Offset = usize>, F: Fn(UnitSectionOffset) -> bool> {
+    dwarf: &'a read::Dwarf<R>,
+    unit: &'a read::Unit<R>,
+    die_filter: &'a F,
+    type_names: &'a HashMap<UnitSectionOffset, String>,
+    canonical: &'a mut HashMap<TypeKey, Vec<UnitSectionOffset>>,
+    duplicates: &'a mut HashMap<UnitSectionOffset, UnitSectionOffset>,
+}
+
+fn read_type_shape<R: Reader<Offset = usize>, F: Fn(UnitSectionOffset) -> bool>(
+    context: &DuplicatesContext<R, F>,
+    node: read::EntriesTreeNode<R>,
+    depth: usize,
+    shape: &mut Vec<TypeShapeItem>,
+    offsets: &mut Vec<UnitSectionOffset>,
+) -> read::Result<()> {
+    let unit = context.unit;
+    shape.push(read_shape_item(
+        node.entry(),
+        depth,
+        context.dwarf,
+        unit,
+        context.type_names,
+    )?);
+    offsets.push(node.entry().offset().to_unit_section_offset(unit));
+    let mut children = node.children();
+    while let Some(child) = children.next()? {
+        if !(context.die_filter)(child.entry().offset().to_unit_section_offset(unit)) {
+            continue;
+        }
+        read_type_shape(context, child, depth + 1, shape, offsets)?;
+    }
+    Ok(())
+}
+
+fn is_modifier_type(tag: DwTag) -> bool {
+    matches!(
+        tag,
+        constants::DW_TAG_pointer_type
+            | constants::DW_TAG_reference_type
+            | constants::DW_TAG_rvalue_reference_type
+            | constants::DW_TAG_const_type
+            | constants::DW_TAG_volatile_type
+            | constants::DW_TAG_restrict_type
+            | constants::DW_TAG_atomic_type
+    )
+}
+
+fn has_decl_context(tag: DwTag) -> bool {
+    matches!(
+        tag,
+        constants::DW_TAG_namespace
+            | constants::DW_TAG_module
+            | constants::DW_TAG_structure_type
+            | constants::DW_TAG_class_type
+            | constants::DW_TAG_union_type
+            | constants::DW_TAG_enumeration_type
+    )
+}
+
+fn collect_die_duplicates<R: Reader<Offset = usize>, F: Fn(UnitSectionOffset) -> bool>(
+    context: &mut DuplicatesContext<R, F>,
+    node: read::EntriesTreeNode<R>,
+    context_path: Option<String>,
+) -> read::Result<()> {
+    let unit = context.unit;
+    let mut children = node.children();
+    while let Some(child) = children.next()? {
+        let entry = child.entry();
+        let offset = entry.offset().to_unit_section_offset(unit);
+        if !(context.die_filter)(offset) {
+            continue;
+        }
+        let name = read_name(entry, context.dwarf, unit)?;
+        // Only the types declared outside of functions can be merged. The types
+        // of the anonymous namespaces are local to their units, and are never
+        // merged.
+        let child_path = match (&context_path, &name) {
+            (Some(path), Some(name)) if has_decl_context(entry.tag()) => {
+                Some(format!("{}::{}:{}", path, entry.tag().0, name))
+            }
+            _ => None,
+        };
+        let is_candidate = context_path.is_some()
+            && name.is_some()
+            && is_type(entry)
+            && entry.attr(constants::DW_AT_declaration)?.is_none();
+        if !is_candidate {
+            collect_die_duplicates(context, child, child_path)?;
+            continue;
+        }
+
+        let tag = entry.tag();
+        let (mut shape, mut offsets) = (Vec::new(), Vec::new());
+        let mut tree = unit.entries_tree(Some(entry.offset()))?;
+        read_type_shape(context, tree.root()?, 0, &mut shape, &mut offsets)?;
+        let key = (context_path.clone().unwrap(), tag, name.unwrap(), shape);
+        match context.canonical.get(&key) {
+            Some(canonical_offsets) => {
+                for (duplicate, original) in offsets.into_iter().zip(canonical_offsets.iter()) {
+                    context.duplicates.insert(duplicate, *original);
+                }
+            }
+            None => {
+                context.canonical.insert(key, offsets);
+                collect_die_duplicates(context, child, child_path)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::{add_entry, read_module, write_module, ENCODING};
+    use gimli::write::{self, LineProgram, Unit, UnitEntryId};
+    use gimli::DwLang;
+
+    fn add_unit(dwarf: &mut write::Dwarf, language: DwLang) -> &mut Unit {
+        let unit_id = dwarf.units.add(Unit::new(ENCODING, LineProgram::none()));
+        let unit = dwarf.units.get_mut(unit_id);
+        let root = unit.root();
+        unit.get_mut(root).set(
+            constants::DW_AT_language,
+            write::AttributeValue::Language(language),
+        );
+        unit
+    }
+
+    fn add_base_type(unit: &mut Unit, name: &str, size: u64) -> UnitEntryId {
+        let root = unit.root();
+        let id = add_entry(unit, root, constants::DW_TAG_base_type, name);
+        unit.get_mut(id).set(
+            constants::DW_AT_byte_size,
+            write::AttributeValue::Udata(size),
+        );
+        id
+    }
+
+    // Adds `struct <name> { <type> a; }` to the parent.
+    fn add_struct(unit: &mut Unit, parent: UnitEntryId, name: &str, member_type: UnitEntryId) {
+        let id = add_entry(unit, parent, constants::DW_TAG_structure_type, name);
+        unit.get_mut(id)
+            .set(constants::DW_AT_byte_size, write::AttributeValue::Udata(4));
+        let member = add_entry(unit, id, constants::DW_TAG_member, "a");
+        let member = unit.get_mut(member);
+        member.set(
+            constants::DW_AT_type,
+            write::AttributeValue::ThisUnitEntryRef(member_type),
+        );
+        member.set(
+            constants::DW_AT_data_member_location,
+            write::AttributeValue::Udata(0),
+        );
+    }
+
+    // Adds the unit with the structs, and returns the names of the merged
+    // duplicate types.
+    fn duplicate_names<B: Fn(&mut Unit)>(language: DwLang, build: B) -> Vec<String> {
+        let mut dwarf = write::Dwarf::new();
+        build(add_unit(&mut dwarf, constants::DW_LANG_C_plus_plus));
+        build(add_unit(&mut dwarf, language));
+        let bin = write_module(&mut dwarf);
+        let dwarf = read_module(&bin);
+        let duplicates = collect_odr_duplicates(&dwarf, &|_| true).unwrap();
+
+        let mut names = Vec::new();
+        let mut units = dwarf.units();
+        while let Some(header) = units.next().unwrap() {
+            let unit = dwarf.unit(header).unwrap();
+            let mut entries = unit.entries();
+            while let Some((_, entry)) = entries.next_dfs().unwrap() {
+                if duplicates.contains_key(&entry.offset().to_unit_section_offset(&unit)) {
+                    names.extend(read_name(entry, &dwarf, &unit).unwrap());
+                }
+            }
+        }
+        names
+    }
+
+    #[test]
+    fn test_odr_duplicates() {
+        let names = duplicate_names(constants::DW_LANG_C_plus_plus, |unit| {
+            let int = add_base_type(unit, "int", 4);
+            let root = unit.root();
+            add_struct(unit, root, "S", int);
+        });
+        assert_eq!(names, vec!["int", "S", "a"]);
+    }
+
+    #[test]
+    fn test_odr_c_units() {
+        let names = duplicate_names(constants::DW_LANG_C99, |unit| {
+            let int = add_base_type(unit, "int", 4);
+            let root = unit.root();
+            add_struct(unit, root, "S", int);
+        });
+        assert!(names.is_empty());
+    }
+
+    #[test]
+    fn test_odr_anonymous_namespace() {
+        let names = duplicate_names(constants::DW_LANG_C_plus_plus, |unit| {
+            let int = add_base_type(unit, "int", 4);
+            let root = unit.root();
+            let namespace = unit.add(root, constants::DW_TAG_namespace);
+            add_struct(unit, namespace, "S", int);
+        });
+        // Only the base type is merged.
+        assert_eq!(names, vec!["int"]);
+    }
+
+    #[test]
+    fn test_odr_member_types() {
+        let is_first = std::cell::Cell::new(true);
+        let names = duplicate_names(constants::DW_LANG_C_plus_plus, |unit| {
+            let int = add_base_type(unit, "int", 4);
+            let float = add_base_type(unit, "float", 4);
+            let root = unit.root();
+            // `struct S { int a; }` and `struct S { float a; }`.
+            add_struct(unit, root, "S", if is_first.get() { int } else { float });
+            is_first.set(false);
+        });
+        assert_eq!(names, vec!["int", "float"]);
+    }
+}
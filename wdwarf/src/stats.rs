@@ -0,0 +1,76 @@
+use gimli::write::Address;
+
+use crate::symbolize::is_tombstone;
+
+// The debug info statistics of the conversion, similar to the ones
+// of `llvm-dwarfdump --statistics`.
+#[derive(Debug, Clone, Default)]
+pub struct Statistics {
+    // The original DIEs, and the converted ones.
+    pub dies: u64,
+    pub dies_kept: u64,
+    pub subprograms: u64,
+    pub subprograms_kept: u64,
+    // The subprograms with code, and the ones with the translated code.
+    pub functions: u64,
+    pub functions_kept: u64,
+    // The variables and the formal parameters.
+    pub variables: u64,
+    pub variables_kept: u64,
+
+    // The line table rows: mapped to at least one target address, merged
+    // with other rows at the same target address, or dropped.
+    pub line_sequences: u64,
+    pub line_sequences_dropped: u64,
+    pub line_rows: u64,
+    pub line_rows_mapped: u64,
+    pub line_rows_merged: u64,
+    pub line_rows_dropped: u64,
+
+    // The bytes covered by the variables location lists.
+    pub location_bytes: u64,
+    pub location_bytes_kept: u64,
+
+    // The code ranges (of the functions, range and location lists), and
+    // the ones translated into several ranges or into none.
+    pub ranges: u64,
+    pub ranges_split: u64,
+    pub ranges_dropped: u64,
+
+    // The dependencies roots, and the subprograms that were rejected as
+    // roots, since their code cannot be translated.
+    pub roots: u64,
+    pub rejected_roots: u64,
+}
+
+impl Statistics {
+    // The ranges of the removed code are not counted.
+    pub(crate) fn add_range(&mut self, start: u64, translated_count: usize) {
+        if is_tombstone(start) {
+            return;
+        }
+        self.ranges += 1;
+        match translated_count {
+            0 => self.ranges_dropped += 1,
+            1 => (),
+            _ => self.ranges_split += 1,
+        }
+    }
+
+    pub(crate) fn add_line_stats(&mut self, other: &Statistics) {
+        self.line_sequences += other.line_sequences;
+        self.line_sequences_dropped += other.line_sequences_dropped;
+        self.line_rows += other.line_rows;
+        self.line_rows_mapped += other.line_rows_mapped;
+        self.line_rows_merged += other.line_rows_merged;
+        self.line_rows_dropped += other.line_rows_dropped;
+    }
+
+    pub(crate) fn add_location(&mut self, start: u64, len: u64, translated: &[(Address, u64)]) {
+        self.add_range(start, translated.len());
+        if !is_tombstone(start) {
+            self.location_bytes += len;
+            self.location_bytes_kept += translated.iter().map(|(_, len)| len).sum::<u64>();
+        }
+    }
+}
@@ -0,0 +1,770 @@
+use gimli::constants;
+use gimli::read;
+use gimli::{Reader, UnitOffset, UnitSectionOffset};
+use std::collections::hash_map::Entry;
+use std::collections::HashMap;
+use std::vec::Vec;
+
+use crate::explain::offset_value;
+
+// The function name and the source location of the code address. The frames
+// of the inlined functions are followed by the frames of their callers.
+#[derive(Debug, Clone, Default)]
+pub struct Frame {
+    pub function: Option<String>,
+    pub file: Option<String>,
+    pub line: Option<u64>,
+    pub column: Option<u64>,
+}
+
+// The subprogram with the code, and the inlined calls of its body.
+#[derive(Debug, Clone)]
+pub struct FunctionInfo {
+    pub name: Option<String>,
+    // The compilation unit name.
+    pub unit: Option<String>,
+    pub ranges: Vec<(u64, u64)>,
+    pub inlined: Vec<InlinedCall>,
+}
+
+// The inlined function call: its nesting depth (0 for the calls made by
+// the function itself), the call site and the inlined code ranges.
+#[derive(Debug, Clone)]
+pub struct InlinedCall {
+    pub depth: u32,
+    pub function: Option<String>,
+    pub call_file: Option<String>,
+    pub call_line: Option<u64>,
+    pub ranges: Vec<(u64, u64)>,
+}
+
+// The line table row, and the code range it describes.
+#[derive(Debug, Clone)]
+pub struct LineRange {
+    pub begin: u64,
+    pub end: u64,
+    pub file: Option<String>,
+    pub line: u64,
+    pub column: u64,
+}
+
+struct Function {
+    begin: u64,
+    end: u64,
+    offset: UnitOffset,
+}
+
+// The function code range in the address ordered index, `max_end` is the
+// largest end of the ranges up to this one, to stop the backward scan.
+struct FunctionRange {
+    begin: u64,
+    end: u64,
+    max_end: u64,
+    unit: usize,
+    function: usize,
+}
+
+#[derive(Clone, Copy)]
+struct LineRow {
+    address: u64,
+    file: u64,
+    line: u64,
+    column: u64,
+}
+
+// The sequences are ordered by the address, `index` is the line program
+// order, and `max_end` is the largest end of the sequences up to this one.
+struct LineSequence {
+    begin: u64,
+    end: u64,
+    index: usize,
+    max_end: u64,
+    rows: Vec<LineRow>,
+}
+
+struct UnitInfo<R: Reader<Offset = usize>> {
+    // The .debug_info range of the unit.
+    start: usize,
+    end: usize,
+    unit: read::Unit<R>,
+    functions: Vec<Function>,
+    sequences: Vec<LineSequence>,
+}
+
+// The removed code addresses: 0 (older linkers), and -1 or -2 (for ranges).
+pub(crate) fn is_tombstone(addr: u64) -> bool {
+    addr == 0 || addr >= 0xffff_fffe
+}
+
+pub struct Symbolizer<'a, R: Reader<Offset = usize>> {
+    dwarf: &'a read::Dwarf<R>,
+    units: Vec<UnitInfo<R>>,
+    function_ranges: Vec<FunctionRange>,
+}
+
+impl<'a, R: Reader<Offset = usize>> Symbolizer<'a, R> {
+    pub fn new(dwarf: &'a read::Dwarf<R>) -> read::Result<Self> {
+        let mut units = Vec::new();
+        let mut headers = dwarf.units();
+        while let Some(header) = headers.next()? {
+            let start = header.offset().0;
+            let end = start + header.length_including_self();
+            let unit = dwarf.unit(header)?;
+            let functions = read_functions(dwarf, &unit)?;
+            let sequences = read_line_sequences(&unit)?;
+            units.push(UnitInfo {
+                start,
+                end,
+                unit,
+                functions,
+                sequences,
+            });
+        }
+        let mut function_ranges = Vec::new();
+        for (i, info) in units.iter().enumerate() {
+            for (j, function) in info.functions.iter().enumerate() {
+                function_ranges.push(FunctionRange {
+                    begin: function.begin,
+                    end: function.end,
+                    max_end: 0,
+                    unit: i,
+                    function: j,
+                });
+            }
+        }
+        function_ranges.sort_by_key(|range| range.begin);
+        let mut max_end = 0;
+        for range in function_ranges.iter_mut() {
+            max_end = max_end.max(range.end);
+            range.max_end = max_end;
+        }
+        Ok(Symbolizer {
+            dwarf,
+            units,
+            function_ranges,
+        })
+    }
+
+    // Returns the frames for the code section relative address, the innermost
+    // inlined function first.
+    pub fn find_frames(&self, addr: u64) -> read::Result<Vec<Frame>> {
+        // The smallest function of the first unit that has the address, the
+        // first one of the same size in the DWARF order.
+        let i = self
+            .function_ranges
+            .partition_point(|range| range.begin <= addr);
+        let found = self.function_ranges[..i]
+            .iter()
+            .rev()
+            .take_while(|range| range.max_end > addr)
+            .filter(|range| addr < range.end)
+            .min_by_key(|range| (range.unit, range.end - range.begin, range.function))
+            .map(|range| {
+                let info = &self.units[range.unit];
+                (info, &info.functions[range.function])
+            });
+        let (info, function) = match found {
+            Some(found) => found,
+            None => {
+                return Ok(match self.find_location(addr)? {
+                    Some(frame) => vec![frame],
+                    None => vec![],
+                });
+            }
+        };
+        let unit = &info.unit;
+        let mut chain = vec![function.offset];
+        let mut tree = unit.entries_tree(Some(function.offset))?;
+        self.find_inlined(unit, tree.root()?, addr, &mut chain)?;
+
+        let mut frames = Vec::new();
+        let mut location = self.find_unit_location(info, addr)?.unwrap_or_default();
+        for offset in chain.into_iter().rev() {
+            let mut entries = unit.entries_at_offset(offset)?;
+            let (_, entry) = entries
+                .next_dfs()?
+                .ok_or(read::Error::NoEntryAtGivenOffset)?;
+            location.function = self.function_name(info, entry, 0)?;
+            let caller = self.call_location(info, entry)?;
+            frames.push(location);
+            location = caller;
+        }
+        Ok(frames)
+    }
+
+    // Returns the functions with the code, in the DWARF order.
+    pub fn functions(&self) -> read::Result<Vec<FunctionInfo>> {
+        let mut result: Vec<FunctionInfo> = Vec::new();
+        for info in self.units.iter() {
+            let unit_name = match &info.unit.name {
+                Some(name) => Some(name.to_string_lossy()?.into_owned()),
+                None => None,
+            };
+            let mut last = None;
+            for function in info.functions.iter() {
+                if last == Some(function.offset) {
+                    let last_function = result.last_mut().unwrap();
+                    last_function.ranges.push((function.begin, function.end));
+                    continue;
+                }
+                last = Some(function.offset);
+                let mut entries = info.unit.entries_at_offset(function.offset)?;
+                let (_, entry) = entries
+                    .next_dfs()?
+                    .ok_or(read::Error::NoEntryAtGivenOffset)?;
+                let name = self.function_name(info, entry, 0)?;
+                let mut inlined = Vec::new();
+                let mut tree = info.unit.entries_tree(Some(function.offset))?;
+                self.collect_inlined(info, tree.root()?, 0, &mut inlined)?;
+                result.push(FunctionInfo {
+                    name,
+                    unit: unit_name.clone(),
+                    ranges: vec![(function.begin, function.end)],
+                    inlined,
+                });
+            }
+        }
+        Ok(result)
+    }
+
+    fn collect_inlined(
+        &self,
+        info: &UnitInfo<R>,
+        node: read::EntriesTreeNode<R>,
+        depth: u32,
+        inlined: &mut Vec<InlinedCall>,
+    ) -> read::Result<()> {
+        let mut children = node.children();
+        while let Some(child) = children.next()? {
+            let entry = child.entry();
+            match entry.tag() {
+                constants::DW_TAG_inlined_subroutine => {
+                    let call = self.call_location(info, entry)?;
+                    inlined.push(InlinedCall {
+                        depth,
+                        function: self.function_name(info, entry, 0)?,
+                        call_file: call.file,
+                        call_line: call.line,
+                        ranges: read_ranges(self.dwarf, &info.unit, entry)?,
+                    });
+                    self.collect_inlined(info, child, depth + 1, inlined)?;
+                }
+                constants::DW_TAG_lexical_block => {
+                    self.collect_inlined(info, child, depth, inlined)?;
+                }
+                _ => (),
+            }
+        }
+        Ok(())
+    }
+
+    // Returns the line table rows code ranges, ordered by address.
+    pub fn line_ranges(&self) -> read::Result<Vec<LineRange>> {
+        let mut result = Vec::new();
+        for info in self.units.iter() {
+            let mut files = HashMap::new();
+            // The line program order, for the same order of the equal ranges.
+            let mut sequences = info.sequences.iter().collect::<Vec<_>>();
+            sequences.sort_by_key(|sequence| sequence.index);
+            for sequence in sequences {
+                for (i, row) in sequence.rows.iter().enumerate() {
+                    let end = sequence
+                        .rows
+                        .get(i + 1)
+                        .map_or(sequence.end, |next| next.address);
+                    if row.address >= end {
+                        continue;
+                    }
+                    let file = match files.entry(row.file) {
+                        Entry::Occupied(entry) => entry.into_mut(),
+                        Entry::Vacant(entry) => entry.insert(self.file_path(info, row.file)?),
+                    };
+                    result.push(LineRange {
+                        begin: row.address,
+                        end,
+                        file: file.clone(),
+                        line: row.line,
+                        column: row.column,
+                    });
+                }
+            }
+        }
+        result.sort_by_key(|range| range.begin);
+        Ok(result)
+    }
+
+    // Returns the line table location of the code section relative address.
+    pub fn find_location(&self, addr: u64) -> read::Result<Option<Frame>> {
+        for info in self.units.iter() {
+            if let Some(frame) = self.find_unit_location(info, addr)? {
+                return Ok(Some(frame));
+            }
+        }
+        Ok(None)
+    }
+
+    fn find_unit_location(&self, info: &UnitInfo<R>, addr: u64) -> read::Result<Option<Frame>> {
+        // The first sequence of the line program that has the address.
+        let i = info.sequences.partition_point(|s| s.begin <= addr);
+        let sequence = info.sequences[..i]
+            .iter()
+            .rev()
+            .take_while(|s| s.max_end > addr)
+            .filter(|s| addr < s.end)
+            .min_by_key(|s| s.index);
+        let sequence = match sequence {
+            Some(sequence) => sequence,
+            None => return Ok(None),
+        };
+        let i = match sequence.rows.binary_search_by(|row| row.address.cmp(&addr)) {
+            Ok(mut i) => {
+                while i + 1 < sequence.rows.len() && sequence.rows[i + 1].address == addr {
+                    i += 1;
+                }
+                i
+            }
+            Err(i) => i - 1,
+        };
+        let row = sequence.rows[i];
+        Ok(Some(Frame {
+            function: None,
+            file: self.file_path(info, row.file)?,
+            line: if row.line != 0 { Some(row.line) } else { None },
+            column: if row.column != 0 {
+                Some(row.column)
+            } else {
+                None
+            },
+        }))
+    }
+
+    fn find_inlined(
+        &self,
+        unit: &read::Unit<R>,
+        node: read::EntriesTreeNode<R>,
+        addr: u64,
+        chain: &mut Vec<UnitOffset>,
+    ) -> read::Result<()> {
+        let mut children = node.children();
+        while let Some(child) = children.next()? {
+            let entry = child.entry();
+            match entry.tag() {
+                constants::DW_TAG_inlined_subroutine | constants::DW_TAG_lexical_block => (),
+                _ => continue,
+            }
+            if !die_contains(self.dwarf, unit, entry, addr)? {
+                continue;
+            }
+            if entry.tag() == constants::DW_TAG_inlined_subroutine {
+                chain.push(entry.offset());
+            }
+            return self.find_inlined(unit, child, addr, chain);
+        }
+        Ok(())
+    }
+
+    // The linkage name is preferred, so it can be demangled with the full path.
+    fn function_name(
+        &self,
+        info: &UnitInfo<R>,
+        entry: &read::DebuggingInformationEntry<R>,
+        depth: u32,
+    ) -> read::Result<Option<String>> {
+        for name in &[
+            constants::DW_AT_linkage_name,
+            constants::DW_AT_MIPS_linkage_name,
+        ] {
+            if let Some(value) = entry.attr_value(*name)? {
+                return self.attr_string(&info.unit, value).map(Some);
+            }
+        }
+        if depth < 16 {
+            for name in &[
+                constants::DW_AT_abstract_origin,
+                constants::DW_AT_specification,
+            ] {
+                let offset = match entry.attr_value(*name)? {
+                    Some(read::AttributeValue::UnitRef(offset)) => {
+                        offset.to_unit_section_offset(&info.unit)
+                    }
+                    Some(read::AttributeValue::DebugInfoRef(offset)) => {
+                        UnitSectionOffset::DebugInfoOffset(offset)
+                    }
+                    _ => continue,
+                };
+                let ref_info = match self.find_unit(offset) {
+                    Some(ref_info) => ref_info,
+                    None => continue,
+                };
+                let ref_offset = match offset.to_unit_offset(&ref_info.unit) {
+                    Some(ref_offset) => ref_offset,
+                    None => continue,
+                };
+                let mut entries = ref_info.unit.entries_at_offset(ref_offset)?;
+                let (_, ref_entry) = entries
+                    .next_dfs()?
+                    .ok_or(read::Error::NoEntryAtGivenOffset)?;
+                if let Some(name) = self.function_name(ref_info, ref_entry, depth + 1)? {
+                    return Ok(Some(name));
+                }
+            }
+        }
+        match entry.attr_value(constants::DW_AT_name)? {
+            Some(value) => self.attr_string(&info.unit, value).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    fn call_location(
+        &self,
+        info: &UnitInfo<R>,
+        entry: &read::DebuggingInformationEntry<R>,
+    ) -> read::Result<Frame> {
+        let udata = |name| -> read::Result<Option<u64>> {
+            Ok(entry.attr(name)?.and_then(|attr| attr.udata_value()))
+        };
+        let file = match udata(constants::DW_AT_call_file)? {
+            Some(file) => self.file_path(info, file)?,
+            None => None,
+        };
+        Ok(Frame {
+            function: None,
+            file,
+            line: udata(constants::DW_AT_call_line)?.filter(|line| *line != 0),
+            column: udata(constants::DW_AT_call_column)?.filter(|column| *column != 0),
+        })
+    }
+
+    fn find_unit(&self, offset: UnitSectionOffset) -> Option<&UnitInfo<R>> {
+        let offset = offset_value(offset);
+        self.units
+            .iter()
+            .find(|info| info.start <= offset && offset < info.end)
+    }
+
+    fn attr_string(
+        &self,
+        unit: &read::Unit<R>,
+        value: read::AttributeValue<R>,
+    ) -> read::Result<String> {
+        let s = self.dwarf.attr_string(unit, value)?;
+        Ok(s.to_string_lossy()?.into_owned())
+    }
+
+    // The file path is joined with its directory and the compilation directory.
+    fn file_path(&self, info: &UnitInfo<R>, index: u64) -> read::Result<Option<String>> {
+        let unit = &info.unit;
+        let header = match &unit.line_program {
+            Some(program) => program.header(),
+            None => return Ok(None),
+        };
+        let file = match header.file(index) {
+            Some(file) => file,
+            None => return Ok(None),
+        };
+        let mut path = self.attr_string(unit, file.path_name())?;
+        if !is_absolute_path(&path) {
+            if let Some(dir) = file.directory(header) {
+                path = join_path(&self.attr_string(unit, dir)?, &path);
+            }
+        }
+        if !is_absolute_path(&path) {
+            if let Some(comp_dir) = &unit.comp_dir {
+                path = join_path(&comp_dir.to_string_lossy()?, &path);
+            }
+        }
+        Ok(Some(path))
+    }
+}
+
+fn is_absolute_path(path: &str) -> bool {
+    path.starts_with('/') || path.starts_with('\\') || path.get(1..2) == Some(":")
+}
+
+fn join_path(dir: &str, path: &str) -> String {
+    if dir.is_empty() {
+        return path.to_string();
+    }
+    format!("{}/{}", dir.trim_end_matches('/'), path)
+}
+
+fn die_contains<R: Reader<Offset = usize>>(
+    dwarf: &read::Dwarf<R>,
+    unit: &read::Unit<R>,
+    entry: &read::DebuggingInformationEntry<R>,
+    addr: u64,
+) -> read::Result<bool> {
+    let mut ranges = dwarf.die_ranges(unit, entry)?;
+    while let Some(range) = ranges.next()? {
+        if !is_tombstone(range.begin) && range.begin <= addr && addr < range.end {
+            return Ok(true);
+        }
+    }
+    Ok(false)
+}
+
+// Returns the DIE code ranges, without the removed code ones.
+fn read_ranges<R: Reader<Offset = usize>>(
+    dwarf: &read::Dwarf<R>,
+    unit: &read::Unit<R>,
+    entry: &read::DebuggingInformationEntry<R>,
+) -> read::Result<Vec<(u64, u64)>> {
+    let mut result = Vec::new();
+    let mut ranges = dwarf.die_ranges(unit, entry)?;
+    while let Some(range) = ranges.next()? {
+        if !is_tombstone(range.begin) && range.begin < range.end {
+            result.push((range.begin, range.end));
+        }
+    }
+    Ok(result)
+}
+
+fn read_functions<R: Reader<Offset = usize>>(
+    dwarf: &read::Dwarf<R>,
+    unit: &read::Unit<R>,
+) -> read::Result<Vec<Function>> {
+    let mut functions = Vec::new();
+    let mut entries = unit.entries();
+    while let Some((_, entry)) = entries.next_dfs()? {
+        if entry.tag() != constants::DW_TAG_subprogram {
+            continue;
+        }
+        for (begin, end) in read_ranges(dwarf, unit, entry)? {
+            functions.push(Function {
+                begin,
+                end,
+                offset: entry.offset(),
+            });
+        }
+    }
+    Ok(functions)
+}
+
+fn read_line_sequences<R: Reader<Offset = usize>>(
+    unit: &read::Unit<R>,
+) -> read::Result<Vec<LineSequence>> {
+    let program = match &unit.line_program {
+        Some(program) => program.clone(),
+        None => return Ok(vec![]),
+    };
+    let mut sequences = Vec::new();
+    let mut rows = Vec::new();
+    let mut program_rows = program.rows();
+    while let Some((_, row)) = program_rows.next_row()? {
+        if row.end_sequence() {
+            let begin = rows.first().map_or(row.address(), |r: &LineRow| r.address);
+            if !is_tombstone(begin) && begin < row.address() {
+                sequences.push(LineSequence {
+                    begin,
+                    end: row.address(),
+                    index: sequences.len(),
+                    max_end: 0,
+                    rows: std::mem::take(&mut rows),
+                });
+            }
+            rows.clear();
+            continue;
+        }
+        rows.push(LineRow {
+            address: row.address(),
+            file: row.file_index(),
+            line: row.line().unwrap_or(0),
+            column: match row.column() {
+                read::ColumnType::LeftEdge => 0,
+                read::ColumnType::Column(column) => column,
+            },
+        });
+    }
+    sequences.sort_by_key(|sequence| sequence.begin);
+    let mut max_end = 0;
+    for sequence in sequences.iter_mut() {
+        max_end = max_end.max(sequence.end);
+        sequence.max_end = max_end;
+    }
+    Ok(sequences)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::{add_entry, read_module, set_code_range, write_module, ENCODING};
+    use gimli::write::{self, Address, AttributeValue, LineProgram, LineString, Unit};
+
+    // The "outer" function at 0x10..0x20 with "inner" inlined at 0x14..0x1c,
+    // and "leaf" inlined into it at 0x18..0x1c, and the removed "dead" one.
+    fn write_fixture() -> Vec<u8> {
+        let string = |s: &str| LineString::String(s.as_bytes().to_vec());
+        let mut program = LineProgram::new(
+            ENCODING,
+            gimli::LineEncoding::default(),
+            string("/src"),
+            string("a.c"),
+            None,
+        );
+        let dir = program.default_directory();
+        let a = program.add_file(string("a.c"), dir, None);
+        let b = program.add_file(string("b.h"), dir, None);
+        for (address, file, line) in &[
+            (0x10, a, 1),
+            (0x14, a, 2),
+            // The rows of the same address, the last one is used.
+            (0x14, a, 3),
+            (0x18, b, 10),
+            (0x1c, a, 4),
+        ] {
+            if *address == 0x10 {
+                program.begin_sequence(Some(Address::Constant(0x10)));
+            }
+            program.row().address_offset = address - 0x10;
+            program.row().file = *file;
+            program.row().line = *line;
+            program.generate_row();
+        }
+        program.end_sequence(0x10);
+        // The sequence of the removed code.
+        program.begin_sequence(Some(Address::Constant(0)));
+        program.row().file = a;
+        program.row().line = 99;
+        program.generate_row();
+        program.end_sequence(0x10);
+
+        let mut dwarf = write::Dwarf::new();
+        let unit_id = dwarf.units.add(Unit::new(ENCODING, program));
+        let unit = dwarf.units.get_mut(unit_id);
+        let root = unit.root();
+        unit.get_mut(root).set(
+            constants::DW_AT_comp_dir,
+            AttributeValue::String(b"/src".to_vec()),
+        );
+        let decl = add_entry(unit, root, constants::DW_TAG_subprogram, "outer");
+        unit.get_mut(decl).set(
+            constants::DW_AT_linkage_name,
+            AttributeValue::String(b"_outer".to_vec()),
+        );
+        let outer = unit.add(root, constants::DW_TAG_subprogram);
+        unit.get_mut(outer).set(
+            constants::DW_AT_specification,
+            AttributeValue::ThisUnitEntryRef(decl),
+        );
+        set_code_range(unit, outer, 0x10, 0x10);
+        let inner = add_entry(unit, root, constants::DW_TAG_subprogram, "inner");
+        let leaf = add_entry(unit, root, constants::DW_TAG_subprogram, "leaf");
+        let dead = add_entry(unit, root, constants::DW_TAG_subprogram, "dead");
+        set_code_range(unit, dead, 0, 8);
+
+        let add_call = |unit: &mut Unit, parent, origin, file, line, low_pc, len| {
+            let call = unit.add(parent, constants::DW_TAG_inlined_subroutine);
+            let entry = unit.get_mut(call);
+            entry.set(
+                constants::DW_AT_abstract_origin,
+                AttributeValue::ThisUnitEntryRef(origin),
+            );
+            entry.set(
+                constants::DW_AT_call_file,
+                AttributeValue::FileIndex(Some(file)),
+            );
+            entry.set(constants::DW_AT_call_line, AttributeValue::Udata(line));
+            set_code_range(unit, call, low_pc, len);
+            call
+        };
+        let inner_call = add_call(unit, outer, inner, a, 2, 0x14, 8);
+        let block = unit.add(inner_call, constants::DW_TAG_lexical_block);
+        set_code_range(unit, block, 0x18, 4);
+        add_call(unit, block, leaf, b, 11, 0x18, 4);
+        write_module(&mut dwarf)
+    }
+
+    fn frame(function: &str, file: &str, line: u64) -> (String, String, u64) {
+        (function.to_string(), file.to_string(), line)
+    }
+
+    fn find_frames<R: Reader<Offset = usize>>(
+        symbolizer: &Symbolizer<R>,
+        addr: u64,
+    ) -> Vec<(String, String, u64)> {
+        symbolizer
+            .find_frames(addr)
+            .unwrap()
+            .into_iter()
+            .map(|f| (f.function.unwrap(), f.file.unwrap(), f.line.unwrap()))
+            .collect()
+    }
+
+    #[test]
+    fn test_find_frames() {
+        let bin = write_fixture();
+        let dwarf = read_module(&bin);
+        let symbolizer = Symbolizer::new(&dwarf).unwrap();
+
+        assert_eq!(
+            find_frames(&symbolizer, 0x10),
+            vec![frame("_outer", "/src/a.c", 1)]
+        );
+        assert_eq!(
+            find_frames(&symbolizer, 0x15),
+            vec![
+                frame("inner", "/src/a.c", 3),
+                frame("_outer", "/src/a.c", 2)
+            ]
+        );
+        assert_eq!(
+            find_frames(&symbolizer, 0x1b),
+            vec![
+                frame("leaf", "/src/b.h", 10),
+                frame("inner", "/src/b.h", 11),
+                frame("_outer", "/src/a.c", 2),
+            ]
+        );
+        assert_eq!(
+            find_frames(&symbolizer, 0x1c),
+            vec![frame("_outer", "/src/a.c", 4)]
+        );
+    }
+
+    #[test]
+    fn test_find_location() {
+        let bin = write_fixture();
+        let dwarf = read_module(&bin);
+        let symbolizer = Symbolizer::new(&dwarf).unwrap();
+
+        let line = |addr| symbolizer.find_location(addr).unwrap().map(|f| f.line);
+        assert_eq!(line(0x13), Some(Some(1)));
+        assert_eq!(line(0x14), Some(Some(3)));
+        assert_eq!(line(0x17), Some(Some(3)));
+        assert_eq!(line(0x20), None);
+        // The removed code is not found, neither its function.
+        assert_eq!(line(0x4), None);
+        assert!(symbolizer.find_frames(0x4).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_functions() {
+        let bin = write_fixture();
+        let dwarf = read_module(&bin);
+        let symbolizer = Symbolizer::new(&dwarf).unwrap();
+
+        let functions = symbolizer.functions().unwrap();
+        assert_eq!(functions.len(), 1);
+        assert_eq!(functions[0].name.as_deref(), Some("_outer"));
+        assert_eq!(functions[0].ranges, vec![(0x10, 0x20)]);
+        let inlined = functions[0]
+            .inlined
+            .iter()
+            .map(|call| {
+                (
+                    call.depth,
+                    call.function.clone().unwrap(),
+                    call.call_line,
+                    call.ranges.clone(),
+                )
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            inlined,
+            vec![
+                (0, "inner".to_string(), Some(2), vec![(0x14, 0x1c)]),
+                (1, "leaf".to_string(), Some(11), vec![(0x18, 0x1c)]),
+            ]
+        );
+    }
+}
@@ -0,0 +1,40 @@
+// The DWARF fixtures of the unit tests: the DWARF is built with gimli's
+// writer, and is read back from the custom sections of an empty module.
+use gimli::write::{self, Address, AttributeValue, Unit, UnitEntryId};
+use gimli::{constants, DwTag, Dwarf, Encoding, EndianSlice, Format, LittleEndian};
+
+use crate::wasm::{create_dwarf_sections, load_dwarf, read_dwarf_sections};
+
+pub const ENCODING: Encoding = Encoding {
+    format: Format::Dwarf32,
+    version: 4,
+    address_size: 4,
+};
+
+pub fn write_module(dwarf: &mut write::Dwarf) -> Vec<u8> {
+    let mut bin = vec![0, b'a', b's', b'm', 1, 0, 0, 0];
+    bin.extend(create_dwarf_sections(dwarf).unwrap());
+    bin
+}
+
+pub fn read_module(bin: &[u8]) -> Dwarf<EndianSlice<'_, LittleEndian>> {
+    load_dwarf(read_dwarf_sections(bin).unwrap()).unwrap()
+}
+
+pub fn add_entry(unit: &mut Unit, parent: UnitEntryId, tag: DwTag, name: &str) -> UnitEntryId {
+    let id = unit.add(parent, tag);
+    unit.get_mut(id).set(
+        constants::DW_AT_name,
+        AttributeValue::String(name.as_bytes().to_vec()),
+    );
+    id
+}
+
+pub fn set_code_range(unit: &mut Unit, id: UnitEntryId, low_pc: u64, len: u64) {
+    let entry = unit.get_mut(id);
+    entry.set(
+        constants::DW_AT_low_pc,
+        AttributeValue::Address(Address::Constant(low_pc)),
+    );
+    entry.set(constants::DW_AT_high_pc, AttributeValue::Udata(len));
+}
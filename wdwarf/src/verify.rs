@@ -0,0 +1,366 @@
+use gimli::read::{self, AttributeValue};
+use gimli::{constants, Reader, UnitSectionOffset};
+use std::collections::HashSet;
+
+use crate::diagnostics::{DiagnosticCode, Diagnostics};
+use crate::explain::offset_value;
+use crate::symbolize::is_tombstone;
+
+struct VerifyContext<'a> {
+    // The function bodies ranges, and the code section size.
+    function_ranges: &'a [(u64, u64)],
+    code_size: u64,
+    offsets: HashSet<UnitSectionOffset>,
+    diagnostics: &'a mut Diagnostics,
+}
+
+impl<'a> VerifyContext<'a> {
+    // The function ranges are sorted, as the bodies in the code section.
+    fn is_inside_function(&self, begin: u64, end: u64) -> bool {
+        let i = self
+            .function_ranges
+            .partition_point(|(start, _)| *start <= begin);
+        i > 0 && end <= self.function_ranges[i - 1].1
+    }
+}
+
+fn collect_die_offsets<R: Reader<Offset = usize>>(
+    dwarf: &read::Dwarf<R>,
+) -> read::Result<HashSet<UnitSectionOffset>> {
+    let mut offsets = HashSet::new();
+    let mut units = dwarf.units();
+    while let Some(header) = units.next()? {
+        let unit = dwarf.unit(header)?;
+        let mut entries = unit.entries();
+        while let Some((_, entry)) = entries.next_dfs()? {
+            offsets.insert(entry.offset().to_unit_section_offset(&unit));
+        }
+    }
+    Ok(offsets)
+}
+
+// Verifies that the DWARF is consistent with the module code, e.g. after
+// the conversion. The function bodies ranges are relative to the code section.
+// The found problems are reported as the errors into the diagnostics sink.
+pub fn verify_dwarf<R: Reader<Offset = usize>>(
+    dwarf: &read::Dwarf<R>,
+    function_ranges: &[(u64, u64)],
+    diagnostics: &mut Diagnostics,
+) -> read::Result<()> {
+    let mut context = VerifyContext {
+        function_ranges,
+        code_size: function_ranges.last().map_or(0, |(_, end)| *end),
+        offsets: collect_die_offsets(dwarf)?,
+        diagnostics,
+    };
+    let mut units = dwarf.units();
+    while let Some(header) = units.next()? {
+        let unit = dwarf.unit(header)?;
+        verify_entries(&mut context, dwarf, &unit)?;
+        verify_line_program(&mut context, &unit)?;
+    }
+    Ok(())
+}
+
+fn verify_entries<R: Reader<Offset = usize>>(
+    context: &mut VerifyContext,
+    dwarf: &read::Dwarf<R>,
+    unit: &read::Unit<R>,
+) -> read::Result<()> {
+    let mut entries = unit.entries();
+    while let Some((_, entry)) = entries.next_dfs()? {
+        let offset = entry.offset().to_unit_section_offset(unit);
+        let mut attrs = entry.attrs();
+        while let Some(attr) = attrs.next()? {
+            let target = match attr.value() {
+                AttributeValue::UnitRef(o) => Some(o.to_unit_section_offset(unit)),
+                AttributeValue::DebugInfoRef(o) => Some(UnitSectionOffset::DebugInfoOffset(o)),
+                _ => None,
+            };
+            match target {
+                Some(target) if !context.offsets.contains(&target) => context.diagnostics.add(
+                    DiagnosticCode::UnresolvedReference,
+                    format!(
+                        "{} refers to the missing DIE 0x{:08x}",
+                        attr.name(),
+                        offset_value(target)
+                    ),
+                    Some(offset),
+                    None,
+                ),
+                _ => (),
+            }
+            verify_locations(context, dwarf, unit, attr.value(), offset);
+        }
+
+        let is_subprogram = entry.tag() == constants::DW_TAG_subprogram;
+        let message = match read_die_ranges(dwarf, unit, entry) {
+            Ok(ranges) => {
+                for (begin, end) in ranges {
+                    if verify_range(context, begin, end, is_subprogram, offset) {
+                        break;
+                    }
+                }
+                continue;
+            }
+            Err(e) => format!("cannot read the code ranges: {}", e),
+        };
+        context.diagnostics.add(
+            DiagnosticCode::InvalidRangeList,
+            message,
+            Some(offset),
+            None,
+        );
+    }
+    Ok(())
+}
+
+fn read_die_ranges<R: Reader<Offset = usize>>(
+    dwarf: &read::Dwarf<R>,
+    unit: &read::Unit<R>,
+    entry: &read::DebuggingInformationEntry<R>,
+) -> read::Result<Vec<(u64, u64)>> {
+    let mut result = Vec::new();
+    let mut ranges = dwarf.die_ranges(unit, entry)?;
+    while let Some(range) = ranges.next()? {
+        result.push((range.begin, range.end));
+    }
+    Ok(result)
+}
+
+// Returns true if the problem is reported: the following ranges of the DIE
+// are not checked.
+fn verify_range(
+    context: &mut VerifyContext,
+    begin: u64,
+    end: u64,
+    is_subprogram: bool,
+    offset: UnitSectionOffset,
+) -> bool {
+    let (code, message) = if is_tombstone(begin) {
+        (
+            DiagnosticCode::TombstoneAddress,
+            "the code range starts at the tombstone address".to_string(),
+        )
+    } else if begin > end {
+        (
+            DiagnosticCode::InvalidRangeList,
+            format!(
+                "the code range 0x{:x}..0x{:x} ends before its start",
+                begin, end
+            ),
+        )
+    } else if begin == end {
+        return false;
+    } else if is_subprogram && !context.is_inside_function(begin, end) {
+        (
+            DiagnosticCode::SubprogramOutsideFunction,
+            format!(
+                "the subprogram range 0x{:x}..0x{:x} is not inside a function body",
+                begin, end
+            ),
+        )
+    } else if end > context.code_size {
+        (
+            DiagnosticCode::InvalidRangeList,
+            format!(
+                "the code range 0x{:x}..0x{:x} is outside the code section",
+                begin, end
+            ),
+        )
+    } else {
+        return false;
+    };
+    context
+        .diagnostics
+        .add(code, message, Some(offset), Some(begin));
+    true
+}
+
+fn read_locations<R: Reader<Offset = usize>>(
+    dwarf: &read::Dwarf<R>,
+    unit: &read::Unit<R>,
+    value: AttributeValue<R>,
+) -> read::Result<Vec<(u64, u64)>> {
+    let mut result = Vec::new();
+    if let Some(mut locations) = dwarf.attr_locations(unit, value)? {
+        while let Some(location) = locations.next()? {
+            result.push((location.range.begin, location.range.end));
+        }
+    }
+    Ok(result)
+}
+
+fn verify_locations<R: Reader<Offset = usize>>(
+    context: &mut VerifyContext,
+    dwarf: &read::Dwarf<R>,
+    unit: &read::Unit<R>,
+    value: AttributeValue<R>,
+    offset: UnitSectionOffset,
+) {
+    let locations = match read_locations(dwarf, unit, value) {
+        Ok(locations) => locations,
+        Err(e) => {
+            context.diagnostics.add(
+                DiagnosticCode::InvalidLocationList,
+                format!("cannot read the location list: {}", e),
+                Some(offset),
+                None,
+            );
+            return;
+        }
+    };
+    for (begin, end) in locations {
+        // The default location covers all addresses.
+        if end == u64::MAX {
+            continue;
+        }
+        let (code, message) = if is_tombstone(begin) {
+            (
+                DiagnosticCode::TombstoneAddress,
+                "the location range starts at the tombstone address".to_string(),
+            )
+        } else if end > context.code_size {
+            (
+                DiagnosticCode::InvalidLocationList,
+                format!(
+                    "the location range 0x{:x}..0x{:x} is outside the code section",
+                    begin, end
+                ),
+            )
+        } else {
+            continue;
+        };
+        context
+            .diagnostics
+            .add(code, message, Some(offset), Some(begin));
+        // Only the first problem of the list is reported.
+        return;
+    }
+}
+
+// Every sequence is reported once: its rows after the first problem
+// are not checked.
+fn verify_line_program<R: Reader<Offset = usize>>(
+    context: &mut VerifyContext,
+    unit: &read::Unit<R>,
+) -> read::Result<()> {
+    let program = match &unit.line_program {
+        Some(program) => program.clone(),
+        None => return Ok(()),
+    };
+    // The problems are reported for the unit DIE.
+    let unit_die = match unit.entries().next_dfs()? {
+        Some((_, entry)) => Some(entry.offset().to_unit_section_offset(unit)),
+        None => None,
+    };
+    let mut previous: Option<u64> = None;
+    let mut reported = false;
+    let mut rows = program.rows();
+    while let Some((_, row)) = rows.next_row()? {
+        let address = row.address();
+        let problem = if reported {
+            None
+        } else if previous.is_none() && is_tombstone(address) {
+            Some((
+                DiagnosticCode::TombstoneAddress,
+                "the line sequence starts at the tombstone address".to_string(),
+            ))
+        } else if let Some(previous) = previous.filter(|previous| address < *previous) {
+            Some((
+                DiagnosticCode::LineNotMonotonic,
+                format!("the line row address goes back from 0x{:x}", previous),
+            ))
+        } else if address > context.code_size
+            || (address == context.code_size && !row.end_sequence())
+        {
+            Some((
+                DiagnosticCode::LineOutsideCode,
+                "the line row is outside the code section".to_string(),
+            ))
+        } else {
+            None
+        };
+        if let Some((code, message)) = problem {
+            context
+                .diagnostics
+                .add(code, message, unit_die, Some(address));
+            reported = true;
+        }
+        previous = Some(address);
+        if row.end_sequence() {
+            previous = None;
+            reported = false;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use gimli::DebugInfoOffset;
+
+    const FUNCTIONS: &[(u64, u64)] = &[(0x10, 0x20), (0x20, 0x38), (0x40, 0x50)];
+
+    fn verify(begin: u64, end: u64, is_subprogram: bool) -> Vec<DiagnosticCode> {
+        let mut diagnostics = Diagnostics::default();
+        let mut context = VerifyContext {
+            function_ranges: FUNCTIONS,
+            code_size: 0x50,
+            offsets: HashSet::new(),
+            diagnostics: &mut diagnostics,
+        };
+        let offset = UnitSectionOffset::DebugInfoOffset(DebugInfoOffset(0x2a));
+        let reported = verify_range(&mut context, begin, end, is_subprogram, offset);
+        assert_eq!(reported, !diagnostics.items.is_empty());
+        diagnostics.items.iter().map(|item| item.code).collect()
+    }
+
+    #[test]
+    fn test_is_inside_function() {
+        let mut diagnostics = Diagnostics::default();
+        let context = VerifyContext {
+            function_ranges: FUNCTIONS,
+            code_size: 0x50,
+            offsets: HashSet::new(),
+            diagnostics: &mut diagnostics,
+        };
+        assert!(context.is_inside_function(0x10, 0x20));
+        assert!(context.is_inside_function(0x24, 0x30));
+        assert!(context.is_inside_function(0x40, 0x50));
+        assert!(!context.is_inside_function(0x08, 0x10));
+        // The range cannot span the adjacent functions, or the gap after one.
+        assert!(!context.is_inside_function(0x18, 0x28));
+        assert!(!context.is_inside_function(0x30, 0x3c));
+        assert!(!context.is_inside_function(0x3c, 0x40));
+    }
+
+    #[test]
+    fn test_verify_range() {
+        assert_eq!(verify(0x10, 0x20, true), vec![]);
+        assert_eq!(verify(0x18, 0x28, false), vec![]);
+        // The empty ranges are ignored.
+        assert_eq!(verify(0x30, 0x30, true), vec![]);
+        assert_eq!(
+            verify(0, 0x10, true),
+            vec![DiagnosticCode::TombstoneAddress]
+        );
+        assert_eq!(
+            verify(0xffff_ffff, 0x10, false),
+            vec![DiagnosticCode::TombstoneAddress]
+        );
+        assert_eq!(
+            verify(0x20, 0x18, false),
+            vec![DiagnosticCode::InvalidRangeList]
+        );
+        assert_eq!(
+            verify(0x18, 0x28, true),
+            vec![DiagnosticCode::SubprogramOutsideFunction]
+        );
+        assert_eq!(
+            verify(0x48, 0x58, false),
+            vec![DiagnosticCode::InvalidRangeList]
+        );
+    }
+}
@@ -1,8 +1,59 @@
 use gimli::write::{self, EndianVec, Sections};
-use gimli::{self, Dwarf};
+use gimli::{self, Dwarf, SectionId};
+use wasmparser::{
+    BinaryReader, BinaryReaderError, ImportSectionEntryType, ModuleReader, Range, SectionCode,
+};
+
+use crate::line_source::{embed_line_sources, EmbedSourcesError, LineRefsWriter, SourceFile};
 use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+pub const EXTERNAL_DEBUG_INFO: &str = "external_debug_info";
+pub const BUILD_ID: &str = "build_id";
+
+// The error of the reading of the module, its DWARF, or the external debug
+// info file.
+#[derive(Debug)]
+pub enum ReadError {
+    Wasm(BinaryReaderError),
+    Io(io::Error),
+    Dwarf(gimli::Error),
+}
+
+impl From<BinaryReaderError> for ReadError {
+    fn from(e: BinaryReaderError) -> Self {
+        ReadError::Wasm(e)
+    }
+}
+
+impl From<io::Error> for ReadError {
+    fn from(e: io::Error) -> Self {
+        ReadError::Io(e)
+    }
+}
+
+impl From<gimli::Error> for ReadError {
+    fn from(e: gimli::Error) -> Self {
+        ReadError::Dwarf(e)
+    }
+}
+
+impl fmt::Display for ReadError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ReadError::Wasm(e) => write!(f, "{}", e),
+            ReadError::Io(e) => write!(f, "{}", e),
+            ReadError::Dwarf(e) => write!(f, "{}", e),
+        }
+    }
+}
 
-pub fn read_dwarf<'input, 'a>(
+pub type ReadResult<T> = Result<T, ReadError>;
+
+pub fn load_dwarf<'input, 'a>(
     sections: HashMap<&'input str, &'input [u8]>,
 ) -> gimli::Result<Dwarf<gimli::EndianSlice<'a, gimli::LittleEndian>>>
 where
@@ -23,7 +74,145 @@ where
     )
 }
 
-fn write_leb128(out: &mut Vec<u8>, mut value: u32) {
+// Reads the DWARF sections, or, if there are none, the ones of the file
+// referenced by the external_debug_info section. The referenced file is
+// resolved relative to the `dir`, and its data is kept in the `external`.
+pub fn read_dwarf<'a>(
+    bin: &'a [u8],
+    dir: &Path,
+    external: &'a mut Vec<u8>,
+) -> ReadResult<Dwarf<gimli::EndianSlice<'a, gimli::LittleEndian>>> {
+    let mut sections = read_dwarf_sections(bin)?;
+    if sections.is_empty() {
+        if let Some(url) = read_external_debug_info(bin)? {
+            let path = dir.join(url.trim_start_matches("file://"));
+            *external = fs::read(&path)?;
+            let external: &'a Vec<u8> = external;
+            sections = read_dwarf_sections(external)?;
+        }
+    }
+    Ok(load_dwarf(sections)?)
+}
+
+pub fn read_custom_section<'a>(bin: &'a [u8], section_name: &str) -> ReadResult<Option<&'a [u8]>> {
+    for sect in ModuleReader::new(bin)? {
+        let sect = sect?;
+        match sect.code {
+            SectionCode::Custom { name, .. } if name == section_name => {
+                return Ok(Some(sect.range().slice(bin)));
+            }
+            _ => (),
+        }
+    }
+    Ok(None)
+}
+
+pub fn read_external_debug_info(bin: &[u8]) -> ReadResult<Option<String>> {
+    let section = match read_custom_section(bin, EXTERNAL_DEBUG_INFO)? {
+        Some(section) => section,
+        None => return Ok(None),
+    };
+    let mut reader = BinaryReader::new(section);
+    Ok(Some(reader.read_string()?.to_string()))
+}
+
+pub fn read_build_id(bin: &[u8]) -> ReadResult<Option<Vec<u8>>> {
+    let section = match read_custom_section(bin, BUILD_ID)? {
+        Some(section) => section,
+        None => return Ok(None),
+    };
+    let mut reader = BinaryReader::new(section);
+    let len = reader.read_var_u32()? as usize;
+    Ok(Some(reader.read_bytes(len)?.to_vec()))
+}
+
+pub struct CodeSection {
+    pub offset: u64,
+    pub size: u64,
+    // The imported functions have no code, but shift the function indices.
+    pub imported_functions: u32,
+    // The function bodies ranges, relative to the code section.
+    pub function_ranges: Vec<(u64, u64)>,
+}
+
+pub fn read_code_section(bin: &[u8]) -> ReadResult<Option<CodeSection>> {
+    let mut imported_functions = 0;
+    for sect in ModuleReader::new(bin)? {
+        let sect = sect?;
+        match sect.code {
+            SectionCode::Import => {
+                for import in sect.get_import_section_reader()? {
+                    if let ImportSectionEntryType::Function(_) = import?.ty {
+                        imported_functions += 1;
+                    }
+                }
+            }
+            SectionCode::Code => {
+                let offset = sect.range().start as u64;
+                let size = sect.range().end as u64 - offset;
+                let mut function_ranges = Vec::new();
+                for body in sect.get_code_section_reader()? {
+                    let Range { start, end } = body?.range();
+                    function_ranges.push((start as u64 - offset, end as u64 - offset));
+                }
+                return Ok(Some(CodeSection {
+                    offset,
+                    size,
+                    imported_functions,
+                    function_ranges,
+                }));
+            }
+            _ => (),
+        }
+    }
+    Ok(None)
+}
+
+// Reads the custom sections with the DWARF section names.
+pub fn read_dwarf_sections(bin: &[u8]) -> ReadResult<HashMap<&str, &[u8]>> {
+    let mut sections = HashMap::new();
+    for sect in ModuleReader::new(bin)? {
+        let sect = sect?;
+        match sect.code {
+            SectionCode::Custom { name, .. } if is_dwarf_section(name) => {
+                sections.insert(name, sect.range().slice(bin));
+            }
+            _ => (),
+        }
+    }
+    Ok(sections)
+}
+
+pub fn is_dwarf_section(name: &str) -> bool {
+    to_section_id(name).is_some()
+}
+
+fn to_section_id(name: &str) -> Option<SectionId> {
+    Some(match name {
+        ".debug_abbrev" => SectionId::DebugAbbrev,
+        ".debug_addr" => SectionId::DebugAddr,
+        ".debug_aranges" => SectionId::DebugAranges,
+        ".debug_frame" => SectionId::DebugFrame,
+        ".eh_frame" => SectionId::EhFrame,
+        ".eh_frame_hdr" => SectionId::EhFrameHdr,
+        ".debug_info" => SectionId::DebugInfo,
+        ".debug_line" => SectionId::DebugLine,
+        ".debug_line_str" => SectionId::DebugLineStr,
+        ".debug_loc" => SectionId::DebugLoc,
+        ".debug_loclists" => SectionId::DebugLocLists,
+        ".debug_macinfo" => SectionId::DebugMacinfo,
+        ".debug_pubnames" => SectionId::DebugPubNames,
+        ".debug_pubtypes" => SectionId::DebugPubTypes,
+        ".debug_ranges" => SectionId::DebugRanges,
+        ".debug_rnglists" => SectionId::DebugRngLists,
+        ".debug_str" => SectionId::DebugStr,
+        ".debug_str_offsets" => SectionId::DebugStrOffsets,
+        ".debug_types" => SectionId::DebugTypes,
+        _ => return None,
+    })
+}
+
+pub fn write_leb128(out: &mut Vec<u8>, mut value: u32) {
     for _ in 0..5 {
         let mut byte = (value & 0x7F) as u8;
         value >>= 7;
@@ -39,22 +228,66 @@ fn write_leb128(out: &mut Vec<u8>, mut value: u32) {
     }
 }
 
+pub fn write_custom_section(out: &mut Vec<u8>, name: &str, body: &[u8]) {
+    let mut section = Vec::new();
+    let name = name.as_bytes();
+    write_leb128(&mut section, name.len() as u32);
+    section.extend_from_slice(name);
+    section.extend_from_slice(body);
+
+    write_leb128(out, 0);
+    write_leb128(out, section.len() as u32);
+    out.extend_from_slice(&section);
+}
+
 pub fn create_dwarf_sections(dwarf: &mut write::Dwarf) -> write::Result<Vec<u8>> {
     let mut sections = Sections::new(EndianVec::new(gimli::LittleEndian));
     dwarf.write(&mut sections)?;
 
     let mut result = Vec::new();
     sections.for_each(|s, w| -> write::Result<()> {
-        let mut section = Vec::new();
-        let name = s.name().as_bytes();
-        write_leb128(&mut section, name.len() as u32);
-        section.extend_from_slice(name);
-        let body = w.slice();
-        section.extend_from_slice(body);
-
-        write_leb128(&mut result, 0);
-        write_leb128(&mut result, section.len() as u32);
-        result.extend_from_slice(&section);
+        write_custom_section(&mut result, s.name(), w.slice());
+        Ok(())
+    })?;
+    Ok(result)
+}
+
+// Same as the create_dwarf_sections, but embeds the contents of the source
+// files, provided by the `sources`, into the (version 5) line programs.
+pub fn create_dwarf_sections_with_sources<F: FnMut(&SourceFile) -> Option<Vec<u8>>>(
+    dwarf: &mut write::Dwarf,
+    sources: F,
+) -> Result<Vec<u8>, EmbedSourcesError> {
+    let mut sections = Sections::new(LineRefsWriter::new());
+    dwarf.write(&mut sections)?;
+
+    let (debug_line, offsets) = embed_line_sources(
+        sections.debug_line.slice(),
+        sections.debug_str.slice(),
+        sections.debug_line_str.slice(),
+        sources,
+    )?;
+    // Fixing the DW_AT_stmt_list values.
+    let mut debug_info = sections.debug_info.slice().to_vec();
+    for (pos, size) in sections.debug_info.line_refs.iter() {
+        if *size != 4 {
+            return Err(EmbedSourcesError::Dwarf64);
+        }
+        let mut offset = [0u8; 4];
+        offset.copy_from_slice(&debug_info[*pos..*pos + 4]);
+        if let Some(new_offset) = offsets.get(&(u32::from_le_bytes(offset) as usize)) {
+            debug_info[*pos..*pos + 4].copy_from_slice(&(*new_offset as u32).to_le_bytes());
+        }
+    }
+
+    let mut result = Vec::new();
+    sections.for_each(|s, w| -> write::Result<()> {
+        let body = match s {
+            SectionId::DebugInfo => &debug_info[..],
+            SectionId::DebugLine => &debug_line[..],
+            _ => w.slice(),
+        };
+        write_custom_section(&mut result, s.name(), body);
         Ok(())
     })?;
     Ok(result)